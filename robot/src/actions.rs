@@ -1,16 +1,33 @@
 use std::error::Error;
 
+use nalgebra::SVector;
+
 use sensors::motor::{Directions, MotorController};
 
 /// # Explanation
 /// The action enum contains all possible actions the robot can perform. Currently it is Idle (not moving)
 /// and Drive(speed of the left motor, speed of the right motor).
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Action {
     Idle,
     Drive(f32, f32),
 }
 
+impl Action {
+    /// # Returns
+    /// Returns the motor speeds (left, right) the action results in. They are used as the control input
+    /// of the differential drive model.
+    pub fn control_input(&self) -> SVector<f64, 2> {
+        match self {
+            Action::Idle => SVector::<f64, 2>::zeros(),
+            Action::Drive(motor_left, motor_right) => SVector::<f64, 2>::new(
+                motor_left.clamp(-1.0, 1.0) as f64,
+                motor_right.clamp(-1.0, 1.0) as f64,
+            ),
+        }
+    }
+}
+
 /// # Explanation
 /// perform_action forwards the action to the MotorController. It requires that two motors are connected to the motor controller.
 /// These motors must have the ids 0 and 2.
@@ -42,6 +59,8 @@ pub fn perform_action<ERR: Error, M: MotorController<ERR>>(
 mod tests {
     use std::fmt::Error;
 
+    use nalgebra::SVector;
+
     use sensors::motor::{Directions, MotorController};
 
     use crate::actions::{Action, perform_action};
@@ -93,4 +112,20 @@ mod tests {
         assert_eq!(motor_controller.motors[0], (Directions::FORWARD, 0.5));
         assert_eq!(motor_controller.motors[2], (Directions::BACKWARD, 0.25));
     }
+
+    #[test]
+    fn test_control_input() {
+        assert_eq!(
+            Action::Idle.control_input(),
+            SVector::<f64, 2>::new(0.0, 0.0)
+        );
+        assert_eq!(
+            Action::Drive(0.5, -0.25).control_input(),
+            SVector::<f64, 2>::new(0.5, -0.25)
+        );
+        assert_eq!(
+            Action::Drive(20.0, -20.0).control_input(),
+            SVector::<f64, 2>::new(1.0, -1.0)
+        );
+    }
 }
@@ -1,12 +1,26 @@
 use serde::{Deserialize, Serialize};
 
+use sensor_fusion::config::EstimatorConfig;
 use sensors::gps::NtripClientSettings;
 
+/// # Explanation
+/// The estimator is built from its configuration (see sensor_fusion::config::EstimatorConfig). The
+/// measurement models belong to the sensors "position" (gps), "velocity" (optical flow) and "heading"
+/// (compass); sensors without a model are not used. If the heading is fused, the velocity has to be
+/// measured in the frame of the robot (body_velocity), since the compass then is the heading sensor.
+/// Otherwise the velocity is rotated by the compass before the filter sees it.
+///
+/// The estimator replaces the former model_parameters: the drift is the drift of the constant_velocity
+/// transition model, the position_error is error_x and error_y of the "position" sensor and the
+/// velocity_error is error_x and error_y of the "velocity" sensor. For the differential_drive only the
+/// speed_gain and the yaw_rate_gain of the motors have to be calibrated, the other parameters have defaults.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Config {
     pub log_level: String,
     pub sensor_parameters: SensorParameterConfig,
-    pub model_parameters: ModelParameterConfig,
+    pub estimator: EstimatorConfig,
+    #[serde(default)]
+    pub track_parameters: TrackParameterConfig,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -17,9 +31,18 @@ pub struct SensorParameterConfig {
     pub optical_flow_sensor_height_mm: f64,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct ModelParameterConfig {
-    pub position_error: f64,
-    pub velocity_error: f64,
-    pub drift: f64,
+/// # Explanation
+/// Limits the waypoints of the track that are kept in memory (max_waypoints is used if both limits are
+/// set). The older waypoints are written to the spill file if one is given.
+///
+/// If an export file is given, the complete track is exported to it at the end (as GeoJSON, GPX or KML
+/// depending on the extension .geojson, .gpx or .kml). The uncertainty ellipses are included if
+/// export_uncertainty_sigma is set.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TrackParameterConfig {
+    pub max_waypoints: Option<usize>,
+    pub max_duration_s: Option<f64>,
+    pub spill_file: Option<String>,
+    pub export_file: Option<String>,
+    pub export_uncertainty_sigma: Option<f64>,
 }
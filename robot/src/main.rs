@@ -1,26 +1,33 @@
 use std::error::Error;
 use std::str::FromStr;
 
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use gilrs::Button;
 use log::LevelFilter;
-use nalgebra::{SMatrix, SVector, Vector4};
+use nalgebra::{DVector, SVector, Vector1, Vector2};
 use simplelog::WriteLogger;
 
-use sensor_fusion::estimator::Estimator;
-use sensor_fusion::kalman::estimator::KalmanFilter;
-use sensor_fusion::kalman::model::{ConstantVelocity, MeasureAllModel};
-use sensor_fusion::state::{GaussianState, Measurement, Waypoint};
+use sensor_fusion::bounded::{BoundedTrack, Capacity};
+use sensor_fusion::config::{
+    ConfiguredEstimator, EstimatorConfig, MeasurementModelConfig, TransitionModelConfig,
+};
+use sensor_fusion::dynamic::state::{DynamicMeasurement, DynamicWaypoint};
+use sensor_fusion::dynamic::track::DynamicTrack;
+use sensor_fusion::export::GeoExporter;
+use sensor_fusion::plot::{TrackLayer, TrackPlot};
+use sensor_fusion::state::Measurement;
 use sensor_fusion::track::Track;
-use sensors::compass::BNO055;
-use sensors::coordinates::{Cartesian2D, KinematicState, Velocity2D};
+use sensors::compass::{Orientation, BNO055};
+use sensors::coordinates::{
+    Cartesian2D, Cartesian3D, CartesianToGeo, ENUToGeo, GeoCoord, Velocity2D,
+};
 use sensors::distance_traveled::PAA5100;
 use sensors::gps::{NtripUbloxSensor, UbloxSensor};
 use sensors::motor::AdafruitDCStepperHat;
-use sensors::{SimplePositionSensor, SimpleVelocitySensor};
+use sensors::{BodyVelocitySensor, HeadingSensor, SimplePositionSensor, SimpleVelocitySensor};
 
 use crate::actions::{perform_action, Action};
-use crate::config::{Config, ModelParameterConfig, SensorParameterConfig};
+use crate::config::{Config, SensorParameterConfig, TrackParameterConfig};
 use crate::deciders::{Decider, FollowJoystick};
 use crate::user_input::{UserInput, UserInputUnit};
 use crate::utils::{GameLoop, ParSampler};
@@ -41,7 +48,11 @@ fn main() -> Result<(), Box<dyn Error>> {
     // log init
     log::info!("Robot started");
 
-    let result = run(config.sensor_parameters, config.model_parameters);
+    let result = run(
+        config.sensor_parameters,
+        config.estimator,
+        config.track_parameters,
+    );
     if let Err(e) = result {
         log::error!("{}", e);
         Err(e)
@@ -51,65 +62,204 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 }
 
+/// The sensors of the robot. The heading sensor is only used if the heading is fused.
+struct Sensors {
+    position: ParSampler<Cartesian2D>,
+    velocity: ParSampler<Velocity2D>,
+    heading: Option<ParSampler<Orientation>>,
+    base_point: GeoCoord,
+}
+
 /// # Explanation
-/// The run function first initializes the gps sensor_utils, the motor controller, the decider and the track.
-/// Then for every "frame" in the game loop the user input is retrieved; the gps sensor_utils is asked for
-/// the position which is then added to the track and in the end the action the decider returned is executed.
+/// The run function first initializes the estimator (see EstimatorConfig) and the sensors. The
+/// estimator determines the state of the track, so the robot is then driven with the track of that
+/// dimension (see drive).
 fn run(
     sensor_parameters: SensorParameterConfig,
-    model_parameters: ModelParameterConfig,
+    estimator_config: EstimatorConfig,
+    track_parameters: TrackParameterConfig,
+) -> Result<(), Box<dyn Error>> {
+    let estimator = estimator_config.build()?;
+    let fuse_heading = estimator.sensor(HEADING).is_some();
+    let body_velocity = matches!(
+        estimator_config
+            .measurement_models
+            .get(VELOCITY)
+            .map(|sensor| sensor.model),
+        Some(MeasurementModelConfig::BodyVelocity { .. })
+    );
+    if fuse_heading != body_velocity {
+        return Err("The velocity has to be measured in the frame of the robot if and only if the heading is fused.".into());
+    }
+
+    let mut sensors = initialize_sensors(sensor_parameters, fuse_heading)?;
+
+    let initial_position = get_initial_position(&mut sensors.position);
+    let initial_velocity = sensors.velocity.next().unwrap_or(Velocity2D::new(0., 0.));
+    let initial_heading = sensors
+        .heading
+        .as_mut()
+        .and_then(|heading_sensor| heading_sensor.next());
+    let initial_state = estimator_config.initial_state(initial_estimate(
+        &estimator_config.transition_model,
+        initial_position,
+        initial_velocity,
+        initial_heading,
+    ))?;
+    let initial_waypoint = DynamicWaypoint::from_state(initial_state);
+
+    let transition_model = estimator_config.transition_model;
+    match estimator.state_dimension() {
+        4 => drive::<4>(
+            &estimator,
+            transition_model,
+            initial_waypoint,
+            sensors,
+            &track_parameters,
+        ),
+        5 => drive::<5>(
+            &estimator,
+            transition_model,
+            initial_waypoint,
+            sensors,
+            &track_parameters,
+        ),
+        6 => drive::<6>(
+            &estimator,
+            transition_model,
+            initial_waypoint,
+            sensors,
+            &track_parameters,
+        ),
+        dimension => Err(format!("A state with {} dimensions is not supported.", dimension).into()),
+    }
+}
+
+/// # Explanation
+/// The drive function first initializes the motor controller, the decider and the track.
+/// Then for every "frame" in the game loop the user input is retrieved; the gps sensor_utils and the velocity
+/// sensor (and the heading sensor if the heading is fused) are asked for their measurements (each one is used on
+/// its own, so a missing gps fix does not discard the velocity) which are then added to the track and in the end
+/// the action the decider returned is executed.
+/// Only the latest waypoints are kept in memory (see TrackParameterConfig); the complete track is plotted (and
+/// exported if an export file is configured) when the loop ends.
+///
+/// # Type parameters
+/// - SD: the state dimension of the estimator
+fn drive<const SD: usize>(
+    estimator: &ConfiguredEstimator,
+    transition_model: TransitionModelConfig,
+    initial_waypoint: DynamicWaypoint,
+    mut sensors: Sensors,
+    track_parameters: &TrackParameterConfig,
 ) -> Result<(), Box<dyn Error>> {
     let mut motor_controller = AdafruitDCStepperHat::new(0x60)?;
     let mut user_input_unit = UserInputUnit::new()?;
     let mut follow_joystick = FollowJoystick::new();
-
-    let mut sensors = initialize_sensors(sensor_parameters)?;
-
-    let initial_measurement = get_initial_measurement(&mut sensors);
-    let (kalman_filter, mut track) = initialize_kalman(model_parameters, initial_measurement);
+    let mut track = initialize_track::<SD>(initial_waypoint, track_parameters)?;
 
     println!("The robot is now drivable.");
 
     for _ in GameLoop::from_fps(20) {
         let user_input = user_input_unit.next().unwrap_or(UserInput::default());
 
-        sensors.next().map(|(position, velocity)| {
-            log::info!(
-                "The robot is at {:?} with a velocity of {:?}.",
-                position,
-                velocity
-            );
-
-            let timestamp = Utc::now();
-            let measurement =
-                Measurement::new(timestamp, KinematicState::new(position, velocity).into());
-            let estimate = kalman_filter.estimate(&track, measurement);
-            if let Ok(estimate) = estimate {
-                track.add_waypoint(Waypoint::new(timestamp, estimate));
+        let timestamp = Utc::now();
+        let mut measurements = Vec::new();
+        if let Some(position) = sensors.position.next() {
+            log::info!("The robot is at {:?}.", position);
+            measurements.push(sensor_measurement(POSITION, timestamp, position.into()));
+        }
+        if let Some(velocity) = sensors.velocity.next() {
+            log::info!("The robot has a velocity of {:?}.", velocity);
+            measurements.push(sensor_measurement(VELOCITY, timestamp, velocity.into()));
+        }
+        if let Some(heading) = sensors
+            .heading
+            .as_mut()
+            .and_then(|heading_sensor| heading_sensor.next())
+        {
+            log::info!("The robot has a heading of {:?}.", heading);
+            measurements.push(sensor_measurement(
+                HEADING,
+                timestamp,
+                Vector1::new(heading.radian),
+            ));
+        }
+        // sensors without a measurement model are not used
+        measurements.retain(|(sensor, _)| estimator.sensor(sensor).is_some());
+
+        let latest_track = DynamicTrack::new(track.track().get_latest_waypoint().clone().into());
+        let waypoints = estimator.estimate_waypoints(&latest_track, measurements);
+        if let Ok(waypoints) = waypoints {
+            for waypoint in waypoints {
+                // the robot keeps driving if the old waypoints cannot be spilled to the disk
+                if let Err(e) = track.add_waypoint(waypoint.try_into()?) {
+                    log::error!("A waypoint could not be spilled: {}", e);
+                }
             }
-        });
+        }
 
         if user_input.is_pressed(Button::East) {
-            log::info!("Plotting the track.");
-
-            track.plot("track", |waypoint| {
-                (waypoint.state.estimate[0], waypoint.state.estimate[1])
-            });
-
             perform_action(Action::Idle, &mut motor_controller).unwrap_or(());
             break;
         }
 
         let action = follow_joystick.decide(&user_input);
+        estimator.set_control(action.control_input());
         perform_action(action, &mut motor_controller).unwrap_or(());
     }
 
+    log::info!("Plotting the track.");
+    let track = track.into_full_track()?;
+    TrackPlot::new("track")
+        .with_layer(
+            TrackLayer::new("filtered", &track)
+                .with_ellipses(Duration::seconds(2))
+                .with_velocity_arrows(Duration::seconds(2), |state| {
+                    velocity_of_state(&transition_model, state)
+                }),
+        )
+        .with_component_names(component_names(&transition_model))
+        .write_html("track");
+
+    if let Some(export_file) = &track_parameters.export_file {
+        log::info!("Exporting the track to {}.", export_file);
+        export_track(
+            &track,
+            sensors.base_point,
+            export_file,
+            track_parameters.export_uncertainty_sigma,
+        )?;
+    }
+
     Ok(())
 }
 
+/// the names of the sensors in the measurement models of the estimator
+const POSITION: &str = "position";
+const VELOCITY: &str = "velocity";
+const HEADING: &str = "heading";
+
+fn sensor_measurement<const D: usize>(
+    sensor: &str,
+    timestamp: DateTime<Utc>,
+    vector: SVector<f64, D>,
+) -> (String, DynamicMeasurement) {
+    (
+        sensor.to_string(),
+        Measurement::new(timestamp, vector).into(),
+    )
+}
+
+/// # Returns
+/// Returns the position sensor, the velocity sensor, the heading sensor and the base point of the ENU frame
+/// of the positions (see Sensors). If the heading is fused, the velocity is measured in the frame of the robot and the
+/// compass is the heading sensor; otherwise there is no heading sensor and the velocity is rotated by the
+/// compass.
 fn initialize_sensors(
     sensors_parameters: SensorParameterConfig,
-) -> Result<ParSampler<(Cartesian2D, Velocity2D)>, Box<dyn Error>> {
+    fuse_heading: bool,
+) -> Result<Sensors, Box<dyn Error>> {
     let ublox_sensor = UbloxSensor::new("/dev/ttyACM0", 38400)?;
     let mut bno055 = BNO055::new(0x28)?;
     bno055
@@ -122,49 +272,158 @@ fn initialize_sensors(
 
     let ntrip_ublox_sensor = NtripUbloxSensor::new(ublox_sensor, sensors_parameters.ntrip_settings);
     let position_sensor = SimplePositionSensor::new(ntrip_ublox_sensor);
+    let base_point = position_sensor.base_point();
 
-    let velocity_sensor = SimpleVelocitySensor::new(bno055, paa5100);
-    let sensors = ParSampler::new(10, position_sensor.zip(velocity_sensor));
+    let (velocity_sensor, heading_sensor) = if fuse_heading {
+        (
+            ParSampler::new(10, BodyVelocitySensor::new(paa5100)),
+            Some(ParSampler::new(10, HeadingSensor::new(bno055))),
+        )
+    } else {
+        (
+            ParSampler::new(10, SimpleVelocitySensor::new(bno055, paa5100)),
+            None,
+        )
+    };
 
-    Ok(sensors)
+    Ok(Sensors {
+        position: ParSampler::new(10, position_sensor),
+        velocity: velocity_sensor,
+        heading: heading_sensor,
+        base_point,
+    })
 }
 
-fn get_initial_measurement(sensors: &mut ParSampler<(Cartesian2D, Velocity2D)>) -> Measurement<4> {
-    let initial_measurement = loop {
-        if let Some((initial_position, initial_velocity)) = sensors.next() {
-            break Measurement::from_into(KinematicState::new(initial_position, initial_velocity));
+fn get_initial_position(position_sensor: &mut ParSampler<Cartesian2D>) -> Cartesian2D {
+    loop {
+        if let Some(initial_position) = position_sensor.next() {
+            break initial_position;
         }
-    };
-    initial_measurement
+    }
 }
 
-fn initialize_kalman(
-    model_parameters: ModelParameterConfig,
-    initial_measurement: Measurement<4>,
-) -> (
-    KalmanFilter<4, 4, ConstantVelocity, MeasureAllModel<4>>,
-    Track<4>,
-) {
-    let initial_state = GaussianState::<4>::new(
-        initial_measurement.vector,
-        SMatrix::from_diagonal(&Vector4::new(
-            model_parameters.position_error,
-            model_parameters.position_error,
-            0.,
-            0.,
-        )),
+/// # Returns
+/// Returns the initial estimate of the state of the transition model. Without the compass the heading is
+/// the direction of the initial velocity (so its initial error should be large, since the heading is
+/// unknown until the robot starts to move).
+fn initial_estimate(
+    transition_model: &TransitionModelConfig,
+    initial_position: Cartesian2D,
+    initial_velocity: Velocity2D,
+    initial_heading: Option<Orientation>,
+) -> DVector<f64> {
+    let (x, y, vx, vy) = (
+        initial_position.x,
+        initial_position.y,
+        initial_velocity.vx,
+        initial_velocity.vy,
     );
+    match transition_model {
+        TransitionModelConfig::ConstantVelocity { .. } => DVector::from_vec(vec![x, y, vx, vy]),
+        TransitionModelConfig::ConstantAcceleration { .. } => {
+            DVector::from_vec(vec![x, y, vx, vy, 0., 0.])
+        }
+        TransitionModelConfig::CoordinatedTurn { .. }
+        | TransitionModelConfig::DifferentialDrive { .. } => {
+            let heading = initial_heading.map_or(vy.atan2(vx), |heading| heading.radian);
+            DVector::from_vec(vec![x, y, vx.hypot(vy), heading, 0.])
+        }
+    }
+}
 
-    let kalman_filter = KalmanFilter::new(
-        ConstantVelocity::new(model_parameters.drift),
-        MeasureAllModel::new(SVector::<f64, 4>::new(
-            model_parameters.position_error,
-            model_parameters.position_error,
-            model_parameters.velocity_error,
-            model_parameters.velocity_error,
-        )),
+fn initialize_track<const SD: usize>(
+    initial_waypoint: DynamicWaypoint,
+    track_parameters: &TrackParameterConfig,
+) -> Result<BoundedTrack<SD>, Box<dyn Error>> {
+    let mut track = BoundedTrack::new(
+        initial_waypoint.try_into()?,
+        track_capacity(track_parameters),
     );
-    let track = Track::new(Waypoint::from_state(initial_state));
+    if let Some(spill_file) = &track_parameters.spill_file {
+        track = track.with_spill_file(spill_file)?;
+    }
+    Ok(track)
+}
+
+fn component_names(transition_model: &TransitionModelConfig) -> &'static [&'static str] {
+    match transition_model {
+        TransitionModelConfig::ConstantVelocity { .. } => {
+            &["x [m]", "y [m]", "vx [m/s]", "vy [m/s]"]
+        }
+        TransitionModelConfig::ConstantAcceleration { .. } => &[
+            "x [m]",
+            "y [m]",
+            "vx [m/s]",
+            "vy [m/s]",
+            "ax [m/s²]",
+            "ay [m/s²]",
+        ],
+        TransitionModelConfig::CoordinatedTurn { .. }
+        | TransitionModelConfig::DifferentialDrive { .. } => &[
+            "x [m]",
+            "y [m]",
+            "v [m/s]",
+            "heading [rad]",
+            "yaw rate [rad/s]",
+        ],
+    }
+}
 
-    (kalman_filter, track)
+fn velocity_of_state(
+    transition_model: &TransitionModelConfig,
+    state: &DVector<f64>,
+) -> Vector2<f64> {
+    match transition_model {
+        TransitionModelConfig::ConstantVelocity { .. }
+        | TransitionModelConfig::ConstantAcceleration { .. } => Vector2::new(state[2], state[3]),
+        TransitionModelConfig::CoordinatedTurn { .. }
+        | TransitionModelConfig::DifferentialDrive { .. } => {
+            Vector2::new(state[2] * state[3].cos(), state[2] * state[3].sin())
+        }
+    }
+}
+
+fn track_capacity(track_parameters: &TrackParameterConfig) -> Capacity {
+    match (
+        track_parameters.max_waypoints,
+        track_parameters.max_duration_s,
+    ) {
+        (Some(max_waypoints), _) => Capacity::LastWaypoints(max_waypoints),
+        (None, Some(max_duration_s)) => {
+            Capacity::LastDuration(Duration::milliseconds((max_duration_s * 1000.) as i64))
+        }
+        (None, None) => Capacity::Unbounded,
+    }
+}
+
+/// # Explanation
+/// Exports the track with geographic coordinates (relative to the base point of the position sensor). The
+/// format is chosen by the extension of the file.
+fn export_track<const D: usize>(
+    track: &Track<D>,
+    base_point: GeoCoord,
+    export_file: &str,
+    uncertainty_sigma: Option<f64>,
+) -> Result<(), Box<dyn Error>> {
+    let enu_to_geo = ENUToGeo::new(base_point, 0.0);
+    let mut exporter = GeoExporter::new(|east, north| {
+        let (geo_coord, _) = enu_to_geo.convert(Cartesian3D::new(east, north, 0.0));
+        (geo_coord.lon, geo_coord.lat)
+    })
+    .with_name("raspberry_pi_localization");
+    if let Some(sigma) = uncertainty_sigma {
+        exporter = exporter.with_uncertainty_ellipses(sigma);
+    }
+
+    let writer = std::io::BufWriter::new(std::fs::File::create(export_file)?);
+    match std::path::Path::new(export_file)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("geojson") | Some("json") => exporter.write_geojson(track, writer)?,
+        Some("gpx") => exporter.write_gpx(track, writer)?,
+        Some("kml") => exporter.write_kml(track, writer)?,
+        _ => return Err(format!("The export format of {} is unknown.", export_file).into()),
+    }
+    Ok(())
 }
@@ -0,0 +1,176 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use chrono::Duration;
+
+use crate::serialization::{
+    csv_writer, read_csv_waypoints, write_csv_waypoint, SerializationError,
+};
+use crate::state::Waypoint;
+use crate::track::Track;
+
+/// # Explanation
+/// The Capacity decides which waypoints a BoundedTrack keeps in memory.
+/// - Unbounded keeps every waypoint.
+/// - LastWaypoints keeps the given number of the newest waypoints.
+/// - LastDuration keeps the waypoints that are at most the given duration older than the latest one.
+///
+/// The latest waypoint is always kept, since the estimators need it.
+#[derive(Debug, Copy, Clone, Default)]
+pub enum Capacity {
+    #[default]
+    Unbounded,
+    LastWaypoints(usize),
+    LastDuration(Duration),
+}
+
+/// # Explanation
+/// The BoundedTrack is a track that only keeps the waypoints allowed by its capacity in memory, so that
+/// the memory stays bounded on long drives. The estimators only need the latest waypoints and work with
+/// the in memory part (see track()).
+///
+/// If a spill file is set, the waypoints that are removed from memory are appended to it (in the csv
+/// format of serialization::write_csv). into_full_track reads them back to rebuild the complete track
+/// (eg for plotting at the end of a session).
+pub struct BoundedTrack<const D: usize> {
+    track: Track<D>,
+    capacity: Capacity,
+    spill: Option<(PathBuf, csv::Writer<File>)>,
+}
+
+impl<const D: usize> BoundedTrack<D> {
+    pub fn new(initial_waypoint: Waypoint<D>, capacity: Capacity) -> Self {
+        Self {
+            track: Track::new(initial_waypoint),
+            capacity,
+            spill: None,
+        }
+    }
+
+    /// # Explanation
+    /// Creates the spill file (an existing file is truncated) that the removed waypoints are appended to.
+    pub fn with_spill_file(mut self, path: impl AsRef<Path>) -> Result<Self, SerializationError> {
+        let path = path.as_ref().to_path_buf();
+        let writer = csv_writer::<D, _>(File::create(&path)?)?;
+        self.spill = Some((path, writer));
+        Ok(self)
+    }
+
+    pub fn track(&self) -> &Track<D> {
+        &self.track
+    }
+
+    pub fn capacity(&self) -> Capacity {
+        self.capacity
+    }
+
+    /// # Explanation
+    /// Adds the waypoint and removes the waypoints that exceed the capacity (they are written to the
+    /// spill file if there is one).
+    pub fn add_waypoint(&mut self, waypoint: Waypoint<D>) -> Result<(), SerializationError> {
+        self.track.add_waypoint(waypoint);
+
+        while self.exceeds_capacity() {
+            let Some(removed) = self.track.remove_first_waypoint() else {
+                break;
+            };
+            if let Some((_, writer)) = self.spill.as_mut() {
+                write_csv_waypoint(writer, &removed)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// # Returns
+    /// Returns the complete track: the waypoints of the spill file followed by the ones in memory. Without
+    /// a spill file this is the track in memory.
+    pub fn into_full_track(self) -> Result<Track<D>, SerializationError> {
+        let Some((path, mut writer)) = self.spill else {
+            return Ok(self.track);
+        };
+        writer.flush()?;
+
+        let spilled = read_csv_waypoints::<D>(BufReader::new(File::open(path)?))?;
+        let mut waypoints = spilled.into_iter().chain(self.track);
+        // the in memory track is never empty
+        let mut full_track = Track::new(waypoints.next().unwrap());
+        waypoints.for_each(|waypoint| full_track.add_waypoint(waypoint));
+        Ok(full_track)
+    }
+
+    fn exceeds_capacity(&self) -> bool {
+        match self.capacity {
+            Capacity::Unbounded => false,
+            Capacity::LastWaypoints(count) => self.track.len() > count.max(1),
+            Capacity::LastDuration(duration) => {
+                self.track.get_latest_waypoint().timestamp
+                    - self.track.get_first_waypoint().timestamp
+                    > duration
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::SMatrix;
+
+    use crate::bounded::{BoundedTrack, Capacity};
+    use crate::sim::trajectory::{FigureEight, Kinematics, Trajectory};
+
+    #[test]
+    fn test_bounded_track() {
+        let ground_truth = FigureEight::new(2., 2., 2. * PI, 10.)
+            .ground_truth(
+                Utc.timestamp_nanos(0),
+                Duration::milliseconds(100),
+                Kinematics::constant_velocity_state,
+            )
+            .unwrap();
+        let mut waypoints = ground_truth.clone().into_iter().map(|mut waypoint| {
+            waypoint.state.error = 0.1 * SMatrix::<f64, 4, 4>::identity();
+            waypoint
+        });
+        let initial_waypoint = waypoints.next().unwrap();
+        let spill_file = std::env::temp_dir().join("sensor_fusion_test_bounded_track.csv");
+
+        let mut last_waypoints =
+            BoundedTrack::new(initial_waypoint.clone(), Capacity::LastWaypoints(10))
+                .with_spill_file(&spill_file)
+                .unwrap();
+        let mut last_second = BoundedTrack::new(
+            initial_waypoint.clone(),
+            Capacity::LastDuration(Duration::seconds(1)),
+        );
+        let mut unbounded = BoundedTrack::new(initial_waypoint, Capacity::Unbounded);
+        for waypoint in waypoints {
+            last_waypoints.add_waypoint(waypoint.clone()).unwrap();
+            last_second.add_waypoint(waypoint.clone()).unwrap();
+            unbounded.add_waypoint(waypoint).unwrap();
+
+            assert!(last_waypoints.track().len() <= 10);
+            let in_memory = last_second.track();
+            assert!(
+                in_memory.get_latest_waypoint().timestamp
+                    - in_memory.get_first_waypoint().timestamp
+                    <= Duration::seconds(1)
+            );
+        }
+        assert_eq!(unbounded.track().len(), ground_truth.len());
+
+        // the spilled waypoints are read back to rebuild the complete track
+        let full_track = last_waypoints.into_full_track().unwrap();
+        std::fs::remove_file(spill_file).unwrap();
+        let track = unbounded.into_full_track().unwrap();
+        assert_eq!(full_track.len(), track.len());
+        for (rebuilt, waypoint) in full_track.iter().zip(track.iter()) {
+            assert_eq!(rebuilt.timestamp, waypoint.timestamp);
+            assert_eq!(rebuilt.state.estimate, waypoint.state.estimate);
+            assert_eq!(rebuilt.state.error, waypoint.state.error);
+        }
+    }
+}
@@ -0,0 +1,662 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+use chrono::Duration;
+use nalgebra::{DMatrix, DVector, SMatrix, SVector};
+use serde::{Deserialize, Serialize};
+
+use crate::dynamic::estimator::{DynamicEstimator, StaticEstimator};
+use crate::dynamic::state::{DynamicGaussianState, DynamicMeasurement, DynamicWaypoint};
+use crate::dynamic::track::DynamicTrack;
+use crate::estimator::{EstimationError, Estimator};
+use crate::kalman::ekf::ExtendedKalmanFilter;
+use crate::kalman::estimator::{CovarianceUpdate, KalmanFilter};
+use crate::kalman::model::{
+    AccelerationMeasurementModel, BodyVelocityMeasurementModel, ConstantAcceleration,
+    ConstantVelocity, CoordinatedTurn, DifferentialDrive, HeadingMeasurementModel,
+    PolarVelocityMeasurementModel, PositionMeasurementModel, VelocityMeasurementModel,
+};
+use crate::kalman::outlier::OutlierHandling;
+use crate::kalman::square_root::SquareRootKalmanFilter;
+use crate::kalman::ukf::{SigmaPointParameters, UnscentedKalmanFilter};
+use crate::linalg::check_dimension;
+use crate::model::{
+    ControlInput, LinearMeasurementModel, LinearTransitionModel, NonlinearMeasurementModel,
+    NonlinearTransitionModel,
+};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Unsupported(String),
+    UnknownSensor(String),
+    Estimation(EstimationError),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Unsupported(details) => {
+                write!(f, "The configuration is not supported: {}.", details)
+            }
+            ConfigError::UnknownSensor(name) => {
+                write!(
+                    f,
+                    "No measurement model is configured for the sensor {}.",
+                    name
+                )
+            }
+            ConfigError::Estimation(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<EstimationError> for ConfigError {
+    fn from(error: EstimationError) -> Self {
+        ConfigError::Estimation(error)
+    }
+}
+
+/// # Explanation
+/// The EstimatorConfig describes an estimator, so that the filter and the models can be chosen in a
+/// configuration file (eg the config.toml of the robot) instead of the code:
+/// - filter: the type of the filter (see FilterConfig)
+/// - transition_model: the model of the motion, which also determines the state (see TransitionModelConfig)
+/// - measurement_models: the measurement model of every sensor by the name of the sensor
+/// - initial_error: the diagonal of the error of the initial state
+///
+/// The estimator is created with build. The models of the measurements have to fit the state of the
+/// transition model, the filter has to be able to handle the models and the outlier handling has to be
+/// valid (see OutlierHandling::validate), otherwise ConfigError::Unsupported is returned.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EstimatorConfig {
+    pub filter: FilterConfig,
+    pub transition_model: TransitionModelConfig,
+    pub measurement_models: BTreeMap<String, SensorConfig>,
+    pub initial_error: Vec<f64>,
+}
+
+/// # Explanation
+/// The filters that can be configured. The KalmanFilter and the SquareRootKalmanFilter need linear models.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterConfig {
+    Kalman {
+        #[serde(default)]
+        covariance_update: CovarianceUpdate,
+    },
+    SquareRootKalman,
+    ExtendedKalman,
+    UnscentedKalman {
+        #[serde(default)]
+        sigma_points: SigmaPointParameters,
+    },
+}
+
+/// # Explanation
+/// The transition models that can be configured (see kalman::model for their states and parameters).
+/// The control input of the differential drive is set with ConfiguredEstimator::set_control.
+///
+/// The differential drive needs the calibrated speed_gain and yaw_rate_gain (the speed and the yaw rate
+/// of the robot at full throttle). Its errors default to 0.01 and its response_time to
+/// DifferentialDrive::DEFAULT_RESPONSE_TIME.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransitionModelConfig {
+    ConstantVelocity {
+        drift: f64,
+    },
+    ConstantAcceleration {
+        drift: f64,
+    },
+    CoordinatedTurn {
+        acceleration_noise: f64,
+        yaw_acceleration_noise: f64,
+    },
+    DifferentialDrive {
+        speed_gain: f64,
+        yaw_rate_gain: f64,
+        #[serde(default = "default_drive_error")]
+        speed_error: f64,
+        #[serde(default = "default_drive_error")]
+        yaw_rate_error: f64,
+        #[serde(default = "default_response_time")]
+        response_time: f64,
+    },
+}
+
+fn default_drive_error() -> f64 {
+    0.01
+}
+
+fn default_response_time() -> f64 {
+    DifferentialDrive::DEFAULT_RESPONSE_TIME
+}
+
+impl TransitionModelConfig {
+    pub fn state_dimension(&self) -> usize {
+        match self {
+            TransitionModelConfig::ConstantVelocity { .. } => 4,
+            TransitionModelConfig::ConstantAcceleration { .. } => 6,
+            TransitionModelConfig::CoordinatedTurn { .. }
+            | TransitionModelConfig::DifferentialDrive { .. } => 5,
+        }
+    }
+}
+
+/// # Explanation
+/// The measurement model of a sensor and how its outliers are treated (by default every measurement is
+/// accepted).
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct SensorConfig {
+    #[serde(flatten)]
+    pub model: MeasurementModelConfig,
+    #[serde(default)]
+    pub outlier_handling: OutlierHandling,
+}
+
+/// # Explanation
+/// The measurement models that can be configured (see kalman::model). The velocity and the acceleration
+/// are measured in the state of the constant velocity and the constant acceleration model. The polar
+/// velocity, the body velocity and the heading need the state of the coordinated turn model (which is also
+/// the state of the differential drive).
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MeasurementModelConfig {
+    Position {
+        error_x: f64,
+        error_y: f64,
+    },
+    Velocity {
+        error_x: f64,
+        error_y: f64,
+    },
+    Acceleration {
+        error_x: f64,
+        error_y: f64,
+    },
+    PolarVelocity {
+        velocity_error: f64,
+    },
+    BodyVelocity {
+        forward_error: f64,
+        lateral_error: f64,
+    },
+    Heading {
+        heading_error: f64,
+    },
+}
+
+impl EstimatorConfig {
+    pub fn state_dimension(&self) -> usize {
+        self.transition_model.state_dimension()
+    }
+
+    /// # Returns
+    /// Returns the initial state with the given estimate and the configured initial error.
+    /// ConfigError::Estimation is returned if the estimate or the initial error do not have the dimension
+    /// of the state.
+    pub fn initial_state(
+        &self,
+        estimate: DVector<f64>,
+    ) -> Result<DynamicGaussianState, ConfigError> {
+        check_dimension(self.state_dimension(), estimate.len())?;
+        check_dimension(self.state_dimension(), self.initial_error.len())?;
+        let error = DMatrix::from_diagonal(&DVector::from_column_slice(&self.initial_error));
+        Ok(DynamicGaussianState::new(estimate, error))
+    }
+
+    /// # Returns
+    /// Returns the configured estimator with one filter per sensor (all of them share the transition
+    /// model).
+    pub fn build(&self) -> Result<ConfiguredEstimator, ConfigError> {
+        let mut sensors = BTreeMap::new();
+        let mut control = None;
+        for (name, sensor) in &self.measurement_models {
+            // eg a confidence level of 99 instead of 0.99 would only fail at the first measurement
+            if let Err(EstimationError::InvalidParameter(details)) =
+                sensor.outlier_handling.validate()
+            {
+                return Err(ConfigError::Unsupported(format!(
+                    "{} (sensor {})",
+                    details, name
+                )));
+            }
+            let estimator = match self.transition_model {
+                TransitionModelConfig::ConstantVelocity { drift } => {
+                    cartesian_estimator::<4, _>(self.filter, ConstantVelocity::new(drift), sensor)
+                }
+                TransitionModelConfig::ConstantAcceleration { drift } => match sensor.model {
+                    MeasurementModelConfig::Acceleration { error_x, error_y } => linear_estimator(
+                        self.filter,
+                        ConstantAcceleration::new(drift),
+                        AccelerationMeasurementModel::new(error_x, error_y),
+                        sensor.outlier_handling,
+                    ),
+                    _ => cartesian_estimator::<6, _>(
+                        self.filter,
+                        ConstantAcceleration::new(drift),
+                        sensor,
+                    ),
+                },
+                TransitionModelConfig::CoordinatedTurn {
+                    acceleration_noise,
+                    yaw_acceleration_noise,
+                } => turn_estimator(
+                    self.filter,
+                    CoordinatedTurn::new(acceleration_noise, yaw_acceleration_noise),
+                    sensor,
+                ),
+                TransitionModelConfig::DifferentialDrive {
+                    speed_gain,
+                    yaw_rate_gain,
+                    speed_error,
+                    yaw_rate_error,
+                    response_time,
+                } => {
+                    let control_input = control.get_or_insert_with(|| {
+                        Rc::new(ControlInput::new(
+                            DifferentialDrive::new(
+                                speed_gain,
+                                yaw_rate_gain,
+                                speed_error,
+                                yaw_rate_error,
+                            )
+                            .with_response_time(response_time),
+                        ))
+                    });
+                    turn_estimator(
+                        self.filter,
+                        SharedTransitionModel(control_input.clone()),
+                        sensor,
+                    )
+                }
+            }
+            .map_err(|error| match error {
+                ConfigError::Unsupported(details) => {
+                    ConfigError::Unsupported(format!("{} (sensor {})", details, name))
+                }
+                error => error,
+            })?;
+            sensors.insert(name.clone(), estimator);
+        }
+
+        Ok(ConfiguredEstimator {
+            state_dimension: self.state_dimension(),
+            sensors,
+            control,
+        })
+    }
+}
+
+/// # Explanation
+/// The ConfiguredEstimator is the estimator that is built from an EstimatorConfig. It works with
+/// DynamicTracks, since the state depends on the configured transition model. Every measurement belongs to
+/// a sensor (by the name in the configuration) whose measurement model is used to correct the state.
+pub struct ConfiguredEstimator {
+    state_dimension: usize,
+    sensors: BTreeMap<String, Box<dyn DynamicEstimator>>,
+    control: Option<Rc<ControlInput<DifferentialDrive, 2>>>,
+}
+
+impl ConfiguredEstimator {
+    pub fn state_dimension(&self) -> usize {
+        self.state_dimension
+    }
+
+    pub fn sensor(&self, name: &str) -> Option<&dyn DynamicEstimator> {
+        self.sensors.get(name).map(|estimator| estimator.as_ref())
+    }
+
+    /// # Explanation
+    /// Sets the control input (the commands of the left and the right motor) of the differential drive.
+    /// Without the differential drive as transition model the control input is ignored.
+    pub fn set_control(&self, control: SVector<f64, 2>) {
+        if let Some(control_input) = &self.control {
+            control_input.set_control(control);
+        }
+    }
+
+    /// # Returns
+    /// Returns the estimate of the given sensor as a waypoint (see DynamicEstimator::estimate_waypoint).
+    pub fn estimate_waypoint(
+        &self,
+        track: &DynamicTrack,
+        sensor: &str,
+        measurement: DynamicMeasurement,
+    ) -> Result<DynamicWaypoint, ConfigError> {
+        let estimator = self
+            .sensor(sensor)
+            .ok_or_else(|| ConfigError::UnknownSensor(sensor.to_string()))?;
+        Ok(estimator.estimate_waypoint(track, measurement)?)
+    }
+
+    /// # Returns
+    /// Returns the waypoints that follow the latest waypoint of the track (one for every distinct
+    /// timestamp of the measurements, oldest first) like the SequentialKalmanFilter. Measurements with the
+    /// same timestamp are applied one after the other to a single waypoint, which keeps the prediction of
+    /// the first one. Measurements that are rejected as outliers are skipped.
+    pub fn estimate_waypoints(
+        &self,
+        track: &DynamicTrack,
+        mut measurements: Vec<(String, DynamicMeasurement)>,
+    ) -> Result<Vec<DynamicWaypoint>, ConfigError> {
+        measurements.sort_by_key(|(_, measurement)| measurement.timestamp);
+
+        let mut waypoints: Vec<DynamicWaypoint> = Vec::new();
+        for (sensor, measurement) in measurements {
+            let previous = waypoints.last().unwrap_or(track.get_latest_waypoint());
+            let timestamp = measurement.timestamp;
+            let waypoint = match self.estimate_waypoint(
+                &DynamicTrack::new(previous.clone()),
+                &sensor,
+                measurement,
+            ) {
+                Err(ConfigError::Estimation(EstimationError::OutlierRejected(_))) => continue,
+                result => result?,
+            };
+
+            match waypoints.last_mut() {
+                Some(latest) if latest.timestamp == timestamp => latest.state = waypoint.state,
+                _ => waypoints.push(waypoint),
+            }
+        }
+        Ok(waypoints)
+    }
+}
+
+/// # Explanation
+/// Lets the filters of all sensors use the same transition model, so that the control input only has to
+/// be set once.
+struct SharedTransitionModel<TModel>(Rc<TModel>);
+
+impl<const SD: usize, TModel> NonlinearTransitionModel<SD> for SharedTransitionModel<TModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+{
+    fn transition(&self, state: &SVector<f64, SD>, dt: Duration) -> SVector<f64, SD> {
+        self.0.transition(state, dt)
+    }
+
+    fn transition_jacobian(&self, state: &SVector<f64, SD>, dt: Duration) -> SMatrix<f64, SD, SD> {
+        self.0.transition_jacobian(state, dt)
+    }
+
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, SD, SD> {
+        self.0.transition_error(dt)
+    }
+}
+
+/// # Returns
+/// Returns the estimator of a position or velocity sensor for the constant velocity or the constant
+/// acceleration model, whose states start with the position and the velocity.
+fn cartesian_estimator<const SD: usize, TModel>(
+    filter: FilterConfig,
+    transition_model: TModel,
+    sensor: &SensorConfig,
+) -> Result<Box<dyn DynamicEstimator>, ConfigError>
+where
+    TModel: LinearTransitionModel<SD> + 'static,
+{
+    let outlier_handling = sensor.outlier_handling;
+    match sensor.model {
+        MeasurementModelConfig::Position { error_x, error_y } => linear_estimator(
+            filter,
+            transition_model,
+            PositionMeasurementModel::<SD>::new(error_x, error_y),
+            outlier_handling,
+        ),
+        MeasurementModelConfig::Velocity { error_x, error_y } => linear_estimator(
+            filter,
+            transition_model,
+            VelocityMeasurementModel::<SD>::new(error_x, error_y),
+            outlier_handling,
+        ),
+        model => Err(ConfigError::Unsupported(format!(
+            "the measurement model {:?} does not fit a state with {} dimensions",
+            model, SD
+        ))),
+    }
+}
+
+/// # Returns
+/// Returns the estimator of a sensor for the coordinated turn or the differential drive model, whose state
+/// is (x, y, v, heading, yaw rate).
+fn turn_estimator<TModel>(
+    filter: FilterConfig,
+    transition_model: TModel,
+    sensor: &SensorConfig,
+) -> Result<Box<dyn DynamicEstimator>, ConfigError>
+where
+    TModel: NonlinearTransitionModel<5> + 'static,
+{
+    let outlier_handling = sensor.outlier_handling;
+    match sensor.model {
+        MeasurementModelConfig::Position { error_x, error_y } => nonlinear_estimator(
+            filter,
+            transition_model,
+            PositionMeasurementModel::<5>::new(error_x, error_y),
+            outlier_handling,
+        ),
+        MeasurementModelConfig::PolarVelocity { velocity_error } => nonlinear_estimator(
+            filter,
+            transition_model,
+            PolarVelocityMeasurementModel::new(velocity_error),
+            outlier_handling,
+        ),
+        MeasurementModelConfig::BodyVelocity {
+            forward_error,
+            lateral_error,
+        } => nonlinear_estimator(
+            filter,
+            transition_model,
+            BodyVelocityMeasurementModel::new(forward_error, lateral_error),
+            outlier_handling,
+        ),
+        MeasurementModelConfig::Heading { heading_error } => nonlinear_estimator(
+            filter,
+            transition_model,
+            HeadingMeasurementModel::new(heading_error),
+            outlier_handling,
+        ),
+        model => Err(ConfigError::Unsupported(format!(
+            "the measurement model {:?} does not fit a state with 5 dimensions",
+            model
+        ))),
+    }
+}
+
+/// # Returns
+/// Returns the configured filter for linear models. The filters for nonlinear models can be used as well,
+/// since every linear model is also a nonlinear one.
+fn linear_estimator<const MD: usize, const SD: usize, TModel, MModel>(
+    filter: FilterConfig,
+    transition_model: TModel,
+    measurement_model: MModel,
+    outlier_handling: OutlierHandling,
+) -> Result<Box<dyn DynamicEstimator>, ConfigError>
+where
+    TModel: LinearTransitionModel<SD> + 'static,
+    MModel: LinearMeasurementModel<MD, SD> + 'static,
+{
+    match filter {
+        FilterConfig::Kalman { covariance_update } => Ok(boxed(
+            KalmanFilter::new(transition_model, measurement_model)
+                .with_outlier_handling(outlier_handling)
+                .with_covariance_update(covariance_update),
+        )),
+        FilterConfig::SquareRootKalman => match outlier_handling {
+            OutlierHandling::AcceptAll => Ok(boxed(SquareRootKalmanFilter::new(
+                transition_model,
+                measurement_model,
+            ))),
+            _ => Err(ConfigError::Unsupported(
+                "the square root kalman filter has no outlier handling".to_string(),
+            )),
+        },
+        _ => nonlinear_estimator(
+            filter,
+            transition_model,
+            measurement_model,
+            outlier_handling,
+        ),
+    }
+}
+
+/// # Returns
+/// Returns the configured filter for nonlinear models. ConfigError::Unsupported is returned for the
+/// filters that need linear models.
+fn nonlinear_estimator<const MD: usize, const SD: usize, TModel, MModel>(
+    filter: FilterConfig,
+    transition_model: TModel,
+    measurement_model: MModel,
+    outlier_handling: OutlierHandling,
+) -> Result<Box<dyn DynamicEstimator>, ConfigError>
+where
+    TModel: NonlinearTransitionModel<SD> + 'static,
+    MModel: NonlinearMeasurementModel<MD, SD> + 'static,
+{
+    match filter {
+        FilterConfig::ExtendedKalman => Ok(boxed(
+            ExtendedKalmanFilter::new(transition_model, measurement_model)
+                .with_outlier_handling(outlier_handling),
+        )),
+        FilterConfig::UnscentedKalman { sigma_points } => Ok(boxed(
+            UnscentedKalmanFilter::new(transition_model, measurement_model, sigma_points)?
+                .with_outlier_handling(outlier_handling),
+        )),
+        FilterConfig::Kalman { .. } => Err(ConfigError::Unsupported(
+            "the kalman filter needs linear models".to_string(),
+        )),
+        FilterConfig::SquareRootKalman => Err(ConfigError::Unsupported(
+            "the square root kalman filter needs linear models".to_string(),
+        )),
+    }
+}
+
+fn boxed<E, const MD: usize, const SD: usize>(estimator: E) -> Box<dyn DynamicEstimator>
+where
+    E: Estimator<MD, SD> + 'static,
+{
+    Box::new(StaticEstimator::new(estimator))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::{DVector, SVector};
+
+    use crate::config::{ConfigError, EstimatorConfig, FilterConfig, TransitionModelConfig};
+    use crate::dynamic::state::{DynamicMeasurement, DynamicWaypoint};
+    use crate::dynamic::track::DynamicTrack;
+    use crate::estimator::EstimationError;
+    use crate::kalman::outlier::OutlierHandling;
+
+    fn differential_drive_config() -> EstimatorConfig {
+        serde_json::from_str(
+            r#"{
+                "filter": { "type": "unscented_kalman" },
+                "transition_model": {
+                    "type": "differential_drive",
+                    "speed_gain": 1.0,
+                    "yaw_rate_gain": 1.0
+                },
+                "measurement_models": {
+                    "gps": {
+                        "type": "position",
+                        "error_x": 0.01,
+                        "error_y": 0.01,
+                        "outlier_handling": { "type": "chi_square_gate", "confidence_level": 0.99 }
+                    },
+                    "compass": { "type": "heading", "heading_error": 0.01 }
+                },
+                "initial_error": [0.01, 0.01, 0.01, 0.01, 0.01]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_differential_drive_sensors() {
+        // the sensors of the differential drive share the control input and the measurements of one
+        // timestamp result in a single waypoint
+        let config = differential_drive_config();
+        let estimator = config.build().unwrap();
+        estimator.set_control(SVector::<f64, 2>::new(1., 1.));
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let track = DynamicTrack::new(DynamicWaypoint::new(
+            start,
+            config.initial_state(DVector::zeros(5)).unwrap(),
+        ));
+        let timestamp = start + Duration::seconds(1);
+        let waypoints = estimator
+            .estimate_waypoints(
+                &track,
+                vec![
+                    (
+                        "gps".to_string(),
+                        DynamicMeasurement::new(timestamp, DVector::from_vec(vec![1., 0.])),
+                    ),
+                    (
+                        "compass".to_string(),
+                        DynamicMeasurement::new(timestamp, DVector::from_vec(vec![0.])),
+                    ),
+                    // the gps jump is rejected
+                    (
+                        "gps".to_string(),
+                        DynamicMeasurement::new(timestamp, DVector::from_vec(vec![10., 10.])),
+                    ),
+                ],
+            )
+            .unwrap();
+        assert_eq!(waypoints.len(), 1);
+        let waypoint = &waypoints[0];
+        assert!(waypoint.prediction.as_ref().unwrap().state.estimate[0] > 0.5);
+        assert!((waypoint.state.estimate[0] - 1.).abs() < 0.1);
+        assert!(waypoint.state.estimate[1].abs() < 0.1);
+    }
+
+    #[test]
+    fn test_rejected_configurations() {
+        let config = differential_drive_config();
+        // combinations the filters or the states cannot handle are rejected
+        let unsupported = [
+            r#"{ "type": "kalman" }"#,
+            r#"{ "type": "square_root_kalman" }"#,
+        ];
+        for filter in unsupported {
+            let mut config = config.clone();
+            config.filter = serde_json::from_str(filter).unwrap();
+            assert!(matches!(config.build(), Err(ConfigError::Unsupported(_))));
+        }
+        // a percent-style confidence level is a typo in the configuration
+        let mut percent_config = config.clone();
+        percent_config
+            .measurement_models
+            .get_mut("gps")
+            .unwrap()
+            .outlier_handling = OutlierHandling::ChiSquareGate {
+            confidence_level: 99.,
+        };
+        assert!(matches!(
+            percent_config.build(),
+            Err(ConfigError::Unsupported(_))
+        ));
+        let mut config = config;
+        config.filter = FilterConfig::ExtendedKalman;
+        config.transition_model = TransitionModelConfig::ConstantVelocity { drift: 0.05 };
+        assert!(matches!(config.build(), Err(ConfigError::Unsupported(_))));
+        assert!(matches!(
+            config.initial_state(DVector::zeros(4)),
+            Err(ConfigError::Estimation(
+                EstimationError::DimensionMismatch {
+                    expected: 4,
+                    actual: 5
+                }
+            ))
+        ));
+    }
+}
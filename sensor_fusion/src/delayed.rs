@@ -0,0 +1,205 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use chrono::Duration;
+
+use crate::estimator::{EstimationError, Estimator, Filter, Predictor};
+use crate::state::Measurement;
+use crate::track::Track;
+
+/// # Explanation
+/// The ReprocessingEstimator accepts measurements that arrive late (eg gps fixes that have a noticeable
+/// latency). It keeps the measurements of the last max_delay. When a measurement arrives that is older
+/// than the latest waypoint, the waypoints after it are removed from the track and the stored measurements
+/// are processed again together with the delayed one (in the order of their timestamps).
+///
+/// Measurements that are more than max_delay older than the latest waypoint are rejected with
+/// EstimationError::MeasurementTooOld. Since the waypoints are recomputed, every waypoint of the track
+/// (except the initial one) must have been created by this estimator. The wrapped estimator must not keep
+/// a state of its own, which is why only filters (Predictor and Filter) can be wrapped.
+pub struct ReprocessingEstimator<const MD: usize, E> {
+    estimator: E,
+    max_delay: Duration,
+    measurements: RefCell<VecDeque<Measurement<MD>>>,
+}
+
+impl<const MD: usize, E> ReprocessingEstimator<MD, E> {
+    pub fn new(estimator: E, max_delay: Duration) -> Self {
+        Self {
+            estimator,
+            max_delay,
+            measurements: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+
+    /// # Explanation
+    /// Adds the estimate for the measurement to the track. If the measurement is older than the latest
+    /// waypoint, all waypoints after it are estimated again. A stored measurement that fails when it is
+    /// processed again (eg because it is rejected as an outlier now) is skipped. If the measurement itself
+    /// fails, the error is returned and the track stays unchanged.
+    pub fn update<const SD: usize>(
+        &self,
+        track: &mut Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<(), EstimationError>
+    where
+        E: Predictor<SD> + Filter<MD, SD>,
+    {
+        let latest_timestamp = track.get_latest_waypoint().timestamp;
+        let delay = latest_timestamp - measurement.timestamp;
+        if delay > self.max_delay {
+            return Err(EstimationError::MeasurementTooOld(delay));
+        }
+
+        if delay <= Duration::zero() {
+            let waypoint = self
+                .estimator
+                .estimate_waypoint(track, measurement.clone())?;
+            track.add_waypoint(waypoint);
+        } else {
+            self.reprocess(track, measurement.clone())?;
+        }
+
+        let mut measurements = self.measurements.borrow_mut();
+        let index =
+            measurements.partition_point(|stored| stored.timestamp <= measurement.timestamp);
+        measurements.insert(index, measurement);
+        let oldest_timestamp = track.get_latest_waypoint().timestamp - self.max_delay;
+        while measurements
+            .front()
+            .is_some_and(|stored| stored.timestamp < oldest_timestamp)
+        {
+            measurements.pop_front();
+        }
+        Ok(())
+    }
+
+    fn reprocess<const SD: usize>(
+        &self,
+        track: &mut Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<(), EstimationError>
+    where
+        E: Predictor<SD> + Filter<MD, SD>,
+    {
+        let timestamp = measurement.timestamp;
+        let removed_waypoints = track.split_off_after(timestamp);
+
+        let mut reprocessed = Track::new(track.get_latest_waypoint().clone());
+        match self.estimator.estimate_waypoint(&reprocessed, measurement) {
+            Ok(waypoint) => reprocessed.add_waypoint(waypoint),
+            Err(error) => {
+                removed_waypoints
+                    .into_iter()
+                    .for_each(|waypoint| track.add_waypoint(waypoint));
+                return Err(error);
+            }
+        }
+
+        // stored measurements with the same timestamp are still part of the track
+        let measurements = self.measurements.borrow();
+        for stored in measurements
+            .iter()
+            .filter(|stored| stored.timestamp > timestamp)
+        {
+            match self
+                .estimator
+                .estimate_waypoint(&reprocessed, stored.clone())
+            {
+                Ok(waypoint) => reprocessed.add_waypoint(waypoint),
+                Err(error) => log::warn!(
+                    "The measurement at {} is skipped, since it cannot be processed again: {}",
+                    stored.timestamp,
+                    error
+                ),
+            }
+        }
+
+        reprocessed
+            .into_iter()
+            .skip(1)
+            .for_each(|waypoint| track.add_waypoint(waypoint));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use nalgebra::{SMatrix, SVector};
+
+    use crate::delayed::ReprocessingEstimator;
+    use crate::estimator::{EstimationError, Estimator};
+    use crate::kalman::estimator::KalmanFilter;
+    use crate::kalman::model::{ConstantVelocity, PositionMeasurementModel};
+    use crate::kalman::outlier::OutlierHandling;
+    use crate::state::{GaussianState, Measurement, Waypoint};
+    use crate::track::Track;
+
+    fn at(t: f64) -> DateTime<Utc> {
+        Utc.timestamp_nanos((t * 1_000_000_000.0) as i64)
+    }
+
+    fn kalman_filter() -> KalmanFilter<2, 4, ConstantVelocity, PositionMeasurementModel<4>> {
+        KalmanFilter::new(
+            ConstantVelocity::new(0.05),
+            PositionMeasurementModel::new(0.1, 0.1),
+        )
+    }
+
+    fn initial_track() -> Track<4> {
+        Track::new(Waypoint::new(
+            at(0.),
+            GaussianState::new(
+                SVector::<f64, 4>::zeros(),
+                SMatrix::<f64, 4, 4>::from_diagonal(&SVector::<f64, 4>::new(1000., 1000., 1., 1.)),
+            ),
+        ))
+    }
+
+    #[test]
+    fn test_too_old_measurement() {
+        let estimator = ReprocessingEstimator::new(kalman_filter(), Duration::milliseconds(500));
+        let mut track = initial_track();
+        for t in [1., 2., 3.] {
+            let measurement = Measurement::new(at(t), SVector::<f64, 2>::new(t, 0.));
+            assert!(estimator.update(&mut track, measurement).is_ok());
+        }
+
+        let too_old = Measurement::new(at(2.), SVector::<f64, 2>::zeros());
+        assert!(matches!(
+            estimator.update(&mut track, too_old.clone()),
+            Err(EstimationError::MeasurementTooOld(_))
+        ));
+        // without reprocessing every delayed measurement is too old
+        assert!(matches!(
+            kalman_filter().estimate(&track, too_old),
+            Err(EstimationError::MeasurementTooOld(_))
+        ));
+        assert_eq!(track.len(), 4);
+    }
+
+    #[test]
+    fn test_rejected_stored_measurements() {
+        // stored measurements that are rejected after the delayed measurement are skipped
+        let estimator = ReprocessingEstimator::new(
+            kalman_filter().with_outlier_handling(OutlierHandling::ChiSquareGate {
+                confidence_level: 0.99,
+            }),
+            Duration::seconds(2),
+        );
+        let mut track = initial_track();
+        for t in [1., 2.] {
+            let measurement = Measurement::new(at(t), SVector::<f64, 2>::zeros());
+            assert!(estimator.update(&mut track, measurement).is_ok());
+        }
+        let delayed = Measurement::new(at(0.5), SVector::<f64, 2>::new(50., 0.));
+        assert!(estimator.update(&mut track, delayed).is_ok());
+        assert_eq!(track.len(), 2);
+        assert_eq!(track.get_latest_waypoint().timestamp, at(0.5));
+    }
+}
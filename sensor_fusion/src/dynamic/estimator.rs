@@ -0,0 +1,364 @@
+use chrono::{DateTime, Duration, Utc};
+use nalgebra::{DMatrix, DVector};
+
+use crate::dynamic::model::{DynamicLinearMeasurementModel, DynamicLinearTransitionModel};
+use crate::dynamic::state::{
+    DynamicGaussianState, DynamicMeasurement, DynamicPrediction, DynamicWaypoint,
+};
+use crate::dynamic::track::DynamicTrack;
+use crate::estimator::{EstimationError, Estimator};
+use crate::kalman::estimator::CovarianceUpdate;
+use crate::kalman::outlier::{OutlierGate, OutlierHandling};
+use crate::linalg::check_dimension;
+use crate::state::Measurement;
+use crate::track::Track;
+
+/// # Explanation
+/// The DynamicEstimator is the Estimator for states and measurements whose dimensions are only known at
+/// runtime. Estimators with a different dimension than the track or the measurement return
+/// EstimationError::DimensionMismatch.
+pub trait DynamicEstimator {
+    fn estimate(
+        &self,
+        track: &DynamicTrack,
+        measurement: DynamicMeasurement,
+    ) -> Result<DynamicGaussianState, EstimationError>;
+
+    /// # Returns
+    /// Returns the estimate as a waypoint at the time of the measurement. Estimators that work with a
+    /// prediction also store it in the waypoint.
+    fn estimate_waypoint(
+        &self,
+        track: &DynamicTrack,
+        measurement: DynamicMeasurement,
+    ) -> Result<DynamicWaypoint, EstimationError> {
+        let timestamp = measurement.timestamp;
+        let estimate = self.estimate(track, measurement)?;
+        Ok(DynamicWaypoint::new(timestamp, estimate))
+    }
+}
+
+impl<T> DynamicEstimator for T
+where
+    T: DynamicPredictor + DynamicFilter + Sized,
+{
+    fn estimate(
+        &self,
+        track: &DynamicTrack,
+        measurement: DynamicMeasurement,
+    ) -> Result<DynamicGaussianState, EstimationError> {
+        let dt = time_since_latest_waypoint(track, measurement.timestamp)?;
+        let prediction = self.predict(track, dt)?;
+        let filtered = self.filter(prediction, measurement)?;
+        filtered.validate()?;
+        Ok(filtered)
+    }
+
+    fn estimate_waypoint(
+        &self,
+        track: &DynamicTrack,
+        measurement: DynamicMeasurement,
+    ) -> Result<DynamicWaypoint, EstimationError> {
+        let timestamp = measurement.timestamp;
+        let dt = time_since_latest_waypoint(track, timestamp)?;
+        let prediction = self.predict(track, dt)?;
+        let filtered = self.filter(prediction.clone(), measurement)?;
+        filtered.validate()?;
+        Ok(DynamicWaypoint::with_prediction(
+            timestamp,
+            filtered,
+            DynamicPrediction::new(dt, prediction),
+        ))
+    }
+}
+
+pub trait DynamicPredictor {
+    fn predict(
+        &self,
+        track: &DynamicTrack,
+        dt: Duration,
+    ) -> Result<DynamicGaussianState, EstimationError>;
+}
+
+pub trait DynamicFilter {
+    fn filter(
+        &self,
+        prediction: DynamicGaussianState,
+        measurement: DynamicMeasurement,
+    ) -> Result<DynamicGaussianState, EstimationError>;
+}
+
+/// # Explanation
+/// The DynamicKalmanFilter is the KalmanFilter for a DynamicTrack. The models are boxed, so that they
+/// can be chosen at runtime (eg a static model wrapped in a StaticTransitionModel or a
+/// StaticMeasurementModel).
+pub struct DynamicKalmanFilter {
+    transition_model: Box<dyn DynamicLinearTransitionModel>,
+    measurement_model: Box<dyn DynamicLinearMeasurementModel>,
+    outlier_gate: OutlierGate,
+    covariance_update: CovarianceUpdate,
+}
+
+impl DynamicKalmanFilter {
+    pub fn new(
+        transition_model: Box<dyn DynamicLinearTransitionModel>,
+        measurement_model: Box<dyn DynamicLinearMeasurementModel>,
+    ) -> Self {
+        let outlier_gate = OutlierGate::new(
+            OutlierHandling::AcceptAll,
+            measurement_model.measurement_dimension(),
+        );
+        Self {
+            transition_model,
+            measurement_model,
+            outlier_gate,
+            covariance_update: CovarianceUpdate::Standard,
+        }
+    }
+
+    /// # Explanation
+    /// Sets how measurements that do not fit the prediction are treated (by default every measurement
+    /// is accepted).
+    pub fn with_outlier_handling(mut self, outlier_handling: OutlierHandling) -> Self {
+        self.outlier_gate = OutlierGate::new(
+            outlier_handling,
+            self.measurement_model.measurement_dimension(),
+        );
+        self
+    }
+
+    pub fn with_covariance_update(mut self, covariance_update: CovarianceUpdate) -> Self {
+        self.covariance_update = covariance_update;
+        self
+    }
+
+    /// # Returns
+    /// Returns the innovation error and its inverse with the measurement error scaled according to the
+    /// outlier handling (see kalman::outlier).
+    fn robust_innovation_error(
+        &self,
+        innovation: &DVector<f64>,
+        predicted_measurement_error: &DMatrix<f64>,
+        measurement_error: &DMatrix<f64>,
+    ) -> Result<(DMatrix<f64>, DMatrix<f64>), EstimationError> {
+        let innovation_error = predicted_measurement_error + measurement_error;
+        let innovation_error_inverse = invert_innovation_error(innovation_error.clone())?;
+        let nis = innovation.dot(&(&innovation_error_inverse * innovation));
+
+        let scale = self.outlier_gate.measurement_error_scale(nis)?;
+        if scale == 1. {
+            return Ok((innovation_error, innovation_error_inverse));
+        }
+
+        let innovation_error = predicted_measurement_error + scale * measurement_error;
+        let innovation_error_inverse = invert_innovation_error(innovation_error.clone())?;
+        Ok((innovation_error, innovation_error_inverse))
+    }
+}
+
+impl DynamicPredictor for DynamicKalmanFilter {
+    fn predict(
+        &self,
+        track: &DynamicTrack,
+        dt: Duration,
+    ) -> Result<DynamicGaussianState, EstimationError> {
+        let prior = &track.get_latest_waypoint().state;
+        prior.check_dimension(self.transition_model.state_dimension())?;
+        let transition_matrix = self.transition_model.transition_matrix(dt);
+        let transition_error = self.transition_model.transition_error(dt);
+
+        Ok(DynamicGaussianState::new(
+            &transition_matrix * &prior.estimate,
+            &transition_matrix * &prior.error * transition_matrix.transpose() + transition_error,
+        ))
+    }
+}
+
+impl DynamicFilter for DynamicKalmanFilter {
+    fn filter(
+        &self,
+        prediction: DynamicGaussianState,
+        measurement: DynamicMeasurement,
+    ) -> Result<DynamicGaussianState, EstimationError> {
+        prediction.check_dimension(self.measurement_model.state_dimension())?;
+        check_dimension(
+            self.measurement_model.measurement_dimension(),
+            measurement.dimension(),
+        )?;
+        let measurement_matrix = self.measurement_model.measurement_matrix();
+        let measurement_error = self.measurement_model.measurement_error();
+
+        let innovation = measurement.vector - &measurement_matrix * &prediction.estimate;
+        let predicted_measurement_error =
+            &measurement_matrix * &prediction.error * measurement_matrix.transpose();
+        let (innovation_error, innovation_error_inverse) = self.robust_innovation_error(
+            &innovation,
+            &predicted_measurement_error,
+            &measurement_error,
+        )?;
+
+        let kalman_gain =
+            &prediction.error * measurement_matrix.transpose() * innovation_error_inverse;
+
+        let filtered_estimate = &prediction.estimate + &kalman_gain * innovation;
+        let filter_error = match self.covariance_update {
+            CovarianceUpdate::Standard => {
+                &prediction.error - &kalman_gain * innovation_error * kalman_gain.transpose()
+            }
+            CovarianceUpdate::Joseph => {
+                // the measurement error might have been scaled by the outlier handling
+                let measurement_error = innovation_error - predicted_measurement_error;
+                let correction =
+                    DMatrix::<f64>::identity(prediction.dimension(), prediction.dimension())
+                        - &kalman_gain * measurement_matrix;
+                &correction * &prediction.error * correction.transpose()
+                    + &kalman_gain * measurement_error * kalman_gain.transpose()
+            }
+        };
+        Ok(DynamicGaussianState::new(filtered_estimate, filter_error))
+    }
+}
+
+/// # Explanation
+/// The StaticEstimator makes an Estimator with static dimensions (eg an ExtendedKalmanFilter) usable as a
+/// DynamicEstimator. The estimators predict from the latest waypoint, so only the latest waypoint of the
+/// track is converted to the static dimension. EstimationError::DimensionMismatch is returned if the track
+/// or the measurement do not have the dimensions of the estimator.
+///
+/// # Type parameters
+/// - MD: the measurement dimension of the estimator
+/// - SD: the state dimension of the estimator
+pub struct StaticEstimator<E, const MD: usize, const SD: usize> {
+    estimator: E,
+}
+
+impl<E, const MD: usize, const SD: usize> StaticEstimator<E, MD, SD>
+where
+    E: Estimator<MD, SD>,
+{
+    pub fn new(estimator: E) -> Self {
+        Self { estimator }
+    }
+
+    pub fn estimator(&self) -> &E {
+        &self.estimator
+    }
+
+    fn to_static(
+        track: &DynamicTrack,
+        measurement: DynamicMeasurement,
+    ) -> Result<(Track<SD>, Measurement<MD>), EstimationError> {
+        let latest_waypoint = track.get_latest_waypoint().clone().try_into()?;
+        Ok((Track::new(latest_waypoint), measurement.try_into()?))
+    }
+}
+
+impl<E, const MD: usize, const SD: usize> DynamicEstimator for StaticEstimator<E, MD, SD>
+where
+    E: Estimator<MD, SD>,
+{
+    fn estimate(
+        &self,
+        track: &DynamicTrack,
+        measurement: DynamicMeasurement,
+    ) -> Result<DynamicGaussianState, EstimationError> {
+        let (track, measurement) = Self::to_static(track, measurement)?;
+        Ok(self.estimator.estimate(&track, measurement)?.into())
+    }
+
+    fn estimate_waypoint(
+        &self,
+        track: &DynamicTrack,
+        measurement: DynamicMeasurement,
+    ) -> Result<DynamicWaypoint, EstimationError> {
+        let (track, measurement) = Self::to_static(track, measurement)?;
+        Ok(self
+            .estimator
+            .estimate_waypoint(&track, measurement)?
+            .into())
+    }
+}
+
+/// # Returns
+/// Returns the time that has passed between the latest waypoint of the track and the given timestamp (see
+/// estimator::time_since_latest_waypoint).
+pub fn time_since_latest_waypoint(
+    track: &DynamicTrack,
+    timestamp: DateTime<Utc>,
+) -> Result<Duration, EstimationError> {
+    let dt = timestamp - track.get_latest_waypoint().timestamp;
+    if dt < Duration::zero() {
+        Err(EstimationError::MeasurementTooOld(-dt))
+    } else {
+        Ok(dt)
+    }
+}
+
+fn invert_innovation_error(
+    innovation_error: DMatrix<f64>,
+) -> Result<DMatrix<f64>, EstimationError> {
+    innovation_error.try_inverse().ok_or_else(|| {
+        EstimationError::NumericalError("the innovation error is not invertible".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::{DMatrix, DVector};
+
+    use crate::dynamic::estimator::{DynamicEstimator, DynamicKalmanFilter};
+    use crate::dynamic::model::{StaticMeasurementModel, StaticTransitionModel};
+    use crate::dynamic::state::{DynamicGaussianState, DynamicMeasurement, DynamicWaypoint};
+    use crate::dynamic::track::DynamicTrack;
+    use crate::estimator::EstimationError;
+    use crate::kalman::model::{ConstantVelocity, PositionMeasurementModel};
+
+    #[test]
+    fn test_dimension_checks() {
+        let dynamic_filter = DynamicKalmanFilter::new(
+            Box::new(StaticTransitionModel::new(ConstantVelocity::new(0.05))),
+            Box::new(StaticMeasurementModel::new(
+                PositionMeasurementModel::<4>::new(0.1, 0.1),
+            )),
+        );
+        let timestamp = Utc.timestamp_nanos(0);
+        let track = DynamicTrack::new(DynamicWaypoint::new(
+            timestamp,
+            DynamicGaussianState::new(DVector::zeros(4), DMatrix::identity(4, 4)),
+        ));
+        let measurement = DynamicMeasurement::new(
+            timestamp + Duration::seconds(1),
+            DVector::from_vec(vec![1., 2.]),
+        );
+        assert!(dynamic_filter
+            .estimate_waypoint(&track, measurement.clone())
+            .is_ok());
+
+        // a measurement that does not fit the measurement model is rejected
+        let wrong_measurement = DynamicMeasurement::new(
+            timestamp + Duration::seconds(1),
+            DVector::from_vec(vec![1., 2., 3.]),
+        );
+        assert!(matches!(
+            dynamic_filter.estimate(&track, wrong_measurement),
+            Err(EstimationError::DimensionMismatch {
+                expected: 2,
+                actual: 3
+            })
+        ));
+
+        // so is a state whose error does not fit its estimate
+        let mismatched_track = DynamicTrack::new(DynamicWaypoint::new(
+            timestamp,
+            DynamicGaussianState::new(DVector::zeros(4), DMatrix::identity(3, 3)),
+        ));
+        assert!(matches!(
+            dynamic_filter.estimate(&mismatched_track, measurement),
+            Err(EstimationError::DimensionMismatch {
+                expected: 4,
+                actual: 3
+            })
+        ));
+    }
+}
@@ -0,0 +1,4 @@
+pub mod estimator;
+pub mod model;
+pub mod state;
+pub mod track;
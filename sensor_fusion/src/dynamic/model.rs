@@ -0,0 +1,111 @@
+use chrono::Duration;
+use nalgebra::DMatrix;
+
+use crate::linalg::to_dynamic;
+use crate::model::{LinearMeasurementModel, LinearTransitionModel};
+
+/// # Explanation
+/// The DynamicLinearTransitionModel is the LinearTransitionModel whose state dimension is only known at
+/// runtime. The transition matrix and the transition error have to be n x n matrices with n being the
+/// state dimension.
+pub trait DynamicLinearTransitionModel {
+    fn state_dimension(&self) -> usize;
+
+    fn transition_matrix(&self, dt: Duration) -> DMatrix<f64>;
+
+    fn transition_error(&self, dt: Duration) -> DMatrix<f64>;
+}
+
+/// # Explanation
+/// The DynamicLinearMeasurementModel is the LinearMeasurementModel whose dimensions are only known at
+/// runtime. The measurement matrix has to be a m x n matrix and the measurement error a m x m matrix with
+/// m being the measurement dimension and n the state dimension.
+pub trait DynamicLinearMeasurementModel {
+    fn measurement_dimension(&self) -> usize;
+
+    fn state_dimension(&self) -> usize;
+
+    fn measurement_matrix(&self) -> DMatrix<f64>;
+
+    fn measurement_error(&self) -> DMatrix<f64>;
+}
+
+/// # Explanation
+/// The StaticTransitionModel makes a LinearTransitionModel (eg ConstantVelocity) usable where a
+/// DynamicLinearTransitionModel is needed.
+///
+/// # Type parameters
+/// - SD: the state dimension of the model
+#[derive(Copy, Clone)]
+pub struct StaticTransitionModel<TModel, const SD: usize> {
+    model: TModel,
+}
+
+impl<TModel, const SD: usize> StaticTransitionModel<TModel, SD>
+where
+    TModel: LinearTransitionModel<SD>,
+{
+    pub fn new(model: TModel) -> Self {
+        Self { model }
+    }
+}
+
+impl<TModel, const SD: usize> DynamicLinearTransitionModel for StaticTransitionModel<TModel, SD>
+where
+    TModel: LinearTransitionModel<SD>,
+{
+    fn state_dimension(&self) -> usize {
+        SD
+    }
+
+    fn transition_matrix(&self, dt: Duration) -> DMatrix<f64> {
+        to_dynamic(&self.model.transition_matrix(dt))
+    }
+
+    fn transition_error(&self, dt: Duration) -> DMatrix<f64> {
+        to_dynamic(&self.model.transition_error(dt))
+    }
+}
+
+/// # Explanation
+/// The StaticMeasurementModel makes a LinearMeasurementModel (eg PositionMeasurementModel) usable where a
+/// DynamicLinearMeasurementModel is needed.
+///
+/// # Type parameters
+/// - MD: the measurement dimension of the model
+/// - SD: the state dimension of the model
+#[derive(Copy, Clone)]
+pub struct StaticMeasurementModel<MModel, const MD: usize, const SD: usize> {
+    model: MModel,
+}
+
+impl<MModel, const MD: usize, const SD: usize> StaticMeasurementModel<MModel, MD, SD>
+where
+    MModel: LinearMeasurementModel<MD, SD>,
+{
+    pub fn new(model: MModel) -> Self {
+        Self { model }
+    }
+}
+
+impl<MModel, const MD: usize, const SD: usize> DynamicLinearMeasurementModel
+    for StaticMeasurementModel<MModel, MD, SD>
+where
+    MModel: LinearMeasurementModel<MD, SD>,
+{
+    fn measurement_dimension(&self) -> usize {
+        MD
+    }
+
+    fn state_dimension(&self) -> usize {
+        SD
+    }
+
+    fn measurement_matrix(&self) -> DMatrix<f64> {
+        to_dynamic(&self.model.measurement_matrix())
+    }
+
+    fn measurement_error(&self) -> DMatrix<f64> {
+        to_dynamic(&self.model.measurement_error())
+    }
+}
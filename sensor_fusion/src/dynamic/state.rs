@@ -0,0 +1,251 @@
+use chrono::{DateTime, Duration, Utc};
+use nalgebra::{DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+use crate::estimator::EstimationError;
+use crate::linalg::{check_dimension, to_dynamic, to_static, to_static_vector};
+use crate::state::{
+    duration_nanoseconds, validate_gaussian, GaussianState, Measurement, Prediction, Waypoint,
+};
+
+/// # Explanation
+/// The DynamicGaussianState is the GaussianState whose dimension is only known at runtime. The estimate
+/// has to be a vector of dimension n and the error a n x n matrix.
+///
+/// It can be converted from a GaussianState (From) and back (TryFrom), which fails with
+/// EstimationError::DimensionMismatch if the dimensions do not fit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynamicGaussianState {
+    pub estimate: DVector<f64>,
+    pub error: DMatrix<f64>,
+}
+
+impl DynamicGaussianState {
+    pub fn new(estimate: DVector<f64>, error: DMatrix<f64>) -> Self {
+        Self { estimate, error }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.estimate.len()
+    }
+
+    /// # Explanation
+    /// Checks that the estimate has the given dimension and that the error is a square matrix of the same
+    /// dimension. Otherwise EstimationError::DimensionMismatch is returned.
+    pub fn check_dimension(&self, dimension: usize) -> Result<(), EstimationError> {
+        check_dimension(dimension, self.dimension())?;
+        check_dimension(dimension, self.error.nrows())?;
+        check_dimension(dimension, self.error.ncols())
+    }
+
+    /// # Explanation
+    /// Checks that the error is a square matrix of the dimension of the estimate and that the state is valid
+    /// (see GaussianState::validate).
+    pub fn validate(&self) -> Result<(), EstimationError> {
+        self.check_dimension(self.dimension())?;
+        validate_gaussian(self.estimate.as_slice(), &self.error)
+    }
+}
+
+impl<const D: usize> From<GaussianState<D>> for DynamicGaussianState {
+    fn from(state: GaussianState<D>) -> Self {
+        Self::new(
+            DVector::from_column_slice(state.estimate.as_slice()),
+            to_dynamic(&state.error),
+        )
+    }
+}
+
+impl<const D: usize> TryFrom<DynamicGaussianState> for GaussianState<D> {
+    type Error = EstimationError;
+
+    fn try_from(state: DynamicGaussianState) -> Result<Self, Self::Error> {
+        Ok(GaussianState::new(
+            to_static_vector(&state.estimate)?,
+            to_static(&state.error)?,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynamicMeasurement {
+    pub timestamp: DateTime<Utc>,
+    pub vector: DVector<f64>,
+}
+
+impl DynamicMeasurement {
+    pub fn new(timestamp: DateTime<Utc>, vector: DVector<f64>) -> Self {
+        Self { timestamp, vector }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.vector.len()
+    }
+}
+
+impl<const D: usize> From<Measurement<D>> for DynamicMeasurement {
+    fn from(measurement: Measurement<D>) -> Self {
+        Self::new(
+            measurement.timestamp,
+            DVector::from_column_slice(measurement.vector.as_slice()),
+        )
+    }
+}
+
+impl<const D: usize> TryFrom<DynamicMeasurement> for Measurement<D> {
+    type Error = EstimationError;
+
+    fn try_from(measurement: DynamicMeasurement) -> Result<Self, Self::Error> {
+        Ok(Measurement::new(
+            measurement.timestamp,
+            to_static_vector(&measurement.vector)?,
+        ))
+    }
+}
+
+/// # Explanation
+/// The DynamicPrediction is the Prediction with a DynamicGaussianState.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynamicPrediction {
+    #[serde(with = "duration_nanoseconds")]
+    pub dt: Duration,
+    pub state: DynamicGaussianState,
+}
+
+impl DynamicPrediction {
+    pub fn new(dt: Duration, state: DynamicGaussianState) -> Self {
+        Self { dt, state }
+    }
+}
+
+impl<const D: usize> From<Prediction<D>> for DynamicPrediction {
+    fn from(prediction: Prediction<D>) -> Self {
+        Self::new(prediction.dt, prediction.state.into())
+    }
+}
+
+impl<const D: usize> TryFrom<DynamicPrediction> for Prediction<D> {
+    type Error = EstimationError;
+
+    fn try_from(prediction: DynamicPrediction) -> Result<Self, Self::Error> {
+        Ok(Prediction::new(prediction.dt, prediction.state.try_into()?))
+    }
+}
+
+/// # Explanation
+/// The DynamicWaypoint is the Waypoint with a DynamicGaussianState.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynamicWaypoint {
+    pub timestamp: DateTime<Utc>,
+    pub state: DynamicGaussianState,
+    pub prediction: Option<DynamicPrediction>,
+    pub mode_probabilities: Option<Vec<f64>>,
+}
+
+impl DynamicWaypoint {
+    pub fn new(timestamp: DateTime<Utc>, state: DynamicGaussianState) -> Self {
+        Self {
+            timestamp,
+            state,
+            prediction: None,
+            mode_probabilities: None,
+        }
+    }
+
+    pub fn with_prediction(
+        timestamp: DateTime<Utc>,
+        state: DynamicGaussianState,
+        prediction: DynamicPrediction,
+    ) -> Self {
+        Self {
+            timestamp,
+            state,
+            prediction: Some(prediction),
+            mode_probabilities: None,
+        }
+    }
+
+    pub fn from_state(state: DynamicGaussianState) -> Self {
+        Self::new(Utc::now(), state)
+    }
+}
+
+impl<const D: usize> From<Waypoint<D>> for DynamicWaypoint {
+    fn from(waypoint: Waypoint<D>) -> Self {
+        Self {
+            timestamp: waypoint.timestamp,
+            state: waypoint.state.into(),
+            prediction: waypoint.prediction.map(DynamicPrediction::from),
+            mode_probabilities: waypoint.mode_probabilities,
+        }
+    }
+}
+
+impl<const D: usize> TryFrom<DynamicWaypoint> for Waypoint<D> {
+    type Error = EstimationError;
+
+    fn try_from(waypoint: DynamicWaypoint) -> Result<Self, Self::Error> {
+        Ok(Waypoint {
+            timestamp: waypoint.timestamp,
+            state: waypoint.state.try_into()?,
+            prediction: waypoint.prediction.map(Prediction::try_from).transpose()?,
+            mode_probabilities: waypoint.mode_probabilities,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::{SMatrix, SVector};
+
+    use crate::dynamic::state::{DynamicGaussianState, DynamicMeasurement, DynamicWaypoint};
+    use crate::estimator::EstimationError;
+    use crate::state::{GaussianState, Measurement, Prediction, Waypoint};
+
+    #[test]
+    fn test_conversions() {
+        // the conversions keep the values and check the dimensions
+        let state = GaussianState::new(
+            SVector::<f64, 4>::new(1., 2., 3., 4.),
+            SMatrix::<f64, 4, 4>::from_diagonal_element(0.1),
+        );
+        let dynamic_state = DynamicGaussianState::from(state.clone());
+        assert_eq!(dynamic_state.dimension(), 4);
+        let converted_state: GaussianState<4> = dynamic_state.clone().try_into().unwrap();
+        assert_eq!(converted_state.estimate, state.estimate);
+        assert_eq!(converted_state.error, state.error);
+        assert!(matches!(
+            GaussianState::<5>::try_from(dynamic_state),
+            Err(EstimationError::DimensionMismatch {
+                expected: 5,
+                actual: 4
+            })
+        ));
+
+        let timestamp = Utc.timestamp_nanos(0);
+        let measurement =
+            DynamicMeasurement::from(Measurement::new(timestamp, SVector::<f64, 2>::new(1., 2.)));
+        assert_eq!(measurement.dimension(), 2);
+        assert!(Measurement::<2>::try_from(measurement.clone()).is_ok());
+        assert!(matches!(
+            Measurement::<3>::try_from(measurement),
+            Err(EstimationError::DimensionMismatch { .. })
+        ));
+
+        let mut waypoint = Waypoint::with_prediction(
+            timestamp,
+            state.clone(),
+            Prediction::new(Duration::milliseconds(100), state),
+        );
+        waypoint.mode_probabilities = Some(vec![0.25, 0.75]);
+        let converted: Waypoint<4> = DynamicWaypoint::from(waypoint.clone()).try_into().unwrap();
+        assert_eq!(converted.timestamp, waypoint.timestamp);
+        assert_eq!(converted.state.estimate, waypoint.state.estimate);
+        let (converted_prediction, prediction) =
+            (converted.prediction.unwrap(), waypoint.prediction.unwrap());
+        assert_eq!(converted_prediction.dt, prediction.dt);
+        assert_eq!(converted_prediction.state.error, prediction.state.error);
+        assert_eq!(converted.mode_probabilities, waypoint.mode_probabilities);
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::dynamic::state::DynamicWaypoint;
+use crate::estimator::EstimationError;
+use crate::state::Waypoint;
+use crate::track::Track;
+
+/// # Explanation
+/// The DynamicTrack is the Track of DynamicWaypoints. Like the track it cannot be empty.
+///
+/// It can be converted from a Track (From) and back (TryFrom), which fails with
+/// EstimationError::DimensionMismatch if a waypoint does not have the dimension of the track.
+#[derive(Clone)]
+pub struct DynamicTrack {
+    waypoints: VecDeque<DynamicWaypoint>,
+}
+
+impl DynamicTrack {
+    pub fn new(initial_waypoint: DynamicWaypoint) -> Self {
+        Self {
+            waypoints: VecDeque::from([initial_waypoint]),
+        }
+    }
+
+    pub fn add_waypoint(&mut self, waypoint: DynamicWaypoint) {
+        self.waypoints.push_back(waypoint);
+    }
+
+    pub fn get_latest_waypoint(&self) -> &DynamicWaypoint {
+        self.waypoints.back().unwrap() // waypoints cannot be empty
+    }
+
+    pub fn get_first_waypoint(&self) -> &DynamicWaypoint {
+        self.waypoints.front().unwrap() // waypoints cannot be empty
+    }
+
+    /// # Returns
+    /// Returns the waypoint with exactly the given timestamp.
+    pub fn get_waypoint(&self, timestamp: DateTime<Utc>) -> Option<&DynamicWaypoint> {
+        self.waypoints
+            .binary_search_by_key(&timestamp, |waypoint| waypoint.timestamp)
+            .ok()
+            .map(|index| &self.waypoints[index])
+    }
+
+    pub fn iter(&self) -> std::collections::vec_deque::Iter<'_, DynamicWaypoint> {
+        self.waypoints.iter()
+    }
+}
+
+impl<const D: usize> From<Track<D>> for DynamicTrack {
+    fn from(track: Track<D>) -> Self {
+        Self {
+            waypoints: track.into_iter().map(DynamicWaypoint::from).collect(),
+        }
+    }
+}
+
+impl<const D: usize> TryFrom<DynamicTrack> for Track<D> {
+    type Error = EstimationError;
+
+    fn try_from(track: DynamicTrack) -> Result<Self, Self::Error> {
+        let mut waypoints = track.into_iter().map(Waypoint::try_from);
+        // the dynamic track cannot be empty
+        let mut converted = Track::new(waypoints.next().unwrap()?);
+        for waypoint in waypoints {
+            converted.add_waypoint(waypoint?);
+        }
+        Ok(converted)
+    }
+}
+
+impl IntoIterator for DynamicTrack {
+    type Item = DynamicWaypoint;
+    type IntoIter = std::collections::vec_deque::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.waypoints.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a DynamicTrack {
+    type Item = &'a DynamicWaypoint;
+    type IntoIter = std::collections::vec_deque::Iter<'a, DynamicWaypoint>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.waypoints.iter()
+    }
+}
+
+impl Serialize for DynamicTrack {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.waypoints.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DynamicTrack {
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let waypoints = VecDeque::<DynamicWaypoint>::deserialize(deserializer)?;
+        if waypoints.is_empty() {
+            return Err(serde::de::Error::custom(
+                "a track needs at least one waypoint",
+            ));
+        }
+        Ok(Self { waypoints })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use nalgebra::{DMatrix, DVector};
+
+    use crate::dynamic::state::{DynamicGaussianState, DynamicWaypoint};
+    use crate::dynamic::track::DynamicTrack;
+    use crate::estimator::EstimationError;
+    use crate::track::Track;
+
+    #[test]
+    fn test_conversion() {
+        let waypoint = |t: i64, dimension: usize| {
+            DynamicWaypoint::new(
+                Utc.timestamp_nanos(t * 1_000_000_000),
+                DynamicGaussianState::new(
+                    DVector::zeros(dimension),
+                    DMatrix::identity(dimension, dimension),
+                ),
+            )
+        };
+        let mut track = DynamicTrack::new(waypoint(0, 4));
+        track.add_waypoint(waypoint(1, 4));
+        let converted: Track<4> = track.clone().try_into().unwrap();
+        assert_eq!(converted.len(), 2);
+        assert!(matches!(
+            Track::<2>::try_from(track.clone()),
+            Err(EstimationError::DimensionMismatch { .. })
+        ));
+
+        // every waypoint must have the dimension of the track
+        track.add_waypoint(waypoint(2, 5));
+        assert!(matches!(
+            Track::<4>::try_from(track),
+            Err(EstimationError::DimensionMismatch { .. })
+        ));
+    }
+}
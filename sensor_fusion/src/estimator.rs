@@ -1,22 +1,57 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 
-use crate::state::{GaussianState, Measurement};
+use crate::state::{GaussianState, Measurement, Prediction, Waypoint};
 use crate::track::Track;
 
 #[derive(Debug)]
 pub enum EstimationError {
-    NumericalError,
+    NumericalError(String),
+    MissingPrediction,
+    MeasurementTooOld(Duration),
+    OutlierRejected(f64),
+    DimensionMismatch { expected: usize, actual: usize },
+    InvalidParameter(String),
     Other,
 }
 
 impl Display for EstimationError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            EstimationError::NumericalError => {
-                write!(f, "Some kind of numerical operation failed.")
+            EstimationError::NumericalError(details) => {
+                write!(f, "Some kind of numerical operation failed: {}.", details)
+            }
+            EstimationError::MissingPrediction => {
+                write!(
+                    f,
+                    "A waypoint does not contain the prediction of the filter."
+                )
+            }
+            EstimationError::MeasurementTooOld(delay) => {
+                write!(
+                    f,
+                    "The measurement is {} ms older than the latest waypoint.",
+                    delay.num_milliseconds()
+                )
+            }
+            EstimationError::OutlierRejected(nis) => {
+                write!(
+                    f,
+                    "The measurement was rejected as an outlier (normalized innovation squared of {:.2}).",
+                    nis
+                )
+            }
+            EstimationError::DimensionMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "A vector or matrix has the dimension {} instead of {}.",
+                    actual, expected
+                )
+            }
+            EstimationError::InvalidParameter(details) => {
+                write!(f, "A parameter is invalid: {}.", details)
             }
             EstimationError::Other => {
                 write!(f, "Something special happened in the estimation phase.")
@@ -33,6 +68,19 @@ pub trait Estimator<const MD: usize, const SD: usize> {
         track: &Track<SD>,
         measurement: Measurement<MD>,
     ) -> Result<GaussianState<SD>, EstimationError>;
+
+    /// # Returns
+    /// Returns the estimate as a waypoint at the time of the measurement. Estimators that work with a
+    /// prediction also store it in the waypoint.
+    fn estimate_waypoint(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<Waypoint<SD>, EstimationError> {
+        let timestamp = measurement.timestamp;
+        let estimate = self.estimate(track, measurement)?;
+        Ok(Waypoint::new(timestamp, estimate))
+    }
 }
 
 impl<const SD: usize, const MD: usize, T> Estimator<MD, SD> for T
@@ -44,11 +92,29 @@ where
         track: &Track<SD>,
         measurement: Measurement<MD>,
     ) -> Result<GaussianState<SD>, EstimationError> {
-        let dt = measurement.timestamp - track.get_latest_waypoint().timestamp;
+        let dt = time_since_latest_waypoint(track, measurement.timestamp)?;
         let prediction = self.predict(track, dt)?;
         let filtered = self.filter(prediction, measurement)?;
+        filtered.validate()?;
         Ok(filtered)
     }
+
+    fn estimate_waypoint(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<Waypoint<SD>, EstimationError> {
+        let timestamp = measurement.timestamp;
+        let dt = time_since_latest_waypoint(track, timestamp)?;
+        let prediction = self.predict(track, dt)?;
+        let filtered = self.filter(prediction.clone(), measurement)?;
+        filtered.validate()?;
+        Ok(Waypoint::with_prediction(
+            timestamp,
+            filtered,
+            Prediction::new(dt, prediction),
+        ))
+    }
 }
 
 pub trait Predictor<const SD: usize> {
@@ -66,3 +132,19 @@ pub trait Filter<const MD: usize, const SD: usize> {
         measurement: Measurement<MD>,
     ) -> Result<GaussianState<SD>, EstimationError>;
 }
+
+/// # Returns
+/// Returns the time that has passed between the latest waypoint of the track and the given timestamp.
+/// Estimators cannot go back in time, so EstimationError::MeasurementTooOld is returned if the timestamp
+/// is older than the latest waypoint (see ReprocessingEstimator for delayed measurements).
+pub fn time_since_latest_waypoint<const SD: usize>(
+    track: &Track<SD>,
+    timestamp: DateTime<Utc>,
+) -> Result<Duration, EstimationError> {
+    let dt = timestamp - track.get_latest_waypoint().timestamp;
+    if dt < Duration::zero() {
+        Err(EstimationError::MeasurementTooOld(-dt))
+    } else {
+        Ok(dt)
+    }
+}
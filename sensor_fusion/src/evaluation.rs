@@ -0,0 +1,237 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, Utc};
+
+use crate::kalman::outlier::chi_square_quantile;
+use crate::model::NonlinearMeasurementModel;
+use crate::state::{Measurement, Waypoint};
+use crate::track::Track;
+
+#[derive(Debug)]
+pub enum EvaluationError {
+    NoMatchingWaypoints,
+    SingularError(DateTime<Utc>),
+    TrackTooShort(f64),
+}
+
+impl Display for EvaluationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvaluationError::NoMatchingWaypoints => {
+                write!(f, "No waypoint of the track has a matching timestamp.")
+            }
+            EvaluationError::SingularError(timestamp) => {
+                write!(f, "The error at {} is not invertible.", timestamp)
+            }
+            EvaluationError::TrackTooShort(distance) => {
+                write!(f, "The ground truth is shorter than {} m.", distance)
+            }
+        }
+    }
+}
+
+impl Error for EvaluationError {}
+
+/// # Explanation
+/// The ConsistencyStatistics summarize chi-square distributed values of a filter run, like the normalized
+/// estimation error squared (NEES) or the normalized innovation squared (NIS). For a consistent filter
+/// every value is chi-square distributed with degrees_of_freedom degrees of freedom, so its mean is
+/// degrees_of_freedom. The sum of n values is chi-square distributed with n * degrees_of_freedom degrees
+/// of freedom, which gives the two-sided confidence bounds of the mean.
+///
+/// A mean above the upper bound means that the filter is over-confident (its errors are too small), a
+/// mean below the lower bound means that it is under-confident.
+#[derive(Debug, Clone)]
+pub struct ConsistencyStatistics {
+    pub values: Vec<f64>,
+    pub degrees_of_freedom: usize,
+    pub confidence_level: f64,
+    pub mean: f64,
+    pub lower_bound: f64,
+    pub upper_bound: f64,
+}
+
+impl ConsistencyStatistics {
+    /// # Explanation
+    /// Computes the statistics of the values (eg the NIS of the UpdateDiagnostics of a run).
+    /// EvaluationError::NoMatchingWaypoints is returned if there are no values.
+    pub fn new(
+        values: Vec<f64>,
+        degrees_of_freedom: usize,
+        confidence_level: f64,
+    ) -> Result<Self, EvaluationError> {
+        if values.is_empty() {
+            return Err(EvaluationError::NoMatchingWaypoints);
+        }
+
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let (lower_probability, upper_probability) = two_sided_probabilities(confidence_level);
+        let total_degrees_of_freedom = values.len() * degrees_of_freedom;
+        Ok(Self {
+            values,
+            degrees_of_freedom,
+            confidence_level,
+            mean,
+            lower_bound: chi_square_quantile(lower_probability, total_degrees_of_freedom) / n,
+            upper_bound: chi_square_quantile(upper_probability, total_degrees_of_freedom) / n,
+        })
+    }
+
+    /// # Returns
+    /// Returns true if the mean lies within the confidence bounds.
+    pub fn is_consistent(&self) -> bool {
+        self.lower_bound <= self.mean && self.mean <= self.upper_bound
+    }
+
+    /// # Returns
+    /// Returns the fraction of the values that lie within the two-sided confidence interval of a single
+    /// value. For a consistent filter this is about the confidence level.
+    pub fn fraction_inside(&self) -> f64 {
+        let (lower_probability, upper_probability) = two_sided_probabilities(self.confidence_level);
+        let lower = chi_square_quantile(lower_probability, self.degrees_of_freedom);
+        let upper = chi_square_quantile(upper_probability, self.degrees_of_freedom);
+        let inside = self
+            .values
+            .iter()
+            .filter(|value| lower <= **value && **value <= upper)
+            .count();
+        inside as f64 / self.values.len() as f64
+    }
+}
+
+/// # Returns
+/// Returns the NEES statistics of the track, ie (x - x_true)^T * P^-1 * (x - x_true) for every waypoint
+/// of the track that has a waypoint with the same timestamp in the ground truth. Only the estimates of
+/// the ground truth are used.
+pub fn nees_statistics<const SD: usize>(
+    ground_truth: &Track<SD>,
+    track: &Track<SD>,
+    confidence_level: f64,
+) -> Result<ConsistencyStatistics, EvaluationError> {
+    let values = matching_waypoints(ground_truth, track)
+        .into_iter()
+        .map(|(true_waypoint, waypoint)| {
+            let error_inverse = waypoint
+                .state
+                .error
+                .try_inverse()
+                .ok_or(EvaluationError::SingularError(waypoint.timestamp))?;
+            let difference = waypoint.state.estimate - true_waypoint.state.estimate;
+            Ok(difference.dot(&(error_inverse * difference)))
+        })
+        .collect::<Result<Vec<f64>, EvaluationError>>()?;
+    ConsistencyStatistics::new(values, SD, confidence_level)
+}
+
+/// # Returns
+/// Returns the NIS statistics of the track for the measurements it was estimated from. The NIS is
+/// computed from the prediction that is stored in the waypoint with the timestamp of the measurement
+/// (waypoints without a prediction are skipped), so the track must have been created by a filter with the
+/// given measurement model. If the filter scaled the measurement error (see OutlierHandling::Huber), the
+/// NIS of its UpdateDiagnostics differs from this one.
+pub fn nis_statistics<const MD: usize, const SD: usize, MModel>(
+    track: &Track<SD>,
+    measurements: &[Measurement<MD>],
+    measurement_model: &MModel,
+    confidence_level: f64,
+) -> Result<ConsistencyStatistics, EvaluationError>
+where
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    let values = measurements
+        .iter()
+        .filter_map(|measurement| {
+            let waypoint = track.get_waypoint(measurement.timestamp)?;
+            let prediction = &waypoint.prediction.as_ref()?.state;
+
+            let measurement_jacobian = measurement_model.measurement_jacobian(&prediction.estimate);
+            let innovation = measurement.vector - measurement_model.measure(&prediction.estimate);
+            let innovation_error =
+                measurement_jacobian * prediction.error * measurement_jacobian.transpose()
+                    + measurement_model.measurement_error();
+            Some(
+                innovation_error
+                    .try_inverse()
+                    .map(|inverse| innovation.dot(&(inverse * innovation)))
+                    .ok_or(EvaluationError::SingularError(measurement.timestamp)),
+            )
+        })
+        .collect::<Result<Vec<f64>, EvaluationError>>()?;
+    ConsistencyStatistics::new(values, MD, confidence_level)
+}
+
+/// # Returns
+/// Returns the pairs of waypoints (ground truth, track) that have the same timestamp.
+pub(crate) fn matching_waypoints<'a, const SD: usize>(
+    ground_truth: &'a Track<SD>,
+    track: &'a Track<SD>,
+) -> Vec<(&'a Waypoint<SD>, &'a Waypoint<SD>)> {
+    track
+        .iter()
+        .filter_map(|waypoint| {
+            ground_truth
+                .get_waypoint(waypoint.timestamp)
+                .map(|true_waypoint| (true_waypoint, waypoint))
+        })
+        .collect()
+}
+
+fn two_sided_probabilities(confidence_level: f64) -> (f64, f64) {
+    ((1. - confidence_level) / 2., (1. + confidence_level) / 2.)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use nalgebra::{SMatrix, SVector};
+
+    use crate::evaluation::{nees_statistics, ConsistencyStatistics, EvaluationError};
+    use crate::state::{GaussianState, Waypoint};
+    use crate::track::Track;
+
+    #[test]
+    fn test_consistency_statistics() {
+        assert!(matches!(
+            ConsistencyStatistics::new(Vec::new(), 2, 0.95),
+            Err(EvaluationError::NoMatchingWaypoints)
+        ));
+
+        // the mean of a consistent filter is the number of degrees of freedom
+        let statistics = ConsistencyStatistics::new(vec![1., 2., 3.], 2, 0.95).unwrap();
+        assert!((statistics.mean - 2.).abs() < 1e-12);
+        assert!(statistics.lower_bound < 2. && 2. < statistics.upper_bound);
+        assert!(statistics.is_consistent());
+        assert!((statistics.fraction_inside() - 1.).abs() < 1e-12);
+
+        let over_confident = ConsistencyStatistics::new(vec![20., 30., 40.], 2, 0.95).unwrap();
+        assert!(over_confident.mean > over_confident.upper_bound);
+        assert!(!over_confident.is_consistent());
+        assert_eq!(over_confident.fraction_inside(), 0.);
+    }
+
+    #[test]
+    fn test_singular_error() {
+        let timestamp = Utc.timestamp_nanos(0);
+        let ground_truth = Track::new(Waypoint::new(
+            timestamp,
+            GaussianState::new(SVector::<f64, 2>::zeros(), SMatrix::<f64, 2, 2>::zeros()),
+        ));
+        // the ground truth has no error, so it cannot be evaluated against itself
+        assert!(matches!(
+            nees_statistics(&ground_truth, &ground_truth, 0.95),
+            Err(EvaluationError::SingularError(_))
+        ));
+
+        let track = Track::new(Waypoint::new(
+            timestamp,
+            GaussianState::new(
+                SVector::<f64, 2>::new(1., 1.),
+                SMatrix::<f64, 2, 2>::identity(),
+            ),
+        ));
+        let nees = nees_statistics(&ground_truth, &track, 0.95).unwrap();
+        assert_eq!(nees.values, vec![2.]);
+    }
+}
@@ -0,0 +1,373 @@
+use std::io::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use nalgebra::Vector2;
+use serde_json::{json, Value};
+
+use crate::linalg::covariance_ellipse;
+use crate::serialization::SerializationError;
+use crate::state::Waypoint;
+use crate::track::Track;
+
+/// # Explanation
+/// The GeoExporter writes a track to formats of GIS tools (GeoJSON, GPX and KML), so that a run can be
+/// viewed on a map next to the raw gps fixes. The position of a waypoint is made up of the first two
+/// entries of the state, which are the east and north coordinates of the local ENU frame. to_geo converts
+/// them to (longitude, latitude) in degrees (eg with sensors::coordinates::ENUToGeo and the base point of
+/// the position sensor).
+///
+/// Optionally the uncertainty of the positions is exported as well: for every waypoint the ellipse of
+/// the position error (sigma standard deviations) is written as a polygon.
+///
+/// # Type parameters
+/// - F: the conversion from (east, north) in meters to (longitude, latitude) in degrees
+pub struct GeoExporter<F> {
+    to_geo: F,
+    name: String,
+    uncertainty_sigma: Option<f64>,
+}
+
+impl<F> GeoExporter<F>
+where
+    F: Fn(f64, f64) -> (f64, f64),
+{
+    const ELLIPSE_POINTS: usize = 36;
+
+    pub fn new(to_geo: F) -> Self {
+        Self {
+            to_geo,
+            name: "track".to_string(),
+            uncertainty_sigma: None,
+        }
+    }
+
+    /// # Explanation
+    /// Sets the name of the track in the exported files.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// # Explanation
+    /// Also exports the ellipses of the position errors with semi-axes of sigma standard deviations.
+    pub fn with_uncertainty_ellipses(mut self, sigma: f64) -> Self {
+        self.uncertainty_sigma = Some(sigma);
+        self
+    }
+
+    /// # Explanation
+    /// Writes a FeatureCollection with a LineString of the track (the timestamps are in the property
+    /// "timestamps") and a Polygon for every uncertainty ellipse (with the properties "timestamp" and
+    /// "sigma").
+    pub fn write_geojson<const D: usize>(
+        &self,
+        track: &Track<D>,
+        writer: impl Write,
+    ) -> Result<(), SerializationError> {
+        let line = json!({
+            "type": "Feature",
+            "properties": {
+                "name": self.name,
+                "timestamps": track
+                    .iter()
+                    .map(|waypoint| format_timestamp(waypoint.timestamp))
+                    .collect::<Vec<String>>(),
+            },
+            "geometry": {
+                "type": "LineString",
+                "coordinates": track
+                    .iter()
+                    .map(|waypoint| geojson_position(self.geo_position(waypoint)))
+                    .collect::<Vec<Value>>(),
+            },
+        });
+        let ellipses = self.ellipses(track).map(|(waypoint, sigma, ring)| {
+            json!({
+                "type": "Feature",
+                "properties": {
+                    "timestamp": format_timestamp(waypoint.timestamp),
+                    "sigma": sigma,
+                },
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [ring.into_iter().map(geojson_position).collect::<Vec<Value>>()],
+                },
+            })
+        });
+
+        let features: Vec<Value> = std::iter::once(line).chain(ellipses).collect();
+        let feature_collection = json!({
+            "type": "FeatureCollection",
+            "features": features,
+        });
+        Ok(serde_json::to_writer_pretty(writer, &feature_collection)?)
+    }
+
+    /// # Explanation
+    /// Writes a GPX 1.1 file with the track as trk (with the timestamps of the waypoints). GPX has no
+    /// polygons, so the uncertainty ellipses are written as a second trk with one closed segment per
+    /// ellipse.
+    pub fn write_gpx<const D: usize>(
+        &self,
+        track: &Track<D>,
+        mut writer: impl Write,
+    ) -> Result<(), SerializationError> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<gpx version="1.1" creator="raspberry_pi_localization" xmlns="http://www.topografix.com/GPX/1/1">"#
+        )?;
+
+        writeln!(writer, "  <trk>")?;
+        writeln!(writer, "    <name>{}</name>", escape_xml(&self.name))?;
+        writeln!(writer, "    <trkseg>")?;
+        for waypoint in track {
+            let (lon, lat) = self.geo_position(waypoint);
+            writeln!(
+                writer,
+                r#"      <trkpt lat="{}" lon="{}"><time>{}</time></trkpt>"#,
+                lat,
+                lon,
+                format_timestamp(waypoint.timestamp)
+            )?;
+        }
+        writeln!(writer, "    </trkseg>")?;
+        writeln!(writer, "  </trk>")?;
+
+        if self.uncertainty_sigma.is_some() {
+            writeln!(writer, "  <trk>")?;
+            writeln!(
+                writer,
+                "    <name>{} uncertainty</name>",
+                escape_xml(&self.name)
+            )?;
+            for (_, _, ring) in self.ellipses(track) {
+                writeln!(writer, "    <trkseg>")?;
+                for (lon, lat) in ring {
+                    writeln!(writer, r#"      <trkpt lat="{}" lon="{}"/>"#, lat, lon)?;
+                }
+                writeln!(writer, "    </trkseg>")?;
+            }
+            writeln!(writer, "  </trk>")?;
+        }
+
+        writeln!(writer, "</gpx>")?;
+        Ok(())
+    }
+
+    /// # Explanation
+    /// Writes a KML 2.2 document with the track as LineString and every uncertainty ellipse as Polygon
+    /// (with the timestamp of its waypoint).
+    pub fn write_kml<const D: usize>(
+        &self,
+        track: &Track<D>,
+        mut writer: impl Write,
+    ) -> Result<(), SerializationError> {
+        let name = escape_xml(&self.name);
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+        writeln!(writer, "  <Document>")?;
+        writeln!(writer, "    <name>{}</name>", name)?;
+
+        let coordinates = kml_coordinates(track.iter().map(|waypoint| self.geo_position(waypoint)));
+        writeln!(writer, "    <Placemark>")?;
+        writeln!(writer, "      <name>{}</name>", name)?;
+        writeln!(
+            writer,
+            "      <LineString><tessellate>1</tessellate><coordinates>{}</coordinates></LineString>",
+            coordinates
+        )?;
+        writeln!(writer, "    </Placemark>")?;
+
+        for (waypoint, sigma, ring) in self.ellipses(track) {
+            writeln!(writer, "    <Placemark>")?;
+            writeln!(writer, "      <name>{} sigma</name>", sigma)?;
+            writeln!(
+                writer,
+                "      <TimeStamp><when>{}</when></TimeStamp>",
+                format_timestamp(waypoint.timestamp)
+            )?;
+            writeln!(
+                writer,
+                "      <Polygon><outerBoundaryIs><LinearRing><coordinates>{}</coordinates></LinearRing></outerBoundaryIs></Polygon>",
+                kml_coordinates(ring.into_iter())
+            )?;
+            writeln!(writer, "    </Placemark>")?;
+        }
+
+        writeln!(writer, "  </Document>")?;
+        writeln!(writer, "</kml>")?;
+        Ok(())
+    }
+
+    fn geo_position<const D: usize>(&self, waypoint: &Waypoint<D>) -> (f64, f64) {
+        let estimate = &waypoint.state.estimate;
+        (self.to_geo)(estimate[0], estimate[1])
+    }
+
+    /// # Returns
+    /// Returns the waypoints together with the sigma and the closed ring (longitude, latitude) of their
+    /// uncertainty ellipse. Nothing is returned if the ellipses are not exported.
+    fn ellipses<'a, const D: usize>(
+        &'a self,
+        track: &'a Track<D>,
+    ) -> impl Iterator<Item = (&'a Waypoint<D>, f64, Vec<(f64, f64)>)> + 'a {
+        self.uncertainty_sigma.into_iter().flat_map(move |sigma| {
+            track.iter().map(move |waypoint| {
+                let state = &waypoint.state;
+                let ring = covariance_ellipse(
+                    Vector2::new(state.estimate[0], state.estimate[1]),
+                    state.error.fixed_view::<2, 2>(0, 0).into_owned(),
+                    sigma,
+                    Self::ELLIPSE_POINTS,
+                )
+                .into_iter()
+                .map(|point| (self.to_geo)(point.x, point.y))
+                .collect();
+                (waypoint, sigma, ring)
+            })
+        })
+    }
+}
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn geojson_position((lon, lat): (f64, f64)) -> Value {
+    json!([lon, lat])
+}
+
+fn kml_coordinates(positions: impl Iterator<Item = (f64, f64)>) -> String {
+    positions
+        .map(|(lon, lat)| format!("{},{}", lon, lat))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::{Matrix4, Vector2};
+
+    use crate::export::GeoExporter;
+    use crate::sim::trajectory::{FigureEight, Kinematics, Trajectory};
+    use crate::track::Track;
+
+    /// # Returns
+    /// Returns the figure eight with a correlated position error in every waypoint.
+    fn uncertain_track() -> Track<4> {
+        let ground_truth = FigureEight::new(2., 2., 2. * PI, 10.)
+            .ground_truth(
+                Utc.timestamp_nanos(0),
+                Duration::milliseconds(100),
+                Kinematics::constant_velocity_state,
+            )
+            .unwrap();
+        let mut waypoints = ground_truth.into_iter().map(|mut waypoint| {
+            waypoint.state.error = Matrix4::from_diagonal_element(0.01);
+            waypoint.state.error[(0, 1)] = 0.005;
+            waypoint.state.error[(1, 0)] = 0.005;
+            waypoint
+        });
+        let mut track = Track::new(waypoints.next().unwrap());
+        waypoints.for_each(|waypoint| track.add_waypoint(waypoint));
+        track
+    }
+
+    /// # Returns
+    /// Returns a local approximation of the ENU frame around the base point (longitude, latitude).
+    fn to_geo(east: f64, north: f64) -> (f64, f64) {
+        let (base_lon, base_lat) = (8.4, 49.0);
+        (
+            base_lon + east / (111_320. * f64::to_radians(base_lat).cos()),
+            base_lat + north / 110_540.,
+        )
+    }
+
+    #[test]
+    fn test_geojson() {
+        let track = uncertain_track();
+        let exporter = GeoExporter::new(to_geo)
+            .with_name("test & track")
+            .with_uncertainty_ellipses(2.);
+
+        let mut geojson = Vec::new();
+        exporter.write_geojson(&track, &mut geojson).unwrap();
+        let geojson: serde_json::Value = serde_json::from_slice(&geojson).unwrap();
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), track.len() + 1);
+        let line = features[0]["geometry"]["coordinates"].as_array().unwrap();
+        assert_eq!(line.len(), track.len());
+        let latest = track.get_latest_waypoint().state.estimate;
+        let (lon, lat) = to_geo(latest[0], latest[1]);
+        assert_eq!(line[line.len() - 1][0].as_f64().unwrap(), lon);
+        assert_eq!(line[line.len() - 1][1].as_f64().unwrap(), lat);
+        for ellipse in &features[1..] {
+            assert_eq!(ellipse["geometry"]["type"], "Polygon");
+            let ring = ellipse["geometry"]["coordinates"][0].as_array().unwrap();
+            assert_eq!(ring.first(), ring.last());
+        }
+
+        // the points of the ellipse are two standard deviations away from the estimate
+        let waypoint = track.get_latest_waypoint();
+        let ring = features[features.len() - 1]["geometry"]["coordinates"][0]
+            .as_array()
+            .unwrap();
+        let position_error_inverse = waypoint
+            .state
+            .error
+            .fixed_view::<2, 2>(0, 0)
+            .try_inverse()
+            .unwrap();
+        let (base_lon, base_lat) = to_geo(0., 0.);
+        for position in ring {
+            let east = (position[0].as_f64().unwrap() - base_lon)
+                * 111_320.
+                * f64::to_radians(base_lat).cos();
+            let north = (position[1].as_f64().unwrap() - base_lat) * 110_540.;
+            let difference = Vector2::new(east, north) - waypoint.state.estimate.fixed_rows::<2>(0);
+            let mahalanobis = difference
+                .dot(&(position_error_inverse * difference))
+                .sqrt();
+            assert!((mahalanobis - 2.).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_gpx_and_kml() {
+        let track = uncertain_track();
+        let exporter = GeoExporter::new(to_geo)
+            .with_name("test & track")
+            .with_uncertainty_ellipses(2.);
+
+        let mut gpx = Vec::new();
+        exporter.write_gpx(&track, &mut gpx).unwrap();
+        let gpx = String::from_utf8(gpx).unwrap();
+        assert!(gpx.contains("<name>test &amp; track</name>"));
+        assert_eq!(gpx.matches("<time>").count(), track.len());
+        assert_eq!(gpx.matches("<trkseg>").count(), track.len() + 1);
+
+        let mut kml = Vec::new();
+        exporter.write_kml(&track, &mut kml).unwrap();
+        let kml = String::from_utf8(kml).unwrap();
+        assert_eq!(kml.matches("<LineString>").count(), 1);
+        assert_eq!(kml.matches("<Polygon>").count(), track.len());
+
+        // without the uncertainty ellipses only the line is exported
+        let mut kml = Vec::new();
+        GeoExporter::new(to_geo)
+            .write_kml(&track, &mut kml)
+            .unwrap();
+        assert!(!String::from_utf8(kml).unwrap().contains("<Polygon>"));
+    }
+}
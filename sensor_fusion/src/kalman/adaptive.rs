@@ -0,0 +1,277 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+use chrono::Duration;
+use nalgebra::SMatrix;
+
+use crate::estimator::{EstimationError, Estimator};
+use crate::kalman::estimator::KalmanFilter;
+use crate::model::{LinearMeasurementModel, LinearTransitionModel};
+use crate::state::{GaussianState, Measurement, Waypoint};
+use crate::track::Track;
+
+/// # Explanation
+/// The AdaptiveKalmanFilter wraps a KalmanFilter and estimates its measurement error (R) and the scale of
+/// its transition error (Q) online by covariance matching over a sliding window of the last updates:
+/// - R = mean(r * r^T + H * P * H^T) where r is the residual of the measurement after the update and P the
+///   filtered error.
+/// - Q is the error of the transition model multiplied with a scale. The scale is the ratio of
+///   trace(mean(dx * dx^T)) and the trace of the (unscaled) transition error, where dx is the correction
+///   of the predicted state by the measurement.
+///
+/// The models start with their own errors and are adapted as soon as the window is full. Whenever the
+/// window was filled with new updates the adapted values are logged (info level), so they can be copied
+/// into the configuration.
+pub struct AdaptiveKalmanFilter<const MD: usize, const SD: usize, TModel, MModel> {
+    filter:
+        KalmanFilter<MD, SD, ScaledTransitionModel<TModel>, AdaptedMeasurementModel<MD, MModel>>,
+    window_size: usize,
+    window: RefCell<VecDeque<Sample<MD>>>,
+    updates: Cell<usize>,
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> AdaptiveKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: LinearTransitionModel<SD>,
+    MModel: LinearMeasurementModel<MD, SD>,
+{
+    /// # Explanation
+    /// Larger windows adapt slower but are less noisy.
+    ///
+    /// # Returns
+    /// Returns the filter or EstimationError::InvalidParameter if the window size is zero.
+    pub fn new(
+        transition_model: TModel,
+        measurement_model: MModel,
+        window_size: usize,
+    ) -> Result<Self, EstimationError> {
+        if window_size == 0 {
+            return Err(EstimationError::InvalidParameter(
+                "the window of an adaptive kalman filter must contain at least one update"
+                    .to_string(),
+            ));
+        }
+
+        let measurement_error = Cell::new(measurement_model.measurement_error());
+        Ok(Self {
+            filter: KalmanFilter::new(
+                ScaledTransitionModel {
+                    model: transition_model,
+                    scale: Cell::new(1.),
+                },
+                AdaptedMeasurementModel {
+                    model: measurement_model,
+                    measurement_error,
+                },
+            ),
+            window_size,
+            window: RefCell::new(VecDeque::with_capacity(window_size + 1)),
+            updates: Cell::new(0),
+        })
+    }
+
+    /// # Returns
+    /// Returns the current (adapted) measurement error.
+    pub fn measurement_error(&self) -> SMatrix<f64, MD, MD> {
+        self.filter.measurement_model().measurement_error.get()
+    }
+
+    /// # Returns
+    /// Returns the factor the error of the transition model is currently multiplied with.
+    pub fn transition_error_scale(&self) -> f64 {
+        self.filter.transition_model().scale.get()
+    }
+
+    fn adapt(&self, window: &VecDeque<Sample<MD>>) {
+        let n = window.len() as f64;
+        let measurement_error = window
+            .iter()
+            .fold(SMatrix::<f64, MD, MD>::zeros(), |sum, sample| {
+                sum + sample.measurement_error
+            })
+            / n;
+        let correction: f64 = window.iter().map(|sample| sample.correction).sum();
+        let transition_error: f64 = window.iter().map(|sample| sample.transition_error).sum();
+
+        self.filter
+            .measurement_model()
+            .measurement_error
+            .set(measurement_error);
+        if transition_error > 0. {
+            self.filter
+                .transition_model()
+                .scale
+                .set((correction / transition_error).max(f64::EPSILON));
+        }
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> Estimator<MD, SD>
+    for AdaptiveKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: LinearTransitionModel<SD>,
+    MModel: LinearMeasurementModel<MD, SD>,
+{
+    fn estimate(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        Ok(self.estimate_waypoint(track, measurement)?.state)
+    }
+
+    fn estimate_waypoint(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<Waypoint<SD>, EstimationError> {
+        let waypoint = self.filter.estimate_waypoint(track, measurement.clone())?;
+        let prediction = waypoint
+            .prediction
+            .as_ref()
+            .ok_or(EstimationError::MissingPrediction)?;
+
+        let measurement_matrix = self.filter.measurement_model().measurement_matrix();
+        let residual = measurement.vector - measurement_matrix * waypoint.state.estimate;
+        let correction = waypoint.state.estimate - prediction.state.estimate;
+        let sample = Sample {
+            measurement_error: residual * residual.transpose()
+                + measurement_matrix * waypoint.state.error * measurement_matrix.transpose(),
+            correction: correction.norm_squared(),
+            transition_error: self
+                .filter
+                .transition_model()
+                .model
+                .transition_error(prediction.dt)
+                .trace(),
+        };
+
+        let mut window = self.window.borrow_mut();
+        window.push_back(sample);
+        while window.len() > self.window_size {
+            window.pop_front();
+        }
+        if window.len() == self.window_size {
+            self.adapt(&window);
+        }
+
+        self.updates.set(self.updates.get() + 1);
+        if self.updates.get() == self.window_size {
+            self.updates.set(0);
+            log::info!(
+                "Adapted measurement error (diagonal): {:?}, transition error scale: {}",
+                self.measurement_error().diagonal().as_slice(),
+                self.transition_error_scale()
+            );
+        }
+        Ok(waypoint)
+    }
+}
+
+/// # Explanation
+/// The values one update contributes to the adaptation.
+struct Sample<const MD: usize> {
+    measurement_error: SMatrix<f64, MD, MD>,
+    correction: f64,
+    transition_error: f64,
+}
+
+/// # Explanation
+/// The transition model whose error is multiplied with the adapted scale.
+struct ScaledTransitionModel<TModel> {
+    model: TModel,
+    scale: Cell<f64>,
+}
+
+impl<const SD: usize, TModel> LinearTransitionModel<SD> for ScaledTransitionModel<TModel>
+where
+    TModel: LinearTransitionModel<SD>,
+{
+    fn transition_matrix(&self, dt: Duration) -> SMatrix<f64, SD, SD> {
+        self.model.transition_matrix(dt)
+    }
+
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, SD, SD> {
+        self.scale.get() * self.model.transition_error(dt)
+    }
+}
+
+/// # Explanation
+/// The measurement model whose error is replaced by the adapted one.
+struct AdaptedMeasurementModel<const MD: usize, MModel> {
+    model: MModel,
+    measurement_error: Cell<SMatrix<f64, MD, MD>>,
+}
+
+impl<const MD: usize, const SD: usize, MModel> LinearMeasurementModel<MD, SD>
+    for AdaptedMeasurementModel<MD, MModel>
+where
+    MModel: LinearMeasurementModel<MD, SD>,
+{
+    fn measurement_matrix(&self) -> SMatrix<f64, MD, SD> {
+        self.model.measurement_matrix()
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, MD, MD> {
+        self.measurement_error.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::{SMatrix, SVector};
+
+    use crate::estimator::{EstimationError, Estimator};
+    use crate::kalman::adaptive::AdaptiveKalmanFilter;
+    use crate::kalman::model::{ConstantVelocity, PositionMeasurementModel};
+    use crate::state::{GaussianState, Measurement, Waypoint};
+    use crate::track::Track;
+
+    #[test]
+    fn test_window() {
+        assert!(matches!(
+            AdaptiveKalmanFilter::new(
+                ConstantVelocity::new(0.05),
+                PositionMeasurementModel::new(1.0, 1.0),
+                0,
+            ),
+            Err(EstimationError::InvalidParameter(_))
+        ));
+
+        let adaptive_filter = AdaptiveKalmanFilter::new(
+            ConstantVelocity::new(0.05),
+            PositionMeasurementModel::new(1.0, 1.0),
+            3,
+        )
+        .unwrap();
+        let start = Utc.timestamp_nanos(0);
+        let mut track = Track::new(Waypoint::new(
+            start,
+            GaussianState::new(
+                SVector::<f64, 4>::new(0., 0., 1., 0.),
+                SMatrix::<f64, 4, 4>::identity(),
+            ),
+        ));
+        for i in 1..=3 {
+            // the models keep their own errors until the window is full
+            assert_eq!(
+                adaptive_filter.measurement_error(),
+                SMatrix::<f64, 2, 2>::identity()
+            );
+            assert_eq!(adaptive_filter.transition_error_scale(), 1.);
+
+            // the measurements are exactly on the line, so the measurement error is way too large
+            let measurement = Measurement::new(
+                start + Duration::seconds(i),
+                SVector::<f64, 2>::new(i as f64, 0.),
+            );
+            let waypoint = adaptive_filter
+                .estimate_waypoint(&track, measurement)
+                .unwrap();
+            track.add_waypoint(waypoint);
+        }
+        let measurement_error = adaptive_filter.measurement_error();
+        assert!(measurement_error[(0, 0)] < 1. && measurement_error[(1, 1)] < 1.);
+    }
+}
@@ -0,0 +1,275 @@
+use chrono::Duration;
+use nalgebra::{SMatrix, SVector};
+
+use crate::model::{
+    ControlledTransitionModel, LinearMeasurementModel, NonlinearMeasurementModel,
+    NonlinearTransitionModel,
+};
+use crate::state::GaussianState;
+
+/// the index of the gps position bias (x, y) in the bias augmented state
+pub const POSITION_BIAS: usize = 5;
+/// the index of the scale factor of the optical flow sensor in the bias augmented state
+pub const SCALE: usize = 7;
+/// the index of the heading offset of the optical flow sensor in the bias augmented state
+pub const HEADING_OFFSET: usize = 8;
+
+/// # Explanation
+/// The BiasParameters describe the systematic sensor errors that the bias augmented state estimates.
+/// The errors are the variances of the initial values and the drifts are the variances of their random
+/// walks per second.
+///
+/// - position_bias: the offset of the gps positions (eg because of multipath)
+/// - scale: the factor the optical flow sensor measures the speed with (eg because of a wrong height)
+/// - heading_offset: the angle between the measured and the true direction of the velocity (eg because
+///   the compass is not aligned with the optical flow sensor)
+#[derive(Copy, Clone, Debug)]
+pub struct BiasParameters {
+    pub position_bias_error: f64,
+    pub position_bias_drift: f64,
+    pub scale_error: f64,
+    pub scale_drift: f64,
+    pub heading_offset_error: f64,
+    pub heading_offset_drift: f64,
+}
+
+impl BiasParameters {
+    /// # Returns
+    /// Returns the initial bias augmented state for an initial state of the coordinated turn model whose
+    /// position is a gps fix (like the initial position of the robot). The biases start at zero and the
+    /// scale at one.
+    ///
+    /// # Explanation
+    /// A single gps fix only tells the sum of the position and the position bias, so both are not
+    /// observable on their own. Therefore the position gets the additional error of the bias and both are
+    /// fully anti-correlated (the error of the sum stays the one of the gps fix). Without this correlation
+    /// the filter would split every later difference between the gps and the optical flow sensor between
+    /// the position and the bias instead of moving the bias. The scale and the heading offset become
+    /// observable once the robot moves, so they are independent of the rest of the state.
+    pub fn initial_state(&self, initial_state: &GaussianState<5>) -> GaussianState<9> {
+        let mut estimate = SVector::<f64, 9>::zeros();
+        estimate
+            .fixed_rows_mut::<5>(0)
+            .copy_from(&initial_state.estimate);
+        estimate[SCALE] = 1.;
+
+        let mut error = SMatrix::<f64, 9, 9>::zeros();
+        error
+            .fixed_view_mut::<5, 5>(0, 0)
+            .copy_from(&initial_state.error);
+        for i in 0..2 {
+            error[(i, i)] += self.position_bias_error;
+            error[(POSITION_BIAS + i, POSITION_BIAS + i)] = self.position_bias_error;
+            error[(i, POSITION_BIAS + i)] = -self.position_bias_error;
+            error[(POSITION_BIAS + i, i)] = -self.position_bias_error;
+        }
+        error[(SCALE, SCALE)] = self.scale_error;
+        error[(HEADING_OFFSET, HEADING_OFFSET)] = self.heading_offset_error;
+
+        GaussianState::new(estimate, error)
+    }
+
+    /// # Returns
+    /// Returns the random walk error of the biases after dt seconds (the lower right 4x4 block of the
+    /// transition error).
+    fn bias_error(&self, dt: f64) -> SMatrix<f64, 4, 4> {
+        SMatrix::<f64, 4, 4>::from_diagonal(&SVector::<f64, 4>::new(
+            self.position_bias_drift,
+            self.position_bias_drift,
+            self.scale_drift,
+            self.heading_offset_drift,
+        )) * dt
+    }
+}
+
+/// # Explanation
+/// The BiasAugmented model extends a model with the state of the coordinated turn model
+/// (x, y, v, heading, yaw rate) by the sensor biases (see BiasParameters). So the state vector consists of
+/// nine dimensions:<br>
+/// (x, y, v, heading, yaw rate, gps bias x, gps bias y, optical flow scale, optical flow heading offset)<br>
+/// The first five entries are predicted with the underlying model, the biases are random walks.
+///
+/// It works with the BiasedPositionMeasurementModel and the BiasedVelocityMeasurementModel, which apply
+/// the biases to the measurements.
+///
+/// # Type parameters
+/// - TModel: the underlying model (eg CoordinatedTurn or DifferentialDrive)
+#[derive(Copy, Clone)]
+pub struct BiasAugmented<TModel> {
+    model: TModel,
+    parameters: BiasParameters,
+}
+
+impl<TModel> BiasAugmented<TModel> {
+    pub fn new(model: TModel, parameters: BiasParameters) -> Self {
+        Self { model, parameters }
+    }
+
+    pub fn parameters(&self) -> BiasParameters {
+        self.parameters
+    }
+
+    fn augment(&self, state: &SVector<f64, 9>, base_state: SVector<f64, 5>) -> SVector<f64, 9> {
+        let mut next_state = *state;
+        next_state.fixed_rows_mut::<5>(0).copy_from(&base_state);
+        next_state
+    }
+
+    fn augment_jacobian(&self, base_jacobian: SMatrix<f64, 5, 5>) -> SMatrix<f64, 9, 9> {
+        let mut jacobian = SMatrix::<f64, 9, 9>::identity();
+        jacobian
+            .fixed_view_mut::<5, 5>(0, 0)
+            .copy_from(&base_jacobian);
+        jacobian
+    }
+
+    fn augment_error(&self, base_error: SMatrix<f64, 5, 5>, dt: Duration) -> SMatrix<f64, 9, 9> {
+        let dt = dt.num_milliseconds() as f64 / 1000.0;
+        let mut error = SMatrix::<f64, 9, 9>::zeros();
+        error.fixed_view_mut::<5, 5>(0, 0).copy_from(&base_error);
+        error
+            .fixed_view_mut::<4, 4>(5, 5)
+            .copy_from(&self.parameters.bias_error(dt));
+        error
+    }
+}
+
+impl<TModel> NonlinearTransitionModel<9> for BiasAugmented<TModel>
+where
+    TModel: NonlinearTransitionModel<5>,
+{
+    fn transition(&self, state: &SVector<f64, 9>, dt: Duration) -> SVector<f64, 9> {
+        let base_state = self
+            .model
+            .transition(&state.fixed_rows::<5>(0).into_owned(), dt);
+        self.augment(state, base_state)
+    }
+
+    fn transition_jacobian(&self, state: &SVector<f64, 9>, dt: Duration) -> SMatrix<f64, 9, 9> {
+        self.augment_jacobian(
+            self.model
+                .transition_jacobian(&state.fixed_rows::<5>(0).into_owned(), dt),
+        )
+    }
+
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, 9, 9> {
+        self.augment_error(self.model.transition_error(dt), dt)
+    }
+}
+
+impl<TModel, const CD: usize> ControlledTransitionModel<9, CD> for BiasAugmented<TModel>
+where
+    TModel: ControlledTransitionModel<5, CD>,
+{
+    fn transition(
+        &self,
+        state: &SVector<f64, 9>,
+        control: &SVector<f64, CD>,
+        dt: Duration,
+    ) -> SVector<f64, 9> {
+        let base_state = self
+            .model
+            .transition(&state.fixed_rows::<5>(0).into_owned(), control, dt);
+        self.augment(state, base_state)
+    }
+
+    fn transition_jacobian(
+        &self,
+        state: &SVector<f64, 9>,
+        control: &SVector<f64, CD>,
+        dt: Duration,
+    ) -> SMatrix<f64, 9, 9> {
+        self.augment_jacobian(self.model.transition_jacobian(
+            &state.fixed_rows::<5>(0).into_owned(),
+            control,
+            dt,
+        ))
+    }
+
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, 9, 9> {
+        self.augment_error(self.model.transition_error(dt), dt)
+    }
+}
+
+/// # Explanation
+/// The BiasedPositionMeasurementModel measures the position plus the gps bias of the bias augmented state
+/// (x + bias x, y + bias y).
+///
+/// # Parameters
+/// The error_x parameter represents the uncertainty in the x-axis.
+/// The error_y parameter represents the uncertainty in the y-axis.
+#[derive(Copy, Clone)]
+pub struct BiasedPositionMeasurementModel {
+    error_x: f64,
+    error_y: f64,
+}
+
+impl BiasedPositionMeasurementModel {
+    pub fn new(error_x: f64, error_y: f64) -> Self {
+        Self { error_x, error_y }
+    }
+}
+
+impl LinearMeasurementModel<2, 9> for BiasedPositionMeasurementModel {
+    /// # Returns
+    /// | 1.  0.  0.  0.  0.  1.  0.  0.  0. |<br>
+    /// | 0.  1.  0.  0.  0.  0.  1.  0.  0. |<br>
+    fn measurement_matrix(&self) -> SMatrix<f64, 2, 9> {
+        let mut measurement_matrix = SMatrix::<f64, 2, 9>::zeros();
+        measurement_matrix[(0, 0)] = 1.;
+        measurement_matrix[(1, 1)] = 1.;
+        measurement_matrix[(0, POSITION_BIAS)] = 1.;
+        measurement_matrix[(1, POSITION_BIAS + 1)] = 1.;
+        measurement_matrix
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, 2, 2> {
+        SMatrix::<f64, 2, 2>::new(self.error_x, 0., 0., self.error_y)
+    }
+}
+
+/// # Explanation
+/// The BiasedVelocityMeasurementModel measures the velocity (vx, vy) like the optical flow sensor with its
+/// scale factor s and heading offset o from the bias augmented state:<br>
+/// vx = s * v * cos(heading + o)<br>
+/// vy = s * v * sin(heading + o)
+///
+/// # Parameters
+/// The velocity_error parameter represents the uncertainty of the velocity (in both axes).
+#[derive(Copy, Clone)]
+pub struct BiasedVelocityMeasurementModel {
+    velocity_error: f64,
+}
+
+impl BiasedVelocityMeasurementModel {
+    pub fn new(velocity_error: f64) -> Self {
+        Self { velocity_error }
+    }
+}
+
+impl NonlinearMeasurementModel<2, 9> for BiasedVelocityMeasurementModel {
+    fn measure(&self, state: &SVector<f64, 9>) -> SVector<f64, 2> {
+        let (v, heading) = (state[2], state[3] + state[HEADING_OFFSET]);
+        state[SCALE] * SVector::<f64, 2>::new(v * heading.cos(), v * heading.sin())
+    }
+
+    fn measurement_jacobian(&self, state: &SVector<f64, 9>) -> SMatrix<f64, 2, 9> {
+        let (v, scale) = (state[2], state[SCALE]);
+        let (sin, cos) = (state[3] + state[HEADING_OFFSET]).sin_cos();
+
+        let mut jacobian = SMatrix::<f64, 2, 9>::zeros();
+        jacobian[(0, 2)] = scale * cos;
+        jacobian[(1, 2)] = scale * sin;
+        jacobian[(0, 3)] = -scale * v * sin;
+        jacobian[(1, 3)] = scale * v * cos;
+        jacobian[(0, SCALE)] = v * cos;
+        jacobian[(1, SCALE)] = v * sin;
+        jacobian[(0, HEADING_OFFSET)] = -scale * v * sin;
+        jacobian[(1, HEADING_OFFSET)] = scale * v * cos;
+        jacobian
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, 2, 2> {
+        self.velocity_error * SMatrix::<f64, 2, 2>::identity()
+    }
+}
@@ -0,0 +1,81 @@
+use std::f64::consts::PI;
+
+use nalgebra::{SMatrix, SVector};
+
+use crate::estimator::{time_since_latest_waypoint, EstimationError, Filter, Predictor};
+use crate::linalg::to_dynamic;
+use crate::state::{GaussianState, Measurement, Prediction, Waypoint};
+use crate::track::Track;
+
+/// # Explanation
+/// The UpdateDiagnostics describe how a kalman filter processed one measurement. They show whether the
+/// filter is over- or under-confident: for a consistent filter the NIS is chi-square distributed with MD
+/// degrees of freedom (see evaluation::ConsistencyStatistics).
+///
+/// - innovation: the difference between the measurement and the predicted measurement
+/// - innovation_error: the covariance of the innovation (S)
+/// - kalman_gain: the gain the innovation was multiplied with (K)
+/// - nis: the normalized innovation squared (innovation^T * S^-1 * innovation)
+/// - log_likelihood: the log likelihood of the measurement given the prediction
+#[derive(Debug, Clone)]
+pub struct UpdateDiagnostics<const MD: usize, const SD: usize> {
+    pub innovation: SVector<f64, MD>,
+    pub innovation_error: SMatrix<f64, MD, MD>,
+    pub kalman_gain: SMatrix<f64, SD, MD>,
+    pub nis: f64,
+    pub log_likelihood: f64,
+}
+
+impl<const MD: usize, const SD: usize> UpdateDiagnostics<MD, SD> {
+    pub(crate) fn new(
+        innovation: SVector<f64, MD>,
+        innovation_error: SMatrix<f64, MD, MD>,
+        innovation_error_inverse: &SMatrix<f64, MD, MD>,
+        kalman_gain: SMatrix<f64, SD, MD>,
+    ) -> Self {
+        let nis = innovation.dot(&(innovation_error_inverse * innovation));
+        let log_determinant = to_dynamic(&innovation_error).determinant().ln();
+        let log_likelihood = -0.5 * (nis + log_determinant + MD as f64 * (2. * PI).ln());
+        Self {
+            innovation,
+            innovation_error,
+            kalman_gain,
+            nis,
+            log_likelihood,
+        }
+    }
+}
+
+/// # Explanation
+/// A DiagnosticFilter is a filter that can also report the UpdateDiagnostics of an update. The diagnostics
+/// are only an additional output, the filtered state is the same as the one of Filter::filter.
+pub trait DiagnosticFilter<const MD: usize, const SD: usize>: Filter<MD, SD> {
+    fn filter_with_diagnostics(
+        &self,
+        prediction: GaussianState<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<(GaussianState<SD>, UpdateDiagnostics<MD, SD>), EstimationError>;
+
+    /// # Returns
+    /// Returns the same waypoint as Estimator::estimate_waypoint together with the diagnostics of the
+    /// update. (The method is not available for trait objects, so that eg boxed ModeFilters can be used.)
+    fn estimate_waypoint_with_diagnostics(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<(Waypoint<SD>, UpdateDiagnostics<MD, SD>), EstimationError>
+    where
+        Self: Predictor<SD> + Sized,
+    {
+        let timestamp = measurement.timestamp;
+        let dt = time_since_latest_waypoint(track, timestamp)?;
+        let prediction = self.predict(track, dt)?;
+        let (filtered, diagnostics) =
+            self.filter_with_diagnostics(prediction.clone(), measurement)?;
+        filtered.validate()?;
+        Ok((
+            Waypoint::with_prediction(timestamp, filtered, Prediction::new(dt, prediction)),
+            diagnostics,
+        ))
+    }
+}
@@ -0,0 +1,155 @@
+use chrono::Duration;
+
+use crate::estimator::{EstimationError, Filter, Predictor};
+use crate::kalman::diagnostics::{DiagnosticFilter, UpdateDiagnostics};
+use crate::kalman::outlier::{robust_innovation_error, OutlierGate, OutlierHandling};
+use crate::model::{NonlinearMeasurementModel, NonlinearTransitionModel};
+use crate::state::{GaussianState, Measurement};
+use crate::track::Track;
+
+/// # Explanation
+/// The ExtendedKalmanFilter works like the KalmanFilter but with nonlinear models. The state is
+/// propagated through the nonlinear functions while the error is propagated through their jacobians
+/// (which are evaluated at the current estimate).
+pub struct ExtendedKalmanFilter<const MD: usize, const SD: usize, TModel, MModel> {
+    transition_model: TModel,
+    measurement_model: MModel,
+    outlier_gate: OutlierGate,
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> ExtendedKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    pub fn new(transition_model: TModel, measurement_model: MModel) -> Self {
+        Self {
+            transition_model,
+            measurement_model,
+            outlier_gate: OutlierGate::new(OutlierHandling::AcceptAll, MD),
+        }
+    }
+
+    pub fn with_outlier_handling(mut self, outlier_handling: OutlierHandling) -> Self {
+        self.outlier_gate = OutlierGate::new(outlier_handling, MD);
+        self
+    }
+
+    pub fn transition_model(&self) -> &TModel {
+        &self.transition_model
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> Predictor<SD>
+    for ExtendedKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    fn predict(
+        &self,
+        track: &Track<SD>,
+        dt: Duration,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        Ok(predict_linearized(
+            &self.transition_model,
+            &track.get_latest_waypoint().state,
+            dt,
+        ))
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> Filter<MD, SD>
+    for ExtendedKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    fn filter(
+        &self,
+        prediction: GaussianState<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        self.filter_with_diagnostics(prediction, measurement)
+            .map(|(filtered, _)| filtered)
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> DiagnosticFilter<MD, SD>
+    for ExtendedKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    fn filter_with_diagnostics(
+        &self,
+        prediction: GaussianState<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<(GaussianState<SD>, UpdateDiagnostics<MD, SD>), EstimationError> {
+        update_linearized(
+            &self.measurement_model,
+            &self.outlier_gate,
+            prediction,
+            measurement,
+        )
+    }
+}
+
+/// # Returns
+/// Returns the prior propagated through the transition model. The error is propagated through the
+/// jacobian of the transition model (evaluated at the estimate of the prior).
+pub(crate) fn predict_linearized<const SD: usize, TModel>(
+    transition_model: &TModel,
+    prior: &GaussianState<SD>,
+    dt: Duration,
+) -> GaussianState<SD>
+where
+    TModel: NonlinearTransitionModel<SD>,
+{
+    let transition_jacobian = transition_model.transition_jacobian(&prior.estimate, dt);
+    let transition_error = transition_model.transition_error(dt);
+
+    GaussianState::new(
+        transition_model.transition(&prior.estimate, dt),
+        transition_jacobian * prior.error * transition_jacobian.transpose() + transition_error,
+    )
+}
+
+/// # Returns
+/// Returns the prediction corrected by the measurement and the diagnostics of the update. The
+/// measurement model is linearized at the estimate of the prediction.
+pub(crate) fn update_linearized<const MD: usize, const SD: usize, MModel>(
+    measurement_model: &MModel,
+    outlier_gate: &OutlierGate,
+    prediction: GaussianState<SD>,
+    measurement: Measurement<MD>,
+) -> Result<(GaussianState<SD>, UpdateDiagnostics<MD, SD>), EstimationError>
+where
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    let measurement_jacobian = measurement_model.measurement_jacobian(&prediction.estimate);
+    let measurement_error = measurement_model.measurement_error();
+
+    let innovation = measurement.vector - measurement_model.measure(&prediction.estimate);
+    let (innovation_error, innovation_error_inverse) = robust_innovation_error(
+        outlier_gate,
+        &innovation,
+        measurement_jacobian * prediction.error * measurement_jacobian.transpose(),
+        measurement_error,
+    )?;
+
+    let kalman_gain =
+        prediction.error * measurement_jacobian.transpose() * innovation_error_inverse;
+
+    let filtered_estimate = prediction.estimate + kalman_gain * innovation;
+    let filter_error = prediction.error - kalman_gain * innovation_error * kalman_gain.transpose();
+    Ok((
+        GaussianState::new(filtered_estimate, filter_error),
+        UpdateDiagnostics::new(
+            innovation,
+            innovation_error,
+            &innovation_error_inverse,
+            kalman_gain,
+        ),
+    ))
+}
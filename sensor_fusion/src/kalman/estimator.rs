@@ -1,13 +1,31 @@
 use chrono::Duration;
+use nalgebra::SMatrix;
+use serde::{Deserialize, Serialize};
 
 use crate::estimator::{EstimationError, Filter, Predictor};
+use crate::kalman::diagnostics::{DiagnosticFilter, UpdateDiagnostics};
+use crate::kalman::outlier::{robust_innovation_error, OutlierGate, OutlierHandling};
 use crate::model::{LinearMeasurementModel, LinearTransitionModel};
 use crate::state::{GaussianState, Measurement};
 use crate::track::Track;
 
+/// # Explanation
+/// The CovarianceUpdate decides how the KalmanFilter computes the error of the filtered state.
+/// Standard uses P - K S K^T. Joseph uses (I - K H) P (I - K H)^T + K R K^T, which is more expensive
+/// but keeps the error symmetric and positive semi-definite despite rounding errors.
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CovarianceUpdate {
+    #[default]
+    Standard,
+    Joseph,
+}
+
 pub struct KalmanFilter<const MD: usize, const SD: usize, TModel, MModel> {
     transition_model: TModel,
     measurement_model: MModel,
+    outlier_gate: OutlierGate,
+    covariance_update: CovarianceUpdate,
 }
 
 impl<const MD: usize, const SD: usize, TModel, MModel> KalmanFilter<MD, SD, TModel, MModel>
@@ -19,8 +37,31 @@ where
         Self {
             transition_model,
             measurement_model,
+            outlier_gate: OutlierGate::new(OutlierHandling::AcceptAll, MD),
+            covariance_update: CovarianceUpdate::Standard,
         }
     }
+
+    /// # Explanation
+    /// Sets how measurements that do not fit the prediction are treated (by default every measurement
+    /// is accepted).
+    pub fn with_outlier_handling(mut self, outlier_handling: OutlierHandling) -> Self {
+        self.outlier_gate = OutlierGate::new(outlier_handling, MD);
+        self
+    }
+
+    pub fn with_covariance_update(mut self, covariance_update: CovarianceUpdate) -> Self {
+        self.covariance_update = covariance_update;
+        self
+    }
+
+    pub fn transition_model(&self) -> &TModel {
+        &self.transition_model
+    }
+
+    pub fn measurement_model(&self) -> &MModel {
+        &self.measurement_model
+    }
 }
 
 impl<const MD: usize, const SD: usize, TModel, MModel> Predictor<SD>
@@ -56,23 +97,60 @@ where
         prediction: GaussianState<SD>,
         measurement: Measurement<MD>,
     ) -> Result<GaussianState<SD>, EstimationError> {
+        self.filter_with_diagnostics(prediction, measurement)
+            .map(|(filtered, _)| filtered)
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> DiagnosticFilter<MD, SD>
+    for KalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: LinearTransitionModel<SD>,
+    MModel: LinearMeasurementModel<MD, SD>,
+{
+    fn filter_with_diagnostics(
+        &self,
+        prediction: GaussianState<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<(GaussianState<SD>, UpdateDiagnostics<MD, SD>), EstimationError> {
         let measurement_matrix = self.measurement_model.measurement_matrix();
         let measurement_error = self.measurement_model.measurement_error();
 
         let innovation = measurement.vector - measurement_matrix * prediction.estimate;
-        let innovation_error =
-            measurement_matrix * prediction.error * measurement_matrix.transpose()
-                + measurement_error;
-        let innovation_error_inverse = innovation_error
-            .try_inverse()
-            .ok_or(EstimationError::NumericalError)?;
+        let predicted_measurement_error =
+            measurement_matrix * prediction.error * measurement_matrix.transpose();
+        let (innovation_error, innovation_error_inverse) = robust_innovation_error(
+            &self.outlier_gate,
+            &innovation,
+            predicted_measurement_error,
+            measurement_error,
+        )?;
 
         let kalman_gain =
             prediction.error * measurement_matrix.transpose() * innovation_error_inverse;
 
         let filtered_estimate = prediction.estimate + kalman_gain * innovation;
-        let filter_error =
-            prediction.error - kalman_gain * innovation_error * kalman_gain.transpose();
-        Ok(GaussianState::new(filtered_estimate, filter_error))
+        let filter_error = match self.covariance_update {
+            CovarianceUpdate::Standard => {
+                prediction.error - kalman_gain * innovation_error * kalman_gain.transpose()
+            }
+            CovarianceUpdate::Joseph => {
+                // the measurement error might have been scaled by the outlier handling
+                let measurement_error = innovation_error - predicted_measurement_error;
+                let correction =
+                    SMatrix::<f64, SD, SD>::identity() - kalman_gain * measurement_matrix;
+                correction * prediction.error * correction.transpose()
+                    + kalman_gain * measurement_error * kalman_gain.transpose()
+            }
+        };
+        Ok((
+            GaussianState::new(filtered_estimate, filter_error),
+            UpdateDiagnostics::new(
+                innovation,
+                innovation_error,
+                &innovation_error_inverse,
+                kalman_gain,
+            ),
+        ))
     }
 }
@@ -0,0 +1,380 @@
+use std::cell::RefCell;
+
+use chrono::{DateTime, Duration, Utc};
+use nalgebra::{DMatrix, SMatrix, SVector};
+
+use crate::estimator::{time_since_latest_waypoint, EstimationError, Estimator, Predictor};
+use crate::kalman::diagnostics::DiagnosticFilter;
+use crate::linalg::check_dimension;
+use crate::state::{GaussianState, Measurement, Waypoint};
+use crate::track::Track;
+
+/// # Explanation
+/// A ModeFilter is a filter for one mode (eg standing still or driving straight) of an
+/// InteractingMultipleModel estimator. Every Predictor that is also a DiagnosticFilter is a ModeFilter
+/// (the diagnostics contain the likelihood of the measurement under the mode).
+pub trait ModeFilter<const MD: usize, const SD: usize>:
+    Predictor<SD> + DiagnosticFilter<MD, SD>
+{
+}
+
+impl<const MD: usize, const SD: usize, T> ModeFilter<MD, SD> for T where
+    T: Predictor<SD> + DiagnosticFilter<MD, SD>
+{
+}
+
+/// # Explanation
+/// The InteractingMultipleModel estimator runs one filter per mode of motion and mixes their estimates
+/// according to the probability of every mode. The switching matrix contains the probability that the
+/// object switches from mode i (row) to mode j (column) between two measurements, so every row must sum
+/// up to one.
+///
+/// All filters must use the same state vector. How likely a measurement is under a mode is determined by
+/// the measurement model of the filter of that mode. The mode probabilities are stored in the waypoints
+/// (Waypoint::mode_probabilities) and taken from the latest waypoint of the track; a waypoint without them
+/// uses the initial probabilities. The states of the modes are kept by the estimator as long as they belong
+/// to the latest waypoint of the track (same timestamp and state), otherwise every mode starts from the
+/// state of the latest waypoint. A failed estimate does not change the states of the modes.
+pub struct InteractingMultipleModel<const MD: usize, const SD: usize> {
+    filters: Vec<Box<dyn ModeFilter<MD, SD>>>,
+    switching_matrix: DMatrix<f64>,
+    initial_probabilities: Vec<f64>,
+    modes: RefCell<Option<ModeStates<SD>>>,
+}
+
+/// # Explanation
+/// The states of the modes that belong to the waypoint with the given timestamp and (combined) state.
+struct ModeStates<const SD: usize> {
+    timestamp: DateTime<Utc>,
+    state: GaussianState<SD>,
+    states: Vec<GaussianState<SD>>,
+}
+
+impl<const SD: usize> ModeStates<SD> {
+    fn belongs_to(&self, waypoint: &Waypoint<SD>) -> bool {
+        self.timestamp == waypoint.timestamp
+            && self.state.estimate == waypoint.state.estimate
+            && self.state.error == waypoint.state.error
+    }
+}
+
+impl<const MD: usize, const SD: usize> InteractingMultipleModel<MD, SD> {
+    /// # Explanation
+    /// All modes start with the same probability.
+    pub fn new(
+        filters: Vec<Box<dyn ModeFilter<MD, SD>>>,
+        switching_matrix: DMatrix<f64>,
+    ) -> Result<Self, EstimationError> {
+        let initial_probabilities = vec![1. / filters.len() as f64; filters.len()];
+        Self::with_initial_probabilities(filters, switching_matrix, initial_probabilities)
+    }
+
+    /// # Returns
+    /// Returns the estimator or EstimationError::DimensionMismatch if the switching matrix is not a n x n
+    /// matrix or there are not n initial probabilities (with n being the number of filters).
+    /// EstimationError::InvalidParameter is returned if a probability is negative or if a row of the
+    /// switching matrix or the initial probabilities do not sum up to one.
+    pub fn with_initial_probabilities(
+        filters: Vec<Box<dyn ModeFilter<MD, SD>>>,
+        switching_matrix: DMatrix<f64>,
+        initial_probabilities: Vec<f64>,
+    ) -> Result<Self, EstimationError> {
+        if filters.is_empty() {
+            return Err(EstimationError::InvalidParameter(
+                "an interacting multiple model needs at least one filter".to_string(),
+            ));
+        }
+        check_dimension(filters.len(), switching_matrix.nrows())?;
+        check_dimension(filters.len(), switching_matrix.ncols())?;
+        check_dimension(filters.len(), initial_probabilities.len())?;
+        for (i, row) in switching_matrix.row_iter().enumerate() {
+            let row: Vec<f64> = row.iter().cloned().collect();
+            check_probabilities(&row, &format!("row {} of the switching matrix", i))?;
+        }
+        check_probabilities(&initial_probabilities, "the initial distribution")?;
+
+        Ok(Self {
+            filters,
+            switching_matrix,
+            initial_probabilities,
+            modes: RefCell::new(None),
+        })
+    }
+
+    /// # Returns
+    /// Returns the states and probabilities of the modes at the latest waypoint of the track (see
+    /// InteractingMultipleModel). EstimationError::DimensionMismatch is returned if the waypoint contains
+    /// the probabilities of another number of modes.
+    fn modes_of(
+        &self,
+        waypoint: &Waypoint<SD>,
+    ) -> Result<Vec<(GaussianState<SD>, f64)>, EstimationError> {
+        let probabilities = match &waypoint.mode_probabilities {
+            Some(probabilities) => {
+                check_dimension(self.filters.len(), probabilities.len())?;
+                probabilities.clone()
+            }
+            None => self.initial_probabilities.clone(),
+        };
+        let states = match self.modes.borrow().as_ref() {
+            Some(modes) if modes.belongs_to(waypoint) => modes.states.clone(),
+            _ => vec![waypoint.state.clone(); self.filters.len()],
+        };
+        Ok(states.into_iter().zip(probabilities).collect())
+    }
+
+    /// # Returns
+    /// Returns the initial state of every mode. This is the mixture of all modes weighted with the
+    /// probability that the object was in that mode and then switched to the mode.
+    /// EstimationError::NumericalError is returned if no mode can switch to a mode (its normalizer is
+    /// zero).
+    fn mix(
+        &self,
+        modes: &[(GaussianState<SD>, f64)],
+    ) -> Result<Vec<(GaussianState<SD>, f64)>, EstimationError> {
+        (0..modes.len())
+            .map(|j| {
+                let weights: Vec<f64> = modes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, probability))| self.switching_matrix[(i, j)] * probability)
+                    .collect();
+                let normalizer: f64 = weights.iter().sum();
+                if !normalizer.is_normal() {
+                    return Err(EstimationError::NumericalError(format!(
+                        "the mixing normalizer of mode {} is {}",
+                        j, normalizer
+                    )));
+                }
+                let mixing_weights: Vec<f64> =
+                    weights.iter().map(|weight| weight / normalizer).collect();
+                let states: Vec<&GaussianState<SD>> =
+                    modes.iter().map(|(state, _)| state).collect();
+
+                Ok((combine(&states, &mixing_weights), normalizer))
+            })
+            .collect()
+    }
+
+    fn step(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+        dt: Duration,
+    ) -> Result<Vec<(GaussianState<SD>, f64)>, EstimationError> {
+        let latest_waypoint = track.get_latest_waypoint();
+        let modes = self.modes_of(latest_waypoint)?;
+
+        let mixed = self.mix(&modes)?;
+        let mut log_likelihoods = Vec::with_capacity(modes.len());
+        let mut filtered_states = Vec::with_capacity(modes.len());
+        for (filter, (mixed_state, _)) in self.filters.iter().zip(mixed.iter().cloned()) {
+            let mode_track = Track::new(Waypoint::new(latest_waypoint.timestamp, mixed_state));
+            let prediction = filter.predict(&mode_track, dt)?;
+            let (filtered, diagnostics) =
+                filter.filter_with_diagnostics(prediction, measurement.clone())?;
+            log_likelihoods.push(diagnostics.log_likelihood);
+            filtered_states.push(filtered);
+        }
+
+        let max_log_likelihood = log_likelihoods.iter().cloned().fold(f64::MIN, f64::max);
+        let weights: Vec<f64> = log_likelihoods
+            .iter()
+            .zip(mixed.iter().map(|(_, normalizer)| *normalizer))
+            .map(|(log_likelihood, probability)| {
+                (log_likelihood - max_log_likelihood).exp() * probability
+            })
+            .collect();
+        let weight_sum: f64 = weights.iter().sum();
+        if !weight_sum.is_normal() {
+            return Err(EstimationError::NumericalError(
+                "the likelihoods of all modes are zero".to_string(),
+            ));
+        }
+
+        Ok(filtered_states
+            .into_iter()
+            .zip(weights)
+            .map(|(state, weight)| (state, weight / weight_sum))
+            .collect())
+    }
+}
+
+impl<const MD: usize, const SD: usize> Estimator<MD, SD> for InteractingMultipleModel<MD, SD> {
+    fn estimate(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        Ok(self.estimate_waypoint(track, measurement)?.state)
+    }
+
+    fn estimate_waypoint(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<Waypoint<SD>, EstimationError> {
+        let timestamp = measurement.timestamp;
+        let dt = time_since_latest_waypoint(track, timestamp)?;
+        let modes = self.step(track, measurement, dt)?;
+
+        let (states, probabilities): (Vec<GaussianState<SD>>, Vec<f64>) = modes.into_iter().unzip();
+        let state = combine(&states.iter().collect::<Vec<_>>(), &probabilities);
+        let mut waypoint = Waypoint::new(timestamp, state.clone());
+        waypoint.mode_probabilities = Some(probabilities);
+
+        self.modes.replace(Some(ModeStates {
+            timestamp,
+            state,
+            states,
+        }));
+        Ok(waypoint)
+    }
+}
+
+/// # Returns
+/// Returns EstimationError::InvalidParameter if a probability is negative or the probabilities do not sum
+/// up to one (with a tolerance for rounding errors).
+fn check_probabilities(probabilities: &[f64], name: &str) -> Result<(), EstimationError> {
+    const TOLERANCE: f64 = 1e-6;
+
+    if probabilities
+        .iter()
+        .any(|probability| probability.is_nan() || *probability < 0.)
+    {
+        return Err(EstimationError::InvalidParameter(format!(
+            "the probabilities in {} must not be negative",
+            name
+        )));
+    }
+    let sum: f64 = probabilities.iter().sum();
+    if (sum - 1.).abs() > TOLERANCE {
+        return Err(EstimationError::InvalidParameter(format!(
+            "the probabilities in {} sum up to {} instead of one",
+            name, sum
+        )));
+    }
+    Ok(())
+}
+
+/// # Returns
+/// Returns the gaussian state that has the same mean and covariance as the weighted mixture of the states.
+fn combine<const SD: usize>(states: &[&GaussianState<SD>], weights: &[f64]) -> GaussianState<SD> {
+    let estimate = states
+        .iter()
+        .zip(weights)
+        .fold(SVector::<f64, SD>::zeros(), |estimate, (state, weight)| {
+            estimate + *weight * state.estimate
+        });
+    let error = states.iter().zip(weights).fold(
+        SMatrix::<f64, SD, SD>::zeros(),
+        |error, (state, weight)| {
+            let diff = state.estimate - estimate;
+            error + *weight * (state.error + diff * diff.transpose())
+        },
+    );
+    GaussianState::new(estimate, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use nalgebra::{DMatrix, SMatrix, SVector};
+
+    use crate::estimator::{EstimationError, Estimator};
+    use crate::kalman::estimator::KalmanFilter;
+    use crate::kalman::imm::{InteractingMultipleModel, ModeFilter};
+    use crate::kalman::model::{ConstantVelocity, PositionMeasurementModel};
+    use crate::state::{GaussianState, Measurement, Waypoint};
+    use crate::track::Track;
+
+    fn filters() -> Vec<Box<dyn ModeFilter<2, 4>>> {
+        vec![
+            Box::new(KalmanFilter::new(
+                ConstantVelocity::new(0.05),
+                PositionMeasurementModel::new(0.1, 0.1),
+            )),
+            Box::new(KalmanFilter::new(
+                ConstantVelocity::new(0.5),
+                PositionMeasurementModel::new(0.1, 0.1),
+            )),
+        ]
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        // the switching matrix has to have a row and a column for every filter
+        assert!(matches!(
+            InteractingMultipleModel::new(filters(), DMatrix::identity(3, 3)),
+            Err(EstimationError::DimensionMismatch { .. })
+        ));
+
+        // the rows of the switching matrix and the initial probabilities must be probability distributions
+        let invalid_matrices = [
+            DMatrix::from_row_slice(2, 2, &[0.9, 0.2, 0.1, 0.9]),
+            DMatrix::from_row_slice(2, 2, &[1.1, -0.1, 0.1, 0.9]),
+        ];
+        for switching_matrix in invalid_matrices {
+            assert!(matches!(
+                InteractingMultipleModel::new(filters(), switching_matrix),
+                Err(EstimationError::InvalidParameter(_))
+            ));
+        }
+        assert!(matches!(
+            InteractingMultipleModel::with_initial_probabilities(
+                filters(),
+                DMatrix::identity(2, 2),
+                vec![0.5, 0.6],
+            ),
+            Err(EstimationError::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_mode_probabilities_of_waypoint() {
+        let imm = InteractingMultipleModel::new(
+            filters(),
+            DMatrix::from_row_slice(2, 2, &[0.9, 0.1, 0.1, 0.9]),
+        )
+        .unwrap();
+        let track_with = |mode_probabilities: Vec<f64>| {
+            let mut waypoint = Waypoint::new(
+                Utc.timestamp_nanos(0),
+                GaussianState::new(
+                    SVector::<f64, 4>::new(0., 0., 1., 0.),
+                    SMatrix::<f64, 4, 4>::identity(),
+                ),
+            );
+            waypoint.mode_probabilities = Some(mode_probabilities);
+            Track::new(waypoint)
+        };
+        let measurement = Measurement::new(
+            Utc.timestamp_nanos(1_000_000_000),
+            SVector::<f64, 2>::new(2., 0.5),
+        );
+
+        // the mode probabilities are taken from the latest waypoint of the track
+        let slow = imm
+            .estimate_waypoint(&track_with(vec![1., 0.]), measurement.clone())
+            .unwrap();
+        let fast = imm
+            .estimate_waypoint(&track_with(vec![0., 1.]), measurement.clone())
+            .unwrap();
+        assert!((slow.state.estimate - fast.state.estimate).norm() > 1e-3);
+        for waypoint in [&slow, &fast] {
+            let mode_probabilities = waypoint.mode_probabilities.as_ref().unwrap();
+            assert_eq!(mode_probabilities.len(), 2);
+            assert!((mode_probabilities.iter().sum::<f64>() - 1.).abs() < 1e-9);
+        }
+
+        // estimating the same measurement again gives the same result
+        let again = imm
+            .estimate(&track_with(vec![1., 0.]), measurement.clone())
+            .unwrap();
+        assert_eq!(slow.state.estimate, again.estimate);
+        assert!(matches!(
+            imm.estimate(&track_with(vec![1.]), measurement),
+            Err(EstimationError::DimensionMismatch { .. })
+        ));
+    }
+}
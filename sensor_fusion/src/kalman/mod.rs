@@ -1,2 +1,12 @@
+pub mod adaptive;
+pub mod bias;
+pub mod diagnostics;
+pub mod ekf;
 pub mod estimator;
-pub mod model;
\ No newline at end of file
+pub mod imm;
+pub mod model;
+pub mod outlier;
+pub mod sequential;
+pub mod smoother;
+pub mod square_root;
+pub mod ukf;
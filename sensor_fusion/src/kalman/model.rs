@@ -1,7 +1,10 @@
 use chrono::Duration;
 use nalgebra::{SMatrix, SVector};
 
-use crate::model::{LinearMeasurementModel, LinearTransitionModel};
+use crate::model::{
+    ControlledTransitionModel, LinearMeasurementModel, LinearTransitionModel,
+    NonlinearMeasurementModel, NonlinearTransitionModel,
+};
 
 /// # Explanation
 /// The constant velocity transition model assumes that the object moves with a constant velocity.
@@ -49,6 +52,345 @@ impl LinearTransitionModel<4> for ConstantVelocity {
     }
 }
 
+/// # Explanation
+/// The constant acceleration transition model assumes that the object moves with a constant acceleration.
+/// The state vector consists of six dimensions (x, y, vx, vy, ax, ay).
+#[derive(Copy, Clone)]
+pub struct ConstantAcceleration {
+    drift: f64,
+}
+
+impl ConstantAcceleration {
+    pub fn new(drift: f64) -> Self {
+        Self { drift }
+    }
+}
+
+impl LinearTransitionModel<6> for ConstantAcceleration {
+    /// # Returns
+    /// | 1.  0.  dt  0.  dt^2/2       0 |<br>
+    /// | 0.  1.  0.  dt       0  dt^2/2 |<br>
+    /// | 0.  0.  1.  0.      dt       0 |<br>
+    /// | 0.  0.  0.  1.       0      dt |<br>
+    /// | 0.  0.  0.  0.       1       0 |<br>
+    /// | 0.  0.  0.  0.       0       1 |<br>
+    fn transition_matrix(&self, dt: Duration) -> SMatrix<f64, 6, 6> {
+        let dt = dt.num_milliseconds() as f64 / 1000.0;
+        let pow2 = dt.powi(2) / 2.;
+        SMatrix::<f64, 6, 6>::from_row_slice(&[
+            1., 0., dt, 0., pow2, 0., //
+            0., 1., 0., dt, 0., pow2, //
+            0., 0., 1., 0., dt, 0., //
+            0., 0., 0., 1., 0., dt, //
+            0., 0., 0., 0., 1., 0., //
+            0., 0., 0., 0., 0., 1.,
+        ])
+    }
+
+    /// # Returns
+    /// The error matrix of the discrete wiener process acceleration model (the acceleration increment is
+    /// white noise):<br>
+    /// q *<br>
+    /// | dt^4/4       0  dt^3/2       0  dt^2/2       0 |<br>
+    /// |      0  dt^4/4       0  dt^3/2       0  dt^2/2 |<br>
+    /// | dt^3/2       0    dt^2       0      dt       0 |<br>
+    /// |      0  dt^3/2       0    dt^2       0      dt |<br>
+    /// | dt^2/2       0      dt       0       1       0 |<br>
+    /// |      0  dt^2/2       0      dt       0       1 |<br>
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, 6, 6> {
+        let dt = dt.num_milliseconds() as f64 / 1000.0;
+        let pow4 = dt.powi(4) / 4.;
+        let pow3 = dt.powi(3) / 2.;
+        let pow2 = dt.powi(2);
+        let half_pow2 = dt.powi(2) / 2.;
+
+        self.drift
+            * SMatrix::<f64, 6, 6>::from_row_slice(&[
+                pow4, 0., pow3, 0., half_pow2, 0., //
+                0., pow4, 0., pow3, 0., half_pow2, //
+                pow3, 0., pow2, 0., dt, 0., //
+                0., pow3, 0., pow2, 0., dt, //
+                half_pow2, 0., dt, 0., 1., 0., //
+                0., half_pow2, 0., dt, 0., 1.,
+            ])
+    }
+}
+
+/// # Explanation
+/// The coordinated turn model (constant turn rate and velocity) assumes that the object moves with a
+/// constant speed on a circle. The state vector consists of five dimensions (x, y, v, heading, yaw rate)
+/// where v is the speed, the heading is the angle between the x-axis and the direction of motion
+/// (counterclockwise in radians) and the yaw rate is the change of the heading per second.
+///
+/// Since the transition is nonlinear this model is meant for the nonlinear filters.
+///
+/// # Parameters
+/// The acceleration_noise parameter is the variance of the (longitudinal) acceleration.
+/// The yaw_acceleration_noise parameter is the variance of the change of the yaw rate.
+#[derive(Copy, Clone)]
+pub struct CoordinatedTurn {
+    acceleration_noise: f64,
+    yaw_acceleration_noise: f64,
+}
+
+impl CoordinatedTurn {
+    /// Below this yaw rate the object is assumed to move straight (to avoid dividing by zero).
+    const MIN_YAW_RATE: f64 = 1e-4;
+
+    pub fn new(acceleration_noise: f64, yaw_acceleration_noise: f64) -> Self {
+        Self {
+            acceleration_noise,
+            yaw_acceleration_noise,
+        }
+    }
+}
+
+impl NonlinearTransitionModel<5> for CoordinatedTurn {
+    fn transition(&self, state: &SVector<f64, 5>, dt: Duration) -> SVector<f64, 5> {
+        let dt = dt.num_milliseconds() as f64 / 1000.0;
+        let (x, y, v, heading, yaw_rate) = (state[0], state[1], state[2], state[3], state[4]);
+        let next_heading = heading + yaw_rate * dt;
+
+        if yaw_rate.abs() < Self::MIN_YAW_RATE {
+            SVector::<f64, 5>::new(
+                x + v * heading.cos() * dt,
+                y + v * heading.sin() * dt,
+                v,
+                next_heading,
+                yaw_rate,
+            )
+        } else {
+            SVector::<f64, 5>::new(
+                x + v / yaw_rate * (next_heading.sin() - heading.sin()),
+                y + v / yaw_rate * (heading.cos() - next_heading.cos()),
+                v,
+                next_heading,
+                yaw_rate,
+            )
+        }
+    }
+
+    fn transition_jacobian(&self, state: &SVector<f64, 5>, dt: Duration) -> SMatrix<f64, 5, 5> {
+        let dt = dt.num_milliseconds() as f64 / 1000.0;
+        let (v, heading, yaw_rate) = (state[2], state[3], state[4]);
+        let next_heading = heading + yaw_rate * dt;
+
+        let mut jacobian = SMatrix::<f64, 5, 5>::identity();
+        jacobian[(3, 4)] = dt;
+        if yaw_rate.abs() < Self::MIN_YAW_RATE {
+            jacobian[(0, 2)] = heading.cos() * dt;
+            jacobian[(0, 3)] = -v * heading.sin() * dt;
+            jacobian[(1, 2)] = heading.sin() * dt;
+            jacobian[(1, 3)] = v * heading.cos() * dt;
+            // limit of the derivative with respect to the yaw rate for a yaw rate of zero
+            jacobian[(0, 4)] = -v * heading.sin() * dt.powi(2) / 2.;
+            jacobian[(1, 4)] = v * heading.cos() * dt.powi(2) / 2.;
+        } else {
+            let (sin, cos) = heading.sin_cos();
+            let (next_sin, next_cos) = next_heading.sin_cos();
+
+            jacobian[(0, 2)] = (next_sin - sin) / yaw_rate;
+            jacobian[(0, 3)] = v / yaw_rate * (next_cos - cos);
+            jacobian[(0, 4)] =
+                v * dt * next_cos / yaw_rate - v * (next_sin - sin) / yaw_rate.powi(2);
+            jacobian[(1, 2)] = (cos - next_cos) / yaw_rate;
+            jacobian[(1, 3)] = v / yaw_rate * (next_sin - sin);
+            jacobian[(1, 4)] =
+                v * dt * next_sin / yaw_rate - v * (cos - next_cos) / yaw_rate.powi(2);
+        }
+        jacobian
+    }
+
+    /// # Returns
+    /// The error is independent of the heading, so the acceleration noise is spread equally on x and y and
+    /// the position is not correlated with the speed (which would depend on the heading):<br>
+    /// | a*dt^4/4         0         0         0         0 |<br>
+    /// |        0  a*dt^4/4         0         0         0 |<br>
+    /// |        0         0    a*dt^2         0         0 |<br>
+    /// |        0         0         0  y*dt^4/4  y*dt^3/2 |<br>
+    /// |        0         0         0  y*dt^3/2    y*dt^2 |<br>
+    /// where a is the acceleration noise and y the yaw acceleration noise.
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, 5, 5> {
+        let dt = dt.num_milliseconds() as f64 / 1000.0;
+        let pow4 = dt.powi(4) / 4.;
+        let pow3 = dt.powi(3) / 2.;
+        let pow2 = dt.powi(2);
+        let a = self.acceleration_noise;
+        let y = self.yaw_acceleration_noise;
+        let (a_pow4, a_pow2) = (a * pow4, a * pow2);
+        let (y_pow4, y_pow3, y_pow2) = (y * pow4, y * pow3, y * pow2);
+
+        SMatrix::<f64, 5, 5>::from_row_slice(&[
+            a_pow4, 0., 0., 0., 0., //
+            0., a_pow4, 0., 0., 0., //
+            0., 0., a_pow2, 0., 0., //
+            0., 0., 0., y_pow4, y_pow3, //
+            0., 0., 0., y_pow3, y_pow2,
+        ])
+    }
+}
+
+/// # Explanation
+/// The differential drive model describes a robot with a left and a right wheel (like the robot). It uses
+/// the state vector of the coordinated turn model (x, y, v, heading, yaw rate) and the motor commands
+/// (left, right) as the control input. The commands are mapped to the speed and the yaw rate with the
+/// calibrated gains:<br>
+/// commanded v = speed_gain * (left + right) / 2<br>
+/// commanded yaw rate = yaw_rate_gain * (right - left) / 2<br>
+/// The motors do not reach the commanded values at once, so the estimated speed and yaw rate only approach
+/// them (with the response time as time constant):<br>
+/// v' = v + (1 - exp(-dt / response_time)) * (commanded v - v)<br>
+/// This way the filter already expects the robot to move when the motors are turned on, but the estimated
+/// speed and yaw rate (eg corrected by the velocity measurements) are not replaced by the commanded ones.
+///
+/// # Parameters
+/// The speed_error parameter is the variance of the speed that results from the commands.
+/// The yaw_rate_error parameter is the variance of the yaw rate that results from the commands.
+/// The response_time (in seconds, see with_response_time) is DEFAULT_RESPONSE_TIME by default. With a
+/// response time of zero the commanded values replace the estimated ones.
+#[derive(Copy, Clone)]
+pub struct DifferentialDrive {
+    speed_gain: f64,
+    yaw_rate_gain: f64,
+    speed_error: f64,
+    yaw_rate_error: f64,
+    response_time: f64,
+}
+
+impl DifferentialDrive {
+    pub const DEFAULT_RESPONSE_TIME: f64 = 0.5;
+
+    pub fn new(speed_gain: f64, yaw_rate_gain: f64, speed_error: f64, yaw_rate_error: f64) -> Self {
+        Self {
+            speed_gain,
+            yaw_rate_gain,
+            speed_error,
+            yaw_rate_error,
+            response_time: Self::DEFAULT_RESPONSE_TIME,
+        }
+    }
+
+    pub fn with_response_time(mut self, response_time: f64) -> Self {
+        self.response_time = response_time;
+        self
+    }
+
+    /// # Returns
+    /// Returns the share of the difference between the commanded and the estimated values that is closed
+    /// after dt.
+    fn command_weight(&self, dt: Duration) -> f64 {
+        let dt = dt.num_milliseconds() as f64 / 1000.0;
+        if self.response_time <= 0. {
+            1.
+        } else {
+            1. - (-dt / self.response_time).exp()
+        }
+    }
+
+    /// # Returns
+    /// Returns the state where the speed and the yaw rate have approached the commanded ones.
+    fn commanded_state(
+        &self,
+        state: &SVector<f64, 5>,
+        control: &SVector<f64, 2>,
+        dt: Duration,
+    ) -> SVector<f64, 5> {
+        let (left, right) = (control[0], control[1]);
+        let weight = self.command_weight(dt);
+        let speed = self.speed_gain * (left + right) / 2.;
+        let yaw_rate = self.yaw_rate_gain * (right - left) / 2.;
+        SVector::<f64, 5>::new(
+            state[0],
+            state[1],
+            state[2] + weight * (speed - state[2]),
+            state[3],
+            state[4] + weight * (yaw_rate - state[4]),
+        )
+    }
+}
+
+impl ControlledTransitionModel<5, 2> for DifferentialDrive {
+    fn transition(
+        &self,
+        state: &SVector<f64, 5>,
+        control: &SVector<f64, 2>,
+        dt: Duration,
+    ) -> SVector<f64, 5> {
+        CoordinatedTurn::new(0., 0.).transition(&self.commanded_state(state, control, dt), dt)
+    }
+
+    fn transition_jacobian(
+        &self,
+        state: &SVector<f64, 5>,
+        control: &SVector<f64, 2>,
+        dt: Duration,
+    ) -> SMatrix<f64, 5, 5> {
+        let mut jacobian = CoordinatedTurn::new(0., 0.)
+            .transition_jacobian(&self.commanded_state(state, control, dt), dt);
+        // the estimated speed and yaw rate only keep the share that is not replaced by the commands
+        let kept = 1. - self.command_weight(dt);
+        jacobian.column_mut(2).scale_mut(kept);
+        jacobian.column_mut(4).scale_mut(kept);
+        jacobian
+    }
+
+    /// # Returns
+    /// | s*dt^2       0  0       0  0 |<br>
+    /// |      0  s*dt^2  0       0  0 |<br>
+    /// |      0       0  s       0  0 |<br>
+    /// |      0       0  0  y*dt^2  0 |<br>
+    /// |      0       0  0       0  y |<br>
+    /// where s is the speed error and y the yaw rate error.
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, 5, 5> {
+        let dt = dt.num_milliseconds() as f64 / 1000.0;
+        let pow2 = dt.powi(2);
+        SMatrix::<f64, 5, 5>::from_diagonal(&SVector::<f64, 5>::new(
+            self.speed_error * pow2,
+            self.speed_error * pow2,
+            self.speed_error,
+            self.yaw_rate_error * pow2,
+            self.yaw_rate_error,
+        ))
+    }
+}
+
+/// # Explanation
+/// The stationary transition model assumes that the object does not move. It uses the same state vector
+/// as the constant velocity model (x, y, vx, vy) so that both can be mixed (eg in an
+/// InteractingMultipleModel estimator). The velocity is always predicted to be zero.
+#[derive(Copy, Clone)]
+pub struct Stationary {
+    drift: f64,
+}
+
+impl Stationary {
+    pub fn new(drift: f64) -> Self {
+        Self { drift }
+    }
+}
+
+impl LinearTransitionModel<4> for Stationary {
+    /// # Returns
+    /// | 1.  0.  0.  0. |<br>
+    /// | 0.  1.  0.  0. |<br>
+    /// | 0.  0.  0.  0. |<br>
+    /// | 0.  0.  0.  0. |<br>
+    fn transition_matrix(&self, _: Duration) -> SMatrix<f64, 4, 4> {
+        SMatrix::<f64, 4, 4>::from_diagonal(&SVector::<f64, 4>::new(1., 1., 0., 0.))
+    }
+
+    /// # Returns
+    /// q *<br>
+    /// | dt  0.  0.  0. |<br>
+    /// | 0.  dt  0.  0. |<br>
+    /// | 0.  0.  dt  0. |<br>
+    /// | 0.  0.  0.  dt |<br>
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, 4, 4> {
+        let dt = dt.num_milliseconds() as f64 / 1000.0;
+        self.drift * dt * SMatrix::<f64, 4, 4>::identity()
+    }
+}
+
 /// # Explanation
 /// The xy sensors model assumes that only the position is measured so that the sensors dimension
 /// is two (x, y).
@@ -80,6 +422,233 @@ impl<const SD: usize> LinearMeasurementModel<2, SD> for PositionMeasurementModel
     }
 }
 
+/// # Explanation
+/// The velocity sensors model assumes that only the velocity is measured (eg by the optical flow sensor)
+/// so that the sensors dimension is two (vx, vy). The velocity must be at the indices two and three of
+/// the state vector (like in the constant velocity and the constant acceleration model).
+///
+/// # Parameters
+/// The error_x parameter represents the uncertainty in the x-axis.
+/// The error_y parameter represents the uncertainty in the y-axis.
+///
+/// SD is the dimension of the state vectors (at least four).
+#[derive(Copy, Clone)]
+pub struct VelocityMeasurementModel<const SD: usize> {
+    error_x: f64,
+    error_y: f64,
+}
+
+impl<const SD: usize> VelocityMeasurementModel<SD> {
+    pub fn new(error_x: f64, error_y: f64) -> Self {
+        Self { error_x, error_y }
+    }
+}
+
+impl<const SD: usize> LinearMeasurementModel<2, SD> for VelocityMeasurementModel<SD> {
+    fn measurement_matrix(&self) -> SMatrix<f64, 2, SD> {
+        let mut measurement_matrix = SMatrix::<f64, 2, SD>::zeros();
+        measurement_matrix[(0, 2)] = 1.;
+        measurement_matrix[(1, 3)] = 1.;
+        measurement_matrix
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, 2, 2> {
+        SMatrix::<f64, 2, 2>::new(self.error_x, 0., 0., self.error_y)
+    }
+}
+
+/// # Explanation
+/// The PolarVelocityMeasurementModel measures the velocity (vx, vy) of an object whose state is described
+/// by the coordinated turn model (x, y, v, heading, yaw rate). It is the velocity part of the
+/// KinematicMeasurementModel.
+///
+/// # Parameters
+/// The velocity_error parameter represents the uncertainty of the velocity (in both axes).
+#[derive(Copy, Clone)]
+pub struct PolarVelocityMeasurementModel {
+    velocity_error: f64,
+}
+
+impl PolarVelocityMeasurementModel {
+    pub fn new(velocity_error: f64) -> Self {
+        Self { velocity_error }
+    }
+}
+
+impl NonlinearMeasurementModel<2, 5> for PolarVelocityMeasurementModel {
+    fn measure(&self, state: &SVector<f64, 5>) -> SVector<f64, 2> {
+        let (v, heading) = (state[2], state[3]);
+        SVector::<f64, 2>::new(v * heading.cos(), v * heading.sin())
+    }
+
+    fn measurement_jacobian(&self, state: &SVector<f64, 5>) -> SMatrix<f64, 2, 5> {
+        let (v, heading) = (state[2], state[3]);
+        let (sin, cos) = heading.sin_cos();
+        let (v_sin, v_cos) = (v * sin, v * cos);
+        SMatrix::<f64, 2, 5>::from_row_slice(&[
+            0., 0., cos, -v_sin, 0., //
+            0., 0., sin, v_cos, 0.,
+        ])
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, 2, 2> {
+        self.velocity_error * SMatrix::<f64, 2, 2>::identity()
+    }
+}
+
+/// # Explanation
+/// The BodyVelocityMeasurementModel measures the velocity in the frame of the robot (forward, left), like
+/// the optical flow sensor does before its velocity is rotated by the compass. The state is described by
+/// the coordinated turn model (x, y, v, heading, yaw rate). The robot cannot move sideways, so the
+/// velocity in the frame of the robot is (v, 0) and does not depend on the heading (the heading is
+/// measured on its own with the HeadingMeasurementModel).
+///
+/// # Parameters
+/// The forward_error parameter represents the uncertainty of the forward velocity.
+/// The lateral_error parameter represents the uncertainty of the lateral velocity (which includes the
+/// slip of the wheels).
+#[derive(Copy, Clone)]
+pub struct BodyVelocityMeasurementModel {
+    forward_error: f64,
+    lateral_error: f64,
+}
+
+impl BodyVelocityMeasurementModel {
+    pub fn new(forward_error: f64, lateral_error: f64) -> Self {
+        Self {
+            forward_error,
+            lateral_error,
+        }
+    }
+}
+
+impl LinearMeasurementModel<2, 5> for BodyVelocityMeasurementModel {
+    /// # Returns
+    /// | 0.  0.  1.  0.  0. |<br>
+    /// | 0.  0.  0.  0.  0. |<br>
+    fn measurement_matrix(&self) -> SMatrix<f64, 2, 5> {
+        let mut measurement_matrix = SMatrix::<f64, 2, 5>::zeros();
+        measurement_matrix[(0, 2)] = 1.;
+        measurement_matrix
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, 2, 2> {
+        SMatrix::<f64, 2, 2>::new(self.forward_error, 0., 0., self.lateral_error)
+    }
+}
+
+/// # Explanation
+/// The HeadingMeasurementModel measures the heading of the coordinated turn model
+/// (x, y, v, heading, yaw rate), eg with the compass. The heading of the state is not wrapped to ±π, so
+/// the measured heading has to be unwrapped as well (like the HeadingSensor of the robot does).
+///
+/// # Parameters
+/// The heading_error parameter represents the uncertainty of the heading.
+#[derive(Copy, Clone)]
+pub struct HeadingMeasurementModel {
+    heading_error: f64,
+}
+
+impl HeadingMeasurementModel {
+    pub fn new(heading_error: f64) -> Self {
+        Self { heading_error }
+    }
+}
+
+impl LinearMeasurementModel<1, 5> for HeadingMeasurementModel {
+    /// # Returns
+    /// | 0.  0.  0.  1.  0. |<br>
+    fn measurement_matrix(&self) -> SMatrix<f64, 1, 5> {
+        SMatrix::<f64, 1, 5>::new(0., 0., 0., 1., 0.)
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, 1, 1> {
+        SMatrix::<f64, 1, 1>::new(self.heading_error)
+    }
+}
+
+/// # Explanation
+/// The acceleration sensors model assumes that only the acceleration of the constant acceleration model is
+/// measured (eg by the BNO055), so that the sensors dimension is two (ax, ay).
+///
+/// # Parameters
+/// The error_x parameter represents the uncertainty in the x-axis.
+/// The error_y parameter represents the uncertainty in the y-axis.
+#[derive(Copy, Clone)]
+pub struct AccelerationMeasurementModel {
+    error_x: f64,
+    error_y: f64,
+}
+
+impl AccelerationMeasurementModel {
+    pub fn new(error_x: f64, error_y: f64) -> Self {
+        Self { error_x, error_y }
+    }
+}
+
+impl LinearMeasurementModel<2, 6> for AccelerationMeasurementModel {
+    fn measurement_matrix(&self) -> SMatrix<f64, 2, 6> {
+        SMatrix::<f64, 2, 6>::new(
+            0., 0., 0., 0., 1., 0., //
+            0., 0., 0., 0., 0., 1.,
+        )
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, 2, 2> {
+        SMatrix::<f64, 2, 2>::new(self.error_x, 0., 0., self.error_y)
+    }
+}
+
+/// # Explanation
+/// The KinematicMeasurementModel measures the position and the velocity (x, y, vx, vy) of an object whose
+/// state is described by the coordinated turn model (x, y, v, heading, yaw rate).
+///
+/// # Parameters
+/// The position_error parameter represents the uncertainty of the position (in both axes).
+/// The velocity_error parameter represents the uncertainty of the velocity (in both axes).
+#[derive(Copy, Clone)]
+pub struct KinematicMeasurementModel {
+    position_error: f64,
+    velocity_error: f64,
+}
+
+impl KinematicMeasurementModel {
+    pub fn new(position_error: f64, velocity_error: f64) -> Self {
+        Self {
+            position_error,
+            velocity_error,
+        }
+    }
+}
+
+impl NonlinearMeasurementModel<4, 5> for KinematicMeasurementModel {
+    fn measure(&self, state: &SVector<f64, 5>) -> SVector<f64, 4> {
+        let (v, heading) = (state[2], state[3]);
+        SVector::<f64, 4>::new(state[0], state[1], v * heading.cos(), v * heading.sin())
+    }
+
+    fn measurement_jacobian(&self, state: &SVector<f64, 5>) -> SMatrix<f64, 4, 5> {
+        let (v, heading) = (state[2], state[3]);
+        let (sin, cos) = heading.sin_cos();
+        let (v_sin, v_cos) = (v * sin, v * cos);
+        SMatrix::<f64, 4, 5>::from_row_slice(&[
+            1., 0., 0., 0., 0., //
+            0., 1., 0., 0., 0., //
+            0., 0., cos, -v_sin, 0., //
+            0., 0., sin, v_cos, 0.,
+        ])
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, 4, 4> {
+        SMatrix::<f64, 4, 4>::from_diagonal(&SVector::<f64, 4>::new(
+            self.position_error,
+            self.position_error,
+            self.velocity_error,
+            self.velocity_error,
+        ))
+    }
+}
+
 /// # Explanation
 /// The MeasureAllModel assumes that all state variables are also measured (so the sensors matrix is the
 /// identity matrix). The error matrix is a diagonal matrix.
@@ -104,3 +673,37 @@ impl<const D: usize> LinearMeasurementModel<D, D> for MeasureAllModel<D> {
         self.measurement_error
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use nalgebra::SVector;
+
+    use crate::kalman::model::DifferentialDrive;
+    use crate::model::ControlledTransitionModel;
+
+    #[test]
+    fn test_differential_drive_response() {
+        let model = DifferentialDrive::new(0.5, 1.5, 0.01, 0.01);
+        let state = SVector::<f64, 5>::new(0., 0., 1., 0., 0.);
+
+        // the commands only pull the speed towards the commanded one (after one response time 1/e is left)
+        let stopped = model.transition(&state, &SVector::zeros(), Duration::milliseconds(500));
+        assert!((stopped[2] - (-1f64).exp()).abs() < 1e-9);
+        let immediate = model.with_response_time(0.).transition(
+            &state,
+            &SVector::zeros(),
+            Duration::milliseconds(100),
+        );
+        assert_eq!(immediate[2], 0.);
+
+        // turning the left wheel backwards and the right one forwards turns the robot to the left
+        let turning = model.with_response_time(0.).transition(
+            &SVector::zeros(),
+            &SVector::<f64, 2>::new(-1., 1.),
+            Duration::milliseconds(100),
+        );
+        assert_eq!(turning[2], 0.);
+        assert!((turning[4] - 1.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,245 @@
+use nalgebra::{SMatrix, SVector};
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{ChiSquared, ContinuousCDF};
+
+use crate::estimator::EstimationError;
+
+/// # Explanation
+/// The OutlierHandling decides how a kalman filter treats a measurement based on its normalized
+/// innovation squared (NIS), ie the squared mahalanobis distance between the measurement and the
+/// predicted measurement. For a correct model the NIS is chi-square distributed with as many degrees of
+/// freedom as the measurement has dimensions.
+///
+/// - AcceptAll uses every measurement with its full weight.
+/// - ChiSquareGate rejects measurements whose NIS is larger than the quantile of the chi-square
+///   distribution at the confidence level (eg 0.99). The filter then returns
+///   EstimationError::OutlierRejected and the caller can simply skip the measurement.
+/// - Huber keeps every measurement but down weights the ones whose mahalanobis distance is larger than
+///   the threshold (eg 3) by inflating their measurement error (Huber weights).
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutlierHandling {
+    #[default]
+    AcceptAll,
+    ChiSquareGate {
+        confidence_level: f64,
+    },
+    Huber {
+        threshold: f64,
+    },
+}
+
+impl OutlierHandling {
+    /// # Explanation
+    /// Checks that the confidence level of the chi-square gate is a probability in (0, 1) (eg 0.99 and
+    /// not 99) and that the threshold of the huber weights is positive.
+    pub fn validate(&self) -> Result<(), EstimationError> {
+        match *self {
+            OutlierHandling::AcceptAll => Ok(()),
+            OutlierHandling::ChiSquareGate { confidence_level } => {
+                if confidence_level > 0. && confidence_level < 1. {
+                    Ok(())
+                } else {
+                    Err(EstimationError::InvalidParameter(format!(
+                        "the confidence level {} of the chi-square gate is not in (0, 1)",
+                        confidence_level
+                    )))
+                }
+            }
+            OutlierHandling::Huber { threshold } => {
+                if threshold > 0. {
+                    Ok(())
+                } else {
+                    Err(EstimationError::InvalidParameter(format!(
+                        "the threshold {} of the huber weights is not positive",
+                        threshold
+                    )))
+                }
+            }
+        }
+    }
+
+    /// # Returns
+    /// Returns the factor the measurement error is multiplied with for a measurement with the given NIS
+    /// and dimension. EstimationError::OutlierRejected is returned if the measurement is rejected and
+    /// EstimationError::InvalidParameter if the outlier handling is invalid (see validate).
+    pub fn measurement_error_scale(
+        &self,
+        nis: f64,
+        dimension: usize,
+    ) -> Result<f64, EstimationError> {
+        OutlierGate::new(*self, dimension).measurement_error_scale(nis)
+    }
+}
+
+/// # Explanation
+/// The OutlierGate is the OutlierHandling of the measurements of one sensor, whose dimension is known
+/// when the filter is created. So the quantile of the chi-square gate is computed once instead of for
+/// every measurement.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct OutlierGate {
+    outlier_handling: OutlierHandling,
+    quantile: f64,
+}
+
+impl OutlierGate {
+    pub(crate) fn new(outlier_handling: OutlierHandling, dimension: usize) -> Self {
+        let quantile = match outlier_handling {
+            // an invalid confidence level is reported when the gate is used
+            OutlierHandling::ChiSquareGate { confidence_level }
+                if outlier_handling.validate().is_ok() =>
+            {
+                chi_square_quantile(confidence_level, dimension)
+            }
+            _ => f64::NAN,
+        };
+        Self {
+            outlier_handling,
+            quantile,
+        }
+    }
+
+    /// # Returns
+    /// Returns the factor the measurement error is multiplied with (see
+    /// OutlierHandling::measurement_error_scale).
+    pub(crate) fn measurement_error_scale(&self, nis: f64) -> Result<f64, EstimationError> {
+        self.outlier_handling.validate()?;
+        if !nis.is_finite() {
+            return Err(EstimationError::NumericalError(
+                "the normalized innovation squared is not finite".to_string(),
+            ));
+        }
+
+        match self.outlier_handling {
+            OutlierHandling::AcceptAll => Ok(1.),
+            OutlierHandling::ChiSquareGate { .. } => {
+                if nis > self.quantile {
+                    Err(EstimationError::OutlierRejected(nis))
+                } else {
+                    Ok(1.)
+                }
+            }
+            OutlierHandling::Huber { threshold } => Ok((nis.sqrt() / threshold).max(1.)),
+        }
+    }
+}
+
+/// # Returns
+/// Returns the value that a chi-square distributed variable with the given degrees of freedom does not
+/// exceed with the given probability. Probabilities outside of (0, 1) result in zero or infinity.
+pub fn chi_square_quantile(probability: f64, degrees_of_freedom: usize) -> f64 {
+    // statrs searches the quantile by doubling an upper bound, which never ends for probabilities above one
+    if probability <= 0. {
+        return 0.;
+    }
+    if probability >= 1. {
+        return f64::INFINITY;
+    }
+    ChiSquared::new(degrees_of_freedom as f64)
+        .map(|distribution| distribution.inverse_cdf(probability))
+        .unwrap_or(f64::INFINITY)
+}
+
+/// # Returns
+/// Returns the innovation error (the covariance of the innovation) and its inverse. The measurement error
+/// is scaled according to the outlier handling before it is added to the error of the predicted
+/// measurement.
+pub(crate) fn robust_innovation_error<const MD: usize>(
+    outlier_gate: &OutlierGate,
+    innovation: &SVector<f64, MD>,
+    predicted_measurement_error: SMatrix<f64, MD, MD>,
+    measurement_error: SMatrix<f64, MD, MD>,
+) -> Result<(SMatrix<f64, MD, MD>, SMatrix<f64, MD, MD>), EstimationError> {
+    let innovation_error = predicted_measurement_error + measurement_error;
+    let innovation_error_inverse = invert_innovation_error(innovation_error)?;
+    let nis = innovation.dot(&(innovation_error_inverse * innovation));
+
+    let scale = outlier_gate.measurement_error_scale(nis)?;
+    if scale == 1. {
+        return Ok((innovation_error, innovation_error_inverse));
+    }
+
+    let innovation_error = predicted_measurement_error + scale * measurement_error;
+    let innovation_error_inverse = invert_innovation_error(innovation_error)?;
+    Ok((innovation_error, innovation_error_inverse))
+}
+
+fn invert_innovation_error<const MD: usize>(
+    innovation_error: SMatrix<f64, MD, MD>,
+) -> Result<SMatrix<f64, MD, MD>, EstimationError> {
+    innovation_error.try_inverse().ok_or_else(|| {
+        EstimationError::NumericalError("the innovation error is not invertible".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use nalgebra::{SMatrix, SVector};
+
+    use crate::estimator::{EstimationError, Estimator};
+    use crate::kalman::estimator::KalmanFilter;
+    use crate::kalman::model::{ConstantVelocity, PositionMeasurementModel};
+    use crate::kalman::outlier::{chi_square_quantile, OutlierHandling};
+    use crate::state::{GaussianState, Measurement, Waypoint};
+    use crate::track::Track;
+
+    #[test]
+    fn test_measurement_error_scale() {
+        let gate = OutlierHandling::ChiSquareGate {
+            confidence_level: 0.99,
+        };
+        let quantile = chi_square_quantile(0.99, 2);
+        assert_eq!(gate.measurement_error_scale(0.9 * quantile, 2).unwrap(), 1.);
+        assert!(matches!(
+            gate.measurement_error_scale(1.1 * quantile, 2),
+            Err(EstimationError::OutlierRejected(_))
+        ));
+
+        // the huber weights only inflate the error of measurements beyond the threshold
+        let huber = OutlierHandling::Huber { threshold: 3. };
+        assert_eq!(huber.measurement_error_scale(4., 2).unwrap(), 1.);
+        assert!((huber.measurement_error_scale(36., 2).unwrap() - 2.).abs() < 1e-9);
+        assert!(matches!(
+            OutlierHandling::AcceptAll.measurement_error_scale(f64::NAN, 2),
+            Err(EstimationError::NumericalError(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_confidence_level() {
+        let track = Track::new(Waypoint::new(
+            Utc.timestamp_nanos(0),
+            GaussianState::new(SVector::<f64, 4>::zeros(), SMatrix::<f64, 4, 4>::identity()),
+        ));
+        let measurement = Measurement::new(
+            Utc.timestamp_nanos(1_000_000_000),
+            SVector::<f64, 2>::zeros(),
+        );
+
+        // a percent-style confidence level is rejected instead of searching the quantile forever
+        for confidence_level in [99., 1., 0., -0.5, f64::NAN] {
+            let outlier_handling = OutlierHandling::ChiSquareGate { confidence_level };
+            assert!(matches!(
+                outlier_handling.validate(),
+                Err(EstimationError::InvalidParameter(_))
+            ));
+            let kalman_filter = KalmanFilter::new(
+                ConstantVelocity::new(0.05),
+                PositionMeasurementModel::new(0.1, 0.1),
+            )
+            .with_outlier_handling(outlier_handling);
+            assert!(matches!(
+                kalman_filter.estimate(&track, measurement.clone()),
+                Err(EstimationError::InvalidParameter(_))
+            ));
+        }
+        assert!(OutlierHandling::ChiSquareGate {
+            confidence_level: 0.99
+        }
+        .validate()
+        .is_ok());
+        assert!(OutlierHandling::Huber { threshold: 0. }.validate().is_err());
+        assert_eq!(chi_square_quantile(99., 2), f64::INFINITY);
+    }
+}
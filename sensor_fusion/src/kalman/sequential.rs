@@ -0,0 +1,162 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::estimator::{time_since_latest_waypoint, EstimationError, Predictor};
+use crate::kalman::ekf::{predict_linearized, update_linearized};
+use crate::kalman::outlier::{OutlierGate, OutlierHandling};
+use crate::model::{NonlinearMeasurementModel, NonlinearTransitionModel};
+use crate::state::{GaussianState, Measurement, Prediction, Waypoint};
+use crate::track::Track;
+
+/// # Explanation
+/// A SensorMeasurement is a measurement that knows how to correct a prediction of the state. It hides the
+/// dimension of the measurement so that measurements of different sensors (eg a gps position and an
+/// optical flow velocity) can be processed together by the SequentialKalmanFilter.
+pub trait SensorMeasurement<const SD: usize> {
+    fn timestamp(&self) -> DateTime<Utc>;
+
+    /// # Returns
+    /// Returns the prediction corrected by this measurement.
+    fn update(&self, prediction: GaussianState<SD>) -> Result<GaussianState<SD>, EstimationError>;
+}
+
+/// # Explanation
+/// A ModeledMeasurement is a measurement together with the model of the sensor that produced it.
+/// Every linear measurement model is also a nonlinear one, so eg the PositionMeasurementModel can be used.
+/// The outlier handling can be chosen per sensor (eg only the gps positions are gated).
+#[derive(Clone)]
+pub struct ModeledMeasurement<const MD: usize, MModel> {
+    measurement: Measurement<MD>,
+    measurement_model: MModel,
+    outlier_gate: OutlierGate,
+}
+
+impl<const MD: usize, MModel> ModeledMeasurement<MD, MModel> {
+    pub fn new(measurement: Measurement<MD>, measurement_model: MModel) -> Self {
+        Self {
+            measurement,
+            measurement_model,
+            outlier_gate: OutlierGate::new(OutlierHandling::AcceptAll, MD),
+        }
+    }
+
+    pub fn with_outlier_handling(mut self, outlier_handling: OutlierHandling) -> Self {
+        self.outlier_gate = OutlierGate::new(outlier_handling, MD);
+        self
+    }
+}
+
+impl<const MD: usize, const SD: usize, MModel> SensorMeasurement<SD>
+    for ModeledMeasurement<MD, MModel>
+where
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.measurement.timestamp
+    }
+
+    fn update(&self, prediction: GaussianState<SD>) -> Result<GaussianState<SD>, EstimationError> {
+        update_linearized(
+            &self.measurement_model,
+            &self.outlier_gate,
+            prediction,
+            self.measurement.clone(),
+        )
+        .map(|(updated, _)| updated)
+    }
+}
+
+/// # Explanation
+/// The SequentialKalmanFilter fuses the measurements of multiple sensors with different dimensions into
+/// one state. The measurements are processed in the order of their timestamps: the state is predicted to
+/// the timestamp of a measurement and then corrected by it (with the model of its sensor). Measurements
+/// with the same timestamp share one prediction and are applied one after the other, so they result in a
+/// single waypoint.
+///
+/// Like the ExtendedKalmanFilter the models are linearized at the current estimate (for linear models this
+/// is the KalmanFilter). A sensor that delivers no measurement simply does not contribute, so eg a
+/// missing gps fix does not throw away the velocity of the optical flow sensor. Measurements that are
+/// rejected as outliers are skipped in the same way.
+pub struct SequentialKalmanFilter<const SD: usize, TModel> {
+    transition_model: TModel,
+}
+
+impl<const SD: usize, TModel> SequentialKalmanFilter<SD, TModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+{
+    pub fn new(transition_model: TModel) -> Self {
+        Self { transition_model }
+    }
+
+    pub fn transition_model(&self) -> &TModel {
+        &self.transition_model
+    }
+
+    /// # Returns
+    /// Returns the waypoints that follow the latest waypoint of the track (one for every distinct
+    /// timestamp of the measurements, oldest first). Every waypoint contains its prediction so that the
+    /// track can be smoothed afterwards.
+    pub fn estimate_waypoints(
+        &self,
+        track: &Track<SD>,
+        mut measurements: Vec<Box<dyn SensorMeasurement<SD>>>,
+    ) -> Result<Vec<Waypoint<SD>>, EstimationError> {
+        measurements.sort_by_key(|measurement| measurement.timestamp());
+        if let Some(oldest) = measurements.first() {
+            time_since_latest_waypoint(track, oldest.timestamp())?;
+        }
+
+        let mut waypoints: Vec<Waypoint<SD>> = Vec::new();
+        for measurement in measurements {
+            let timestamp = measurement.timestamp();
+            if let Some(waypoint) = waypoints
+                .last_mut()
+                .filter(|waypoint| waypoint.timestamp == timestamp)
+            {
+                waypoint.state = update_or_skip(measurement.as_ref(), waypoint.state.clone())?;
+                continue;
+            }
+
+            let previous = waypoints.last().unwrap_or(track.get_latest_waypoint());
+            let dt = timestamp - previous.timestamp;
+            let prediction = predict_linearized(&self.transition_model, &previous.state, dt);
+            let filtered = update_or_skip(measurement.as_ref(), prediction.clone())?;
+            waypoints.push(Waypoint::with_prediction(
+                timestamp,
+                filtered,
+                Prediction::new(dt, prediction),
+            ));
+        }
+        Ok(waypoints)
+    }
+}
+
+/// # Returns
+/// Returns the state corrected by the measurement or the unchanged state if the measurement was rejected
+/// as an outlier.
+fn update_or_skip<const SD: usize>(
+    measurement: &dyn SensorMeasurement<SD>,
+    state: GaussianState<SD>,
+) -> Result<GaussianState<SD>, EstimationError> {
+    match measurement.update(state.clone()) {
+        Err(EstimationError::OutlierRejected(_)) => Ok(state),
+        result => result,
+    }
+}
+
+impl<const SD: usize, TModel> Predictor<SD> for SequentialKalmanFilter<SD, TModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+{
+    fn predict(
+        &self,
+        track: &Track<SD>,
+        dt: Duration,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        Ok(predict_linearized(
+            &self.transition_model,
+            &track.get_latest_waypoint().state,
+            dt,
+        ))
+    }
+}
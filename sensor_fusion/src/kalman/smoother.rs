@@ -0,0 +1,264 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::estimator::{EstimationError, Estimator, Filter, Predictor};
+use crate::model::LinearTransitionModel;
+use crate::state::{GaussianState, Measurement, Waypoint};
+use crate::track::Track;
+
+/// # Explanation
+/// The RauchTungStriebelSmoother improves the estimates of a recorded track by also using the
+/// measurements that arrived after a waypoint. It runs backwards over the track (that was created with
+/// Estimator::estimate_waypoint of a KalmanFilter) and combines every filtered state with the smoothed
+/// state of the next waypoint. For this the predictions stored in the waypoints and the transition
+/// matrices of the transition model are used.
+///
+/// The transition model must be the one that the KalmanFilter used.
+pub struct RauchTungStriebelSmoother<TModel> {
+    transition_model: TModel,
+}
+
+impl<TModel> RauchTungStriebelSmoother<TModel> {
+    pub fn new(transition_model: TModel) -> Self {
+        Self { transition_model }
+    }
+
+    /// # Returns
+    /// Returns the smoothed track. Every waypoint except the first one needs to contain the prediction
+    /// of the filter, otherwise EstimationError::MissingPrediction is returned.
+    pub fn smooth<const SD: usize>(&self, track: &Track<SD>) -> Result<Track<SD>, EstimationError>
+    where
+        TModel: LinearTransitionModel<SD>,
+    {
+        let filtered: Vec<Waypoint<SD>> = track.clone().into_iter().collect();
+        let mut waypoints = self.smooth_waypoints(&filtered)?.into_iter();
+
+        let mut smoothed_track = Track::new(waypoints.next().unwrap()); // track cannot be empty
+        for waypoint in waypoints {
+            smoothed_track.add_waypoint(waypoint);
+        }
+        Ok(smoothed_track)
+    }
+
+    /// # Returns
+    /// Returns the smoothed waypoints (in the same order). The last waypoint stays unchanged since no
+    /// later measurements exist for it.
+    fn smooth_waypoints<const SD: usize>(
+        &self,
+        filtered: &[Waypoint<SD>],
+    ) -> Result<Vec<Waypoint<SD>>, EstimationError>
+    where
+        TModel: LinearTransitionModel<SD>,
+    {
+        let mut smoothed: Vec<Waypoint<SD>> = Vec::with_capacity(filtered.len());
+        if let Some(last) = filtered.last() {
+            smoothed.push(last.clone());
+        }
+
+        for (current, next) in filtered.iter().zip(filtered.iter().skip(1)).rev() {
+            let prediction = next
+                .prediction
+                .as_ref()
+                .ok_or(EstimationError::MissingPrediction)?;
+            let smoothed_next = &smoothed[smoothed.len() - 1].state;
+
+            let transition_matrix = self.transition_model.transition_matrix(prediction.dt);
+            let prediction_error_inverse =
+                prediction.state.error.try_inverse().ok_or_else(|| {
+                    EstimationError::NumericalError(
+                        "the predicted error is not invertible".to_string(),
+                    )
+                })?;
+            let smoother_gain =
+                current.state.error * transition_matrix.transpose() * prediction_error_inverse;
+
+            let smoothed_estimate = current.state.estimate
+                + smoother_gain * (smoothed_next.estimate - prediction.state.estimate);
+            let smoothed_error = current.state.error
+                + smoother_gain
+                    * (smoothed_next.error - prediction.state.error)
+                    * smoother_gain.transpose();
+
+            let mut waypoint = current.clone();
+            waypoint.state = GaussianState::new(smoothed_estimate, smoothed_error);
+            smoothed.push(waypoint);
+        }
+
+        smoothed.reverse();
+        Ok(smoothed)
+    }
+}
+
+/// # Explanation
+/// The FixedLagSmoother wraps a filter (anything that is a Predictor and a Filter) and keeps the last
+/// waypoints the filter produced in a window of the given size. Whenever a new measurement arrives the
+/// window is smoothed again (with the RauchTungStriebelSmoother) so that the older waypoints of the window
+/// also profit from the new measurement.
+///
+/// As an Estimator it still returns the output of the forward filter so that the track records the
+/// filtered states. The smoothed states are available with smoothed_waypoints and lagged_waypoint.
+pub struct FixedLagSmoother<const SD: usize, E, TModel> {
+    estimator: E,
+    smoother: RauchTungStriebelSmoother<TModel>,
+    window_size: usize,
+    window: RefCell<VecDeque<Waypoint<SD>>>,
+    smoothed: RefCell<Vec<Waypoint<SD>>>,
+}
+
+impl<const SD: usize, E, TModel> FixedLagSmoother<SD, E, TModel>
+where
+    TModel: LinearTransitionModel<SD>,
+{
+    /// # Explanation
+    /// The transition model must be the one the estimator uses.
+    ///
+    /// # Returns
+    /// Returns the smoother or EstimationError::InvalidParameter if the window size is zero.
+    pub fn new(
+        estimator: E,
+        transition_model: TModel,
+        window_size: usize,
+    ) -> Result<Self, EstimationError> {
+        if window_size == 0 {
+            return Err(EstimationError::InvalidParameter(
+                "the window of a fixed lag smoother must contain at least one waypoint".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            estimator,
+            smoother: RauchTungStriebelSmoother::new(transition_model),
+            window_size,
+            window: RefCell::new(VecDeque::with_capacity(window_size + 1)),
+            smoothed: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// # Returns
+    /// Returns the smoothed waypoints of the window (oldest first).
+    pub fn smoothed_waypoints(&self) -> Vec<Waypoint<SD>> {
+        self.smoothed.borrow().clone()
+    }
+
+    /// # Returns
+    /// Returns the oldest smoothed waypoint of the window. This is the most refined estimate, but it is
+    /// delayed by (window size - 1) measurements.
+    pub fn lagged_waypoint(&self) -> Option<Waypoint<SD>> {
+        self.smoothed.borrow().first().cloned()
+    }
+}
+
+impl<const MD: usize, const SD: usize, E, TModel> Estimator<MD, SD>
+    for FixedLagSmoother<SD, E, TModel>
+where
+    E: Predictor<SD> + Filter<MD, SD>,
+    TModel: LinearTransitionModel<SD>,
+{
+    fn estimate(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        Ok(self.estimate_waypoint(track, measurement)?.state)
+    }
+
+    fn estimate_waypoint(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<Waypoint<SD>, EstimationError> {
+        let waypoint = self.estimator.estimate_waypoint(track, measurement)?;
+
+        let mut window = self.window.borrow_mut();
+        if window.is_empty() {
+            // the first waypoint of the window does not need a prediction
+            window.push_back(track.get_latest_waypoint().clone());
+        }
+        window.push_back(waypoint.clone());
+        while window.len() > self.window_size {
+            window.pop_front();
+        }
+
+        let smoothed = self.smoother.smooth_waypoints(window.make_contiguous())?;
+        self.smoothed.replace(smoothed);
+        Ok(waypoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::{SMatrix, SVector};
+
+    use crate::estimator::{EstimationError, Estimator};
+    use crate::kalman::estimator::KalmanFilter;
+    use crate::kalman::model::{ConstantVelocity, PositionMeasurementModel};
+    use crate::kalman::smoother::{FixedLagSmoother, RauchTungStriebelSmoother};
+    use crate::state::{GaussianState, Measurement, Waypoint};
+    use crate::track::Track;
+
+    fn initial_waypoint() -> Waypoint<4> {
+        Waypoint::new(
+            Utc.timestamp_nanos(0),
+            GaussianState::new(
+                SVector::<f64, 4>::new(0., 0., 1., 0.),
+                SMatrix::<f64, 4, 4>::identity(),
+            ),
+        )
+    }
+
+    #[test]
+    fn test_missing_prediction() {
+        let mut track = Track::new(initial_waypoint());
+        track.add_waypoint(Waypoint::new(
+            Utc.timestamp_nanos(1_000_000_000),
+            GaussianState::new(
+                SVector::<f64, 4>::new(1., 0., 1., 0.),
+                SMatrix::<f64, 4, 4>::identity(),
+            ),
+        ));
+        assert!(matches!(
+            RauchTungStriebelSmoother::new(ConstantVelocity::new(0.05)).smooth(&track),
+            Err(EstimationError::MissingPrediction)
+        ));
+    }
+
+    #[test]
+    fn test_fixed_lag_window() {
+        let transition_model = ConstantVelocity::new(0.05);
+        let kalman_filter =
+            || KalmanFilter::new(transition_model, PositionMeasurementModel::new(0.1, 0.1));
+        assert!(matches!(
+            FixedLagSmoother::new(kalman_filter(), transition_model, 0),
+            Err(EstimationError::InvalidParameter(_))
+        ));
+
+        let fixed_lag_smoother =
+            FixedLagSmoother::new(kalman_filter(), transition_model, 3).unwrap();
+        assert!(fixed_lag_smoother.lagged_waypoint().is_none());
+        let mut track = Track::new(initial_waypoint());
+        for i in 1..=5 {
+            let measurement = Measurement::new(
+                Utc.timestamp_nanos(0) + Duration::seconds(i),
+                SVector::<f64, 2>::new(i as f64, 0.),
+            );
+            let waypoint = fixed_lag_smoother
+                .estimate_waypoint(&track, measurement)
+                .unwrap();
+            track.add_waypoint(waypoint);
+
+            // the window contains the latest waypoints (and the initial one until it is full)
+            let smoothed_waypoints = fixed_lag_smoother.smoothed_waypoints();
+            assert_eq!(smoothed_waypoints.len(), (i as usize + 1).min(3));
+            assert_eq!(
+                smoothed_waypoints.last().unwrap().timestamp,
+                track.get_latest_waypoint().timestamp
+            );
+        }
+        let lagged_waypoint = fixed_lag_smoother.lagged_waypoint().unwrap();
+        assert_eq!(
+            lagged_waypoint.timestamp,
+            Utc.timestamp_nanos(0) + Duration::seconds(3)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::Duration;
+use nalgebra::{DMatrix, SMatrix};
+
+use crate::estimator::{EstimationError, Filter, Predictor};
+use crate::linalg::{lower_triangularize, sqrt_psd, to_dynamic};
+use crate::model::{LinearMeasurementModel, LinearTransitionModel};
+use crate::state::{GaussianState, Measurement};
+use crate::track::Track;
+
+/// # Explanation
+/// The SquareRootKalmanFilter computes the same estimates as the KalmanFilter, but it works with square
+/// roots S of the errors (S * S^T = error) instead of the errors themselves. The square roots are updated
+/// with qr decompositions of the stacked square roots, so the resulting errors are always symmetric and
+/// positive semi-definite and the innovation error never has to be inverted.
+///
+/// Since the track stores the errors, their square roots are computed at the beginning of every step.
+pub struct SquareRootKalmanFilter<const MD: usize, const SD: usize, TModel, MModel> {
+    transition_model: TModel,
+    measurement_model: MModel,
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel>
+    SquareRootKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: LinearTransitionModel<SD>,
+    MModel: LinearMeasurementModel<MD, SD>,
+{
+    pub fn new(transition_model: TModel, measurement_model: MModel) -> Self {
+        Self {
+            transition_model,
+            measurement_model,
+        }
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> Predictor<SD>
+    for SquareRootKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: LinearTransitionModel<SD>,
+    MModel: LinearMeasurementModel<MD, SD>,
+{
+    fn predict(
+        &self,
+        track: &Track<SD>,
+        dt: Duration,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        let prior = &track.get_latest_waypoint().state;
+        let transition_matrix = self.transition_model.transition_matrix(dt);
+        let sqrt_transition_error = sqrt_psd(&self.transition_model.transition_error(dt));
+
+        // | F S  sqrt(Q) |
+        let mut stacked = DMatrix::<f64>::zeros(SD, 2 * SD);
+        stacked
+            .view_mut((0, 0), (SD, SD))
+            .copy_from(&to_dynamic(&(transition_matrix * sqrt_psd(&prior.error))));
+        stacked
+            .view_mut((0, SD), (SD, SD))
+            .copy_from(&to_dynamic(&sqrt_transition_error));
+        let sqrt_error =
+            SMatrix::<f64, SD, SD>::from_column_slice(lower_triangularize(stacked).as_slice());
+
+        Ok(GaussianState::new(
+            transition_matrix * prior.estimate,
+            sqrt_error * sqrt_error.transpose(),
+        ))
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> Filter<MD, SD>
+    for SquareRootKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: LinearTransitionModel<SD>,
+    MModel: LinearMeasurementModel<MD, SD>,
+{
+    fn filter(
+        &self,
+        prediction: GaussianState<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        let measurement_matrix = self.measurement_model.measurement_matrix();
+        let sqrt_prediction_error = sqrt_psd(&prediction.error);
+        let sqrt_measurement_error = sqrt_psd(&self.measurement_model.measurement_error());
+
+        // | sqrt(R)  H S |    | sqrt(innovation error)  0             |
+        // | 0        S   | -> | K sqrt(innovation error) sqrt(filtered) |
+        let mut stacked = DMatrix::<f64>::zeros(MD + SD, MD + SD);
+        stacked
+            .view_mut((0, 0), (MD, MD))
+            .copy_from(&to_dynamic(&sqrt_measurement_error));
+        stacked
+            .view_mut((0, MD), (MD, SD))
+            .copy_from(&to_dynamic(&(measurement_matrix * sqrt_prediction_error)));
+        stacked
+            .view_mut((MD, MD), (SD, SD))
+            .copy_from(&to_dynamic(&sqrt_prediction_error));
+        let triangular = lower_triangularize(stacked);
+
+        let sqrt_innovation_error = triangular.view((0, 0), (MD, MD)).into_owned();
+        let scaled_kalman_gain = triangular.view((MD, 0), (SD, MD)).into_owned();
+        let sqrt_filter_error = triangular.view((MD, MD), (SD, SD)).into_owned();
+
+        // K = scaled K * sqrt(innovation error)^-1
+        let kalman_gain_transposed = sqrt_innovation_error
+            .transpose()
+            .solve_upper_triangular(&scaled_kalman_gain.transpose())
+            .ok_or_else(|| {
+                EstimationError::NumericalError(
+                    "the square root of the innovation error is singular".to_string(),
+                )
+            })?;
+        let kalman_gain = SMatrix::<f64, SD, MD>::from_column_slice(
+            kalman_gain_transposed.transpose().as_slice(),
+        );
+        let sqrt_filter_error =
+            SMatrix::<f64, SD, SD>::from_column_slice(sqrt_filter_error.as_slice());
+
+        let innovation = measurement.vector - measurement_matrix * prediction.estimate;
+        Ok(GaussianState::new(
+            prediction.estimate + kalman_gain * innovation,
+            sqrt_filter_error * sqrt_filter_error.transpose(),
+        ))
+    }
+}
@@ -0,0 +1,298 @@
+use chrono::Duration;
+use nalgebra::{SMatrix, SVector};
+use serde::{Deserialize, Serialize};
+
+use crate::estimator::{EstimationError, Filter, Predictor};
+use crate::kalman::diagnostics::{DiagnosticFilter, UpdateDiagnostics};
+use crate::kalman::outlier::{robust_innovation_error, OutlierGate, OutlierHandling};
+use crate::model::{NonlinearMeasurementModel, NonlinearTransitionModel};
+use crate::state::{GaussianState, Measurement};
+use crate::track::Track;
+
+/// # Explanation
+/// The parameters of the unscented transform.
+///
+/// # Parameters
+/// alpha determines the spread of the sigma points around the mean (a value in (0, 1]).
+/// beta incorporates prior knowledge of the distribution (two is optimal for gaussian distributions).
+/// kappa is a secondary scaling parameter (usually zero).
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct SigmaPointParameters {
+    pub alpha: f64,
+    pub beta: f64,
+    pub kappa: f64,
+}
+
+impl SigmaPointParameters {
+    pub fn new(alpha: f64, beta: f64, kappa: f64) -> Self {
+        Self { alpha, beta, kappa }
+    }
+
+    /// # Explanation
+    /// Checks that alpha is positive and that the sigma points of a state with the given dimension can be
+    /// scaled, ie n + lambda = alpha^2 * (n + kappa) is positive.
+    pub fn validate(&self, dimension: usize) -> Result<(), EstimationError> {
+        if self.alpha.is_nan() || self.alpha <= 0. {
+            return Err(EstimationError::InvalidParameter(format!(
+                "the alpha {} of the sigma points is not positive",
+                self.alpha
+            )));
+        }
+        let scale = dimension as f64 + self.lambda(dimension);
+        if scale.is_nan() || scale <= 0. {
+            return Err(EstimationError::InvalidParameter(format!(
+                "the scale n + lambda = {} of the sigma points is not positive (kappa of {})",
+                scale, self.kappa
+            )));
+        }
+        Ok(())
+    }
+
+    fn lambda(&self, dimension: usize) -> f64 {
+        let n = dimension as f64;
+        self.alpha.powi(2) * (n + self.kappa) - n
+    }
+
+    /// # Returns
+    /// Returns the weights of the first sigma point (the mean) for the mean and the covariance and
+    /// the weight of all the other sigma points.
+    fn weights(&self, dimension: usize) -> (f64, f64, f64) {
+        let n = dimension as f64;
+        let lambda = self.lambda(dimension);
+
+        let mean_weight = lambda / (n + lambda);
+        let covariance_weight = mean_weight + 1. - self.alpha.powi(2) + self.beta;
+        let weight = 1. / (2. * (n + lambda));
+        (mean_weight, covariance_weight, weight)
+    }
+}
+
+/// # Explanation
+/// With alpha = 1 and kappa = 0 none of the weights is negative. A small alpha (like 1e-3) gives the first
+/// sigma point a weight of about -10^6, which makes the covariance lose its positive definiteness easily.
+impl Default for SigmaPointParameters {
+    fn default() -> Self {
+        Self::new(1., 2., 0.)
+    }
+}
+
+/// # Explanation
+/// The UnscentedKalmanFilter approximates the gaussian state with a set of deterministically chosen
+/// sigma points. These points are propagated through the nonlinear functions of the models and the
+/// mean and covariance are recovered from the transformed points. Unlike the ExtendedKalmanFilter the
+/// jacobians of the models are never needed.
+pub struct UnscentedKalmanFilter<const MD: usize, const SD: usize, TModel, MModel> {
+    transition_model: TModel,
+    measurement_model: MModel,
+    parameters: SigmaPointParameters,
+    outlier_gate: OutlierGate,
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> UnscentedKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    /// # Returns
+    /// Returns the filter or EstimationError::InvalidParameter if the sigma point parameters are invalid
+    /// (see SigmaPointParameters::validate).
+    pub fn new(
+        transition_model: TModel,
+        measurement_model: MModel,
+        parameters: SigmaPointParameters,
+    ) -> Result<Self, EstimationError> {
+        parameters.validate(SD)?;
+        Ok(Self {
+            transition_model,
+            measurement_model,
+            parameters,
+            outlier_gate: OutlierGate::new(OutlierHandling::AcceptAll, MD),
+        })
+    }
+
+    pub fn with_outlier_handling(mut self, outlier_handling: OutlierHandling) -> Self {
+        self.outlier_gate = OutlierGate::new(outlier_handling, MD);
+        self
+    }
+
+    /// # Returns
+    /// Returns the 2 * SD + 1 sigma points of the given state. The first sigma point is the mean.
+    fn sigma_points(
+        &self,
+        state: &GaussianState<SD>,
+    ) -> Result<Vec<SVector<f64, SD>>, EstimationError> {
+        let scale = SD as f64 + self.parameters.lambda(SD);
+        let sqrt_error = (scale * state.error)
+            .cholesky()
+            .ok_or_else(|| {
+                EstimationError::NumericalError(
+                    "the error has no cholesky decomposition".to_string(),
+                )
+            })?
+            .l();
+
+        let mut sigma_points = Vec::with_capacity(2 * SD + 1);
+        sigma_points.push(state.estimate);
+        for column in sqrt_error.column_iter() {
+            sigma_points.push(state.estimate + column);
+        }
+        for column in sqrt_error.column_iter() {
+            sigma_points.push(state.estimate - column);
+        }
+        Ok(sigma_points)
+    }
+
+    /// # Returns
+    /// Returns the weighted mean and covariance of the (transformed) sigma points.
+    fn recover<const D: usize>(&self, points: &[SVector<f64, D>]) -> GaussianState<D> {
+        let (mean_weight, covariance_weight, weight) = self.parameters.weights(SD);
+
+        let mean = points
+            .iter()
+            .skip(1)
+            .fold(mean_weight * points[0], |mean, point| mean + weight * point);
+
+        let first_diff = points[0] - mean;
+        let covariance = points.iter().skip(1).fold(
+            covariance_weight * first_diff * first_diff.transpose(),
+            |covariance, point| {
+                let diff = point - mean;
+                covariance + weight * diff * diff.transpose()
+            },
+        );
+
+        GaussianState::new(mean, covariance)
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> Predictor<SD>
+    for UnscentedKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    fn predict(
+        &self,
+        track: &Track<SD>,
+        dt: Duration,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        let prior = &track.get_latest_waypoint().state;
+        let transformed: Vec<_> = self
+            .sigma_points(prior)?
+            .iter()
+            .map(|point| self.transition_model.transition(point, dt))
+            .collect();
+
+        let prediction = self.recover(&transformed);
+        Ok(GaussianState::new(
+            prediction.estimate,
+            prediction.error + self.transition_model.transition_error(dt),
+        ))
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> Filter<MD, SD>
+    for UnscentedKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    fn filter(
+        &self,
+        prediction: GaussianState<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        self.filter_with_diagnostics(prediction, measurement)
+            .map(|(filtered, _)| filtered)
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel> DiagnosticFilter<MD, SD>
+    for UnscentedKalmanFilter<MD, SD, TModel, MModel>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+{
+    fn filter_with_diagnostics(
+        &self,
+        prediction: GaussianState<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<(GaussianState<SD>, UpdateDiagnostics<MD, SD>), EstimationError> {
+        let sigma_points = self.sigma_points(&prediction)?;
+        let measured: Vec<_> = sigma_points
+            .iter()
+            .map(|point| self.measurement_model.measure(point))
+            .collect();
+
+        let expected = self.recover(&measured);
+        let innovation = measurement.vector - expected.estimate;
+        let (innovation_error, innovation_error_inverse) = robust_innovation_error(
+            &self.outlier_gate,
+            &innovation,
+            expected.error,
+            self.measurement_model.measurement_error(),
+        )?;
+
+        let (_, covariance_weight, weight) = self.parameters.weights(SD);
+        let cross_covariance = sigma_points.iter().zip(measured.iter()).enumerate().fold(
+            SMatrix::<f64, SD, MD>::zeros(),
+            |cross_covariance, (i, (point, measured_point))| {
+                let weight = if i == 0 { covariance_weight } else { weight };
+                cross_covariance
+                    + weight
+                        * (point - prediction.estimate)
+                        * (measured_point - expected.estimate).transpose()
+            },
+        );
+
+        let kalman_gain = cross_covariance * innovation_error_inverse;
+
+        let filtered_estimate = prediction.estimate + kalman_gain * innovation;
+        let filter_error =
+            prediction.error - kalman_gain * innovation_error * kalman_gain.transpose();
+        Ok((
+            GaussianState::new(filtered_estimate, filter_error),
+            UpdateDiagnostics::new(
+                innovation,
+                innovation_error,
+                &innovation_error_inverse,
+                kalman_gain,
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::estimator::EstimationError;
+    use crate::kalman::model::{CoordinatedTurn, PositionMeasurementModel};
+    use crate::kalman::ukf::{SigmaPointParameters, UnscentedKalmanFilter};
+
+    #[test]
+    fn test_sigma_point_parameters() {
+        assert!(SigmaPointParameters::default().validate(5).is_ok());
+        assert!(SigmaPointParameters::new(0.5, 2., 0.).validate(5).is_ok());
+
+        // the sigma points need a positive alpha and a positive scale n + lambda
+        let invalid_parameters = [
+            SigmaPointParameters::new(0., 2., 0.),
+            SigmaPointParameters::new(-0.5, 2., 0.),
+            SigmaPointParameters::new(f64::NAN, 2., 0.),
+            SigmaPointParameters::new(1., 2., -5.),
+        ];
+        for parameters in invalid_parameters {
+            assert!(matches!(
+                parameters.validate(5),
+                Err(EstimationError::InvalidParameter(_))
+            ));
+            assert!(matches!(
+                UnscentedKalmanFilter::new(
+                    CoordinatedTurn::new(1.0, 1.0),
+                    PositionMeasurementModel::new(0.1, 0.1),
+                    parameters,
+                ),
+                Err(EstimationError::InvalidParameter(_))
+            ));
+        }
+    }
+}
@@ -1,5 +1,17 @@
+pub mod bounded;
+pub mod config;
+pub mod delayed;
+pub mod dynamic;
 pub mod estimator;
+pub mod evaluation;
+pub mod export;
 pub mod kalman;
+mod linalg;
+pub mod metrics;
 pub mod model;
+pub mod particle;
+pub mod plot;
+pub mod serialization;
+pub mod sim;
 pub mod state;
 pub mod track;
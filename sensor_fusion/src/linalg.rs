@@ -0,0 +1,83 @@
+use std::f64::consts::PI;
+
+use nalgebra::{DMatrix, DVector, Matrix2, SMatrix, SVector, Vector2};
+
+use crate::estimator::EstimationError;
+
+/// # Returns
+/// Returns a matrix S with S * S^T = matrix for a positive semi-definite matrix. Unlike the cholesky
+/// decomposition this also works for singular matrices (like the error of the constant velocity model).
+pub(crate) fn sqrt_psd<const D: usize>(matrix: &SMatrix<f64, D, D>) -> SMatrix<f64, D, D> {
+    // the eigen decomposition is not available for const generic dimensions
+    let eigen = to_dynamic(matrix).symmetric_eigen();
+    let sqrt_eigenvalues = eigen
+        .eigenvalues
+        .map(|eigenvalue| eigenvalue.max(0.).sqrt());
+    let sqrt = eigen.eigenvectors * DMatrix::from_diagonal(&sqrt_eigenvalues);
+    SMatrix::<f64, D, D>::from_column_slice(sqrt.as_slice())
+}
+
+/// # Returns
+/// Returns the lower triangular n x n matrix L with L * L^T = matrix * matrix^T for a n x m matrix with
+/// m >= n (computed with the qr decomposition of the transposed matrix).
+pub(crate) fn lower_triangularize(matrix: DMatrix<f64>) -> DMatrix<f64> {
+    matrix.transpose().qr().r().transpose()
+}
+
+pub(crate) fn to_dynamic<const R: usize, const C: usize>(
+    matrix: &SMatrix<f64, R, C>,
+) -> DMatrix<f64> {
+    DMatrix::from_column_slice(R, C, matrix.as_slice())
+}
+
+/// # Returns
+/// Returns the matrix with static dimensions. EstimationError::DimensionMismatch is returned if the matrix
+/// is not a R x C matrix.
+pub(crate) fn to_static<const R: usize, const C: usize>(
+    matrix: &DMatrix<f64>,
+) -> Result<SMatrix<f64, R, C>, EstimationError> {
+    check_dimension(R, matrix.nrows())?;
+    check_dimension(C, matrix.ncols())?;
+    Ok(SMatrix::<f64, R, C>::from_column_slice(matrix.as_slice()))
+}
+
+pub(crate) fn to_static_vector<const D: usize>(
+    vector: &DVector<f64>,
+) -> Result<SVector<f64, D>, EstimationError> {
+    check_dimension(D, vector.len())?;
+    Ok(SVector::<f64, D>::from_column_slice(vector.as_slice()))
+}
+
+pub(crate) fn check_dimension(expected: usize, actual: usize) -> Result<(), EstimationError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(EstimationError::DimensionMismatch { expected, actual })
+    }
+}
+
+/// # Returns
+/// Returns the given number of points on the ellipse of the 2D covariance around the center. The semi-axes
+/// are sigma standard deviations long (along the eigenvectors of the covariance). The first point is
+/// repeated at the end, so the points form a closed ring.
+pub(crate) fn covariance_ellipse(
+    center: Vector2<f64>,
+    covariance: Matrix2<f64>,
+    sigma: f64,
+    points: usize,
+) -> Vec<Vector2<f64>> {
+    let eigen = covariance.symmetric_eigen();
+    let axes = eigen.eigenvectors
+        * Matrix2::from_diagonal(
+            &eigen
+                .eigenvalues
+                .map(|eigenvalue| eigenvalue.max(0.).sqrt()),
+        )
+        * sigma;
+    (0..=points)
+        .map(|i| {
+            let angle = 2. * PI * (i % points) as f64 / points as f64;
+            center + axes * Vector2::new(angle.cos(), angle.sin())
+        })
+        .collect()
+}
@@ -0,0 +1,343 @@
+use nalgebra::{Rotation2, SVector, Vector2};
+
+use crate::evaluation::EvaluationError;
+use crate::track::Track;
+
+/// # Explanation
+/// How the estimated track is aligned to the ground truth before the absolute trajectory error is
+/// computed. Rigid finds the rotation and translation that minimize the squared position errors, which
+/// removes errors of the initial position and heading (eg a map frame that is rotated against the frame
+/// of the ground truth).
+#[derive(Debug, Copy, Clone, Default)]
+pub enum Alignment {
+    #[default]
+    None,
+    Rigid,
+}
+
+/// # Returns
+/// Returns the distances between the positions of the ground truth and the track for every waypoint of
+/// the track that lies within the time span of the ground truth. The ground truth is interpolated at the
+/// timestamps of the waypoints (see Track::state_at). The position is made up of the first two entries of
+/// the state.
+pub fn position_errors<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+) -> Result<Vec<f64>, EvaluationError> {
+    let errors: Vec<f64> = matched_positions(ground_truth, track)
+        .into_iter()
+        .map(|(true_position, position)| (position - true_position).norm())
+        .collect();
+    if errors.is_empty() {
+        Err(EvaluationError::NoMatchingWaypoints)
+    } else {
+        Ok(errors)
+    }
+}
+
+/// # Returns
+/// Returns the root mean squared position error.
+pub fn rmse<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+) -> Result<f64, EvaluationError> {
+    Ok(root_mean_square(&position_errors(ground_truth, track)?))
+}
+
+pub fn mean_position_error<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+) -> Result<f64, EvaluationError> {
+    let errors = position_errors(ground_truth, track)?;
+    Ok(errors.iter().sum::<f64>() / errors.len() as f64)
+}
+
+pub fn median_position_error<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+) -> Result<f64, EvaluationError> {
+    let mut errors = position_errors(ground_truth, track)?;
+    errors.sort_by(f64::total_cmp);
+    let middle = errors.len() / 2;
+    if errors.len() % 2 == 0 {
+        Ok((errors[middle - 1] + errors[middle]) / 2.)
+    } else {
+        Ok(errors[middle])
+    }
+}
+
+pub fn max_position_error<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+) -> Result<f64, EvaluationError> {
+    Ok(position_errors(ground_truth, track)?
+        .into_iter()
+        .fold(0., f64::max))
+}
+
+/// # Returns
+/// Returns the absolute trajectory error (ATE), ie the root mean squared position error after the
+/// positions of the track were aligned to the ground truth.
+pub fn absolute_trajectory_error<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+    alignment: Alignment,
+) -> Result<f64, EvaluationError> {
+    let positions = matched_positions(ground_truth, track);
+    if positions.is_empty() {
+        return Err(EvaluationError::NoMatchingWaypoints);
+    }
+
+    let (rotation, translation) = match alignment {
+        Alignment::None => (Rotation2::identity(), Vector2::zeros()),
+        Alignment::Rigid => rigid_alignment(&positions),
+    };
+    let errors: Vec<f64> = positions
+        .iter()
+        .map(|(true_position, position)| (rotation * position + translation - true_position).norm())
+        .collect();
+    Ok(root_mean_square(&errors))
+}
+
+/// # Returns
+/// Returns the relative pose error (RPE) over windows of the given distance (in meters). For every matched
+/// waypoint the window ends at the first matched waypoint at which the ground truth has travelled at least
+/// the distance. The error of a window is the difference between the displacements of the track and of
+/// the ground truth, so a constant offset of the track does not count. The root mean square of the errors
+/// of all windows is returned.
+///
+/// EvaluationError::TrackTooShort is returned if the ground truth never travels the distance.
+pub fn relative_pose_error<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+    distance: f64,
+) -> Result<f64, EvaluationError> {
+    let positions = matched_positions(ground_truth, track);
+    if positions.is_empty() {
+        return Err(EvaluationError::NoMatchingWaypoints);
+    }
+
+    // travelled distance of the ground truth up to each matched waypoint
+    let travelled: Vec<f64> = positions
+        .iter()
+        .scan((0., positions[0].0), |(sum, last), (true_position, _)| {
+            *sum += (true_position - *last).norm();
+            *last = *true_position;
+            Some(*sum)
+        })
+        .collect();
+
+    let errors: Vec<f64> = (0..positions.len())
+        .filter_map(|start| {
+            let end = start
+                + travelled[start..].partition_point(|travelled_distance| {
+                    travelled_distance - travelled[start] < distance
+                });
+            let (true_start, start_position) = positions[start];
+            let (true_end, end_position) = positions.get(end)?;
+            Some(((end_position - start_position) - (true_end - true_start)).norm())
+        })
+        .collect();
+    if errors.is_empty() {
+        Err(EvaluationError::TrackTooShort(distance))
+    } else {
+        Ok(root_mean_square(&errors))
+    }
+}
+
+/// # Returns
+/// Returns the root mean squared velocity error. Since the velocity is stored differently in the states of
+/// the models, to_velocity extracts the velocity (vx, vy) of a state.
+pub fn velocity_error<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+    to_velocity: impl Fn(&SVector<f64, D>) -> Vector2<f64>,
+) -> Result<f64, EvaluationError> {
+    let errors: Vec<f64> = sampled_estimates(ground_truth, track)
+        .into_iter()
+        .map(|(true_estimate, estimate)| {
+            (to_velocity(&estimate) - to_velocity(&true_estimate)).norm()
+        })
+        .collect();
+    if errors.is_empty() {
+        Err(EvaluationError::NoMatchingWaypoints)
+    } else {
+        Ok(root_mean_square(&errors))
+    }
+}
+
+/// # Returns
+/// Returns the pairs of estimates (ground truth, track) for every waypoint of the track. The ground truth
+/// is sampled at the timestamp of the waypoint, so the tracks do not need to share their timestamps.
+/// Waypoints before the first or after the latest waypoint of the ground truth are skipped.
+fn sampled_estimates<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+) -> Vec<(SVector<f64, D>, SVector<f64, D>)> {
+    track
+        .iter()
+        .filter_map(|waypoint| {
+            ground_truth
+                .state_at(waypoint.timestamp)
+                .map(|true_state| (true_state.estimate, waypoint.state.estimate))
+        })
+        .collect()
+}
+
+/// # Returns
+/// Returns the pairs of positions (ground truth, track) of the sampled estimates.
+fn matched_positions<const D: usize>(
+    ground_truth: &Track<D>,
+    track: &Track<D>,
+) -> Vec<(Vector2<f64>, Vector2<f64>)> {
+    sampled_estimates(ground_truth, track)
+        .into_iter()
+        .map(|(true_estimate, estimate)| (position(&true_estimate), position(&estimate)))
+        .collect()
+}
+
+fn position<const D: usize>(state: &SVector<f64, D>) -> Vector2<f64> {
+    Vector2::new(state[0], state[1])
+}
+
+/// # Returns
+/// Returns the rotation and translation that map the positions of the track onto the positions of the
+/// ground truth with the least squared error (the two dimensional case of the Kabsch algorithm).
+fn rigid_alignment(positions: &[(Vector2<f64>, Vector2<f64>)]) -> (Rotation2<f64>, Vector2<f64>) {
+    let n = positions.len() as f64;
+    let true_centroid = positions
+        .iter()
+        .fold(Vector2::zeros(), |sum, (true_position, _)| {
+            sum + true_position
+        })
+        / n;
+    let centroid = positions
+        .iter()
+        .fold(Vector2::zeros(), |sum, (_, position)| sum + position)
+        / n;
+
+    let (sin, cos) = positions
+        .iter()
+        .fold((0., 0.), |(sin, cos), (true_position, position)| {
+            let p = true_position - true_centroid;
+            let q = position - centroid;
+            (sin + q.x * p.y - q.y * p.x, cos + q.x * p.x + q.y * p.y)
+        });
+    let rotation = Rotation2::new(f64::atan2(sin, cos));
+    (rotation, true_centroid - rotation * centroid)
+}
+
+fn root_mean_square(values: &[f64]) -> f64 {
+    (values.iter().map(|value| value * value).sum::<f64>() / values.len() as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use nalgebra::{Rotation2, SVector, Vector2, Vector4};
+
+    use crate::evaluation::EvaluationError;
+    use crate::metrics;
+    use crate::metrics::Alignment;
+    use crate::sim::trajectory::{FigureEight, Kinematics, Trajectory};
+    use crate::track::Track;
+
+    const INTERVAL: Duration = Duration::milliseconds(100);
+
+    fn start() -> DateTime<Utc> {
+        Utc.timestamp_nanos(0)
+    }
+
+    fn figure_eight() -> FigureEight {
+        FigureEight::new(2., 2., 2. * PI, 10.)
+    }
+
+    /// # Returns
+    /// Returns a track of every second waypoint of the figure eight that is rotated and then translated.
+    fn transformed_track(rotation: Rotation2<f64>, translation: Vector2<f64>) -> Track<4> {
+        let transformed = figure_eight()
+            .ground_truth(start(), INTERVAL, |kinematics| {
+                let position = rotation * kinematics.position + translation;
+                let velocity = rotation * kinematics.velocity;
+                Vector4::new(position.x, position.y, velocity.x, velocity.y)
+            })
+            .unwrap();
+        // the track does not need to contain every timestamp of the ground truth
+        let mut waypoints = transformed.into_iter().step_by(2);
+        let mut track = Track::new(waypoints.next().unwrap());
+        waypoints.for_each(|waypoint| track.add_waypoint(waypoint));
+        track
+    }
+
+    fn to_velocity(state: &SVector<f64, 4>) -> Vector2<f64> {
+        Vector2::new(state[2], state[3])
+    }
+
+    #[test]
+    fn test_shifted_track() {
+        let ground_truth = figure_eight()
+            .ground_truth(start(), INTERVAL, Kinematics::constant_velocity_state)
+            .unwrap();
+        let shifted = transformed_track(Rotation2::identity(), Vector2::new(1., -2.));
+        let offset = 5f64.sqrt();
+
+        assert_eq!(
+            metrics::position_errors(&ground_truth, &shifted)
+                .unwrap()
+                .len(),
+            shifted.len()
+        );
+        assert!((metrics::rmse(&ground_truth, &shifted).unwrap() - offset).abs() < 1e-9);
+        assert!(
+            (metrics::median_position_error(&ground_truth, &shifted).unwrap() - offset).abs()
+                < 1e-9
+        );
+        assert!(metrics::relative_pose_error(&ground_truth, &shifted, 1.).unwrap() < 1e-9);
+        assert!(metrics::velocity_error(&ground_truth, &shifted, to_velocity).unwrap() < 1e-9);
+
+        // a ground truth without the timestamps of the track is interpolated
+        let coarse_ground_truth = ground_truth.resample(Duration::milliseconds(250)).unwrap();
+        let sampled_errors = metrics::position_errors(&coarse_ground_truth, &shifted).unwrap();
+        let end = coarse_ground_truth.get_latest_waypoint().timestamp;
+        assert_eq!(
+            sampled_errors.len(),
+            shifted
+                .iter()
+                .filter(|waypoint| waypoint.timestamp <= end)
+                .count()
+        );
+        assert!(sampled_errors
+            .iter()
+            .all(|error| (error - offset).abs() < 0.05));
+        assert!(
+            metrics::absolute_trajectory_error(&coarse_ground_truth, &shifted, Alignment::Rigid)
+                .unwrap()
+                < 0.05
+        );
+    }
+
+    #[test]
+    fn test_rotated_track() {
+        let ground_truth = figure_eight()
+            .ground_truth(start(), INTERVAL, Kinematics::constant_velocity_state)
+            .unwrap();
+        let rotated = transformed_track(Rotation2::new(PI / 6.), Vector2::new(1., -2.));
+
+        assert!(
+            metrics::absolute_trajectory_error(&ground_truth, &rotated, Alignment::None).unwrap()
+                > 1.
+        );
+        assert!(
+            metrics::absolute_trajectory_error(&ground_truth, &rotated, Alignment::Rigid).unwrap()
+                < 1e-9
+        );
+        assert!(metrics::relative_pose_error(&ground_truth, &rotated, 1.).unwrap() > 0.1);
+        assert!(metrics::velocity_error(&ground_truth, &rotated, to_velocity).unwrap() > 0.1);
+        assert!(matches!(
+            metrics::relative_pose_error(&ground_truth, &rotated, 1000.),
+            Err(EvaluationError::TrackTooShort(_))
+        ));
+    }
+}
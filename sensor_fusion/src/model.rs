@@ -1,5 +1,7 @@
+use std::cell::Cell;
+
 use chrono::Duration;
-use nalgebra::SMatrix;
+use nalgebra::{SMatrix, SVector};
 
 /// # Explanation
 /// The LinearTransitionModel should contain the transition model (so the transition matrix and the error matrix).
@@ -35,4 +37,166 @@ pub trait LinearMeasurementModel<const MD: usize, const SD: usize> {
     /// # Returns
     /// Returns the sensors error.
     fn measurement_error(&self) -> SMatrix<f64, MD, MD>;
-}
\ No newline at end of file
+}
+
+/// # Explanation
+/// The NonlinearTransitionModel describes a transition that cannot be written as a matrix
+/// multiplication (eg a motion that depends on the heading). Instead of a transition matrix the
+/// model provides the transition function itself and its jacobian, which is the linearization of
+/// the transition function around a given state.
+///
+/// Every LinearTransitionModel is also a NonlinearTransitionModel.
+///
+/// # Type parameters
+/// SD is the dimension of the state.
+pub trait NonlinearTransitionModel<const SD: usize> {
+    /// # Returns
+    /// Returns the state after dt has passed when the state was the given one.
+    fn transition(&self, state: &SVector<f64, SD>, dt: Duration) -> SVector<f64, SD>;
+
+    /// # Returns
+    /// Returns the jacobian of the transition function evaluated at the given state.
+    fn transition_jacobian(&self, state: &SVector<f64, SD>, dt: Duration) -> SMatrix<f64, SD, SD>;
+
+    /// # Returns
+    /// Returns the error matrix when dt is the time that has passed since the last
+    /// sensors.
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, SD, SD>;
+}
+
+impl<const SD: usize, T> NonlinearTransitionModel<SD> for T
+where
+    T: LinearTransitionModel<SD>,
+{
+    fn transition(&self, state: &SVector<f64, SD>, dt: Duration) -> SVector<f64, SD> {
+        self.transition_matrix(dt) * state
+    }
+
+    fn transition_jacobian(&self, _: &SVector<f64, SD>, dt: Duration) -> SMatrix<f64, SD, SD> {
+        self.transition_matrix(dt)
+    }
+
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, SD, SD> {
+        LinearTransitionModel::transition_error(self, dt)
+    }
+}
+
+/// # Explanation
+/// The NonlinearMeasurementModel transforms the state into the sensors space with a function
+/// (eg a range/bearing sensor). The jacobian is the linearization of this function around a given state.
+///
+/// Every LinearMeasurementModel is also a NonlinearMeasurementModel.
+///
+/// # Type parameters
+/// SD is the dimension of the state. MD is the dimension of the sensors vectors.
+pub trait NonlinearMeasurementModel<const MD: usize, const SD: usize> {
+    /// # Returns
+    /// Returns the sensors vector that is expected when the object is in the given state.
+    fn measure(&self, state: &SVector<f64, SD>) -> SVector<f64, MD>;
+
+    /// # Returns
+    /// Returns the jacobian of the measure function evaluated at the given state.
+    fn measurement_jacobian(&self, state: &SVector<f64, SD>) -> SMatrix<f64, MD, SD>;
+
+    /// # Returns
+    /// Returns the sensors error.
+    fn measurement_error(&self) -> SMatrix<f64, MD, MD>;
+}
+
+impl<const MD: usize, const SD: usize, T> NonlinearMeasurementModel<MD, SD> for T
+where
+    T: LinearMeasurementModel<MD, SD>,
+{
+    fn measure(&self, state: &SVector<f64, SD>) -> SVector<f64, MD> {
+        self.measurement_matrix() * state
+    }
+
+    fn measurement_jacobian(&self, _: &SVector<f64, SD>) -> SMatrix<f64, MD, SD> {
+        self.measurement_matrix()
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, MD, MD> {
+        LinearMeasurementModel::measurement_error(self)
+    }
+}
+
+/// # Explanation
+/// The ControlledTransitionModel describes a transition that also depends on a known control input
+/// (eg the motor commands of the robot). Like the NonlinearTransitionModel it provides the transition
+/// function and its jacobian (with respect to the state).
+///
+/// # Type parameters
+/// SD is the dimension of the state. CD is the dimension of the control input.
+pub trait ControlledTransitionModel<const SD: usize, const CD: usize> {
+    /// # Returns
+    /// Returns the state after dt has passed when the state was the given one and the given control
+    /// input was applied.
+    fn transition(
+        &self,
+        state: &SVector<f64, SD>,
+        control: &SVector<f64, CD>,
+        dt: Duration,
+    ) -> SVector<f64, SD>;
+
+    /// # Returns
+    /// Returns the jacobian of the transition function (with respect to the state) evaluated at the
+    /// given state and control input.
+    fn transition_jacobian(
+        &self,
+        state: &SVector<f64, SD>,
+        control: &SVector<f64, CD>,
+        dt: Duration,
+    ) -> SMatrix<f64, SD, SD>;
+
+    /// # Returns
+    /// Returns the error matrix when dt is the time that has passed since the last
+    /// sensors.
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, SD, SD>;
+}
+
+/// # Explanation
+/// The ControlInput turns a ControlledTransitionModel into a NonlinearTransitionModel by remembering the
+/// latest control input. So every filter that works with nonlinear models can also use the control input.
+/// The control input should be updated (with set_control) whenever a new command is applied.
+pub struct ControlInput<TModel, const CD: usize> {
+    model: TModel,
+    control: Cell<SVector<f64, CD>>,
+}
+
+impl<TModel, const CD: usize> ControlInput<TModel, CD> {
+    /// # Explanation
+    /// The control input is zero until set_control is called.
+    pub fn new(model: TModel) -> Self {
+        Self {
+            model,
+            control: Cell::new(SVector::<f64, CD>::zeros()),
+        }
+    }
+
+    pub fn set_control(&self, control: SVector<f64, CD>) {
+        self.control.set(control);
+    }
+
+    pub fn get_control(&self) -> SVector<f64, CD> {
+        self.control.get()
+    }
+}
+
+impl<const SD: usize, const CD: usize, TModel> NonlinearTransitionModel<SD>
+    for ControlInput<TModel, CD>
+where
+    TModel: ControlledTransitionModel<SD, CD>,
+{
+    fn transition(&self, state: &SVector<f64, SD>, dt: Duration) -> SVector<f64, SD> {
+        self.model.transition(state, &self.control.get(), dt)
+    }
+
+    fn transition_jacobian(&self, state: &SVector<f64, SD>, dt: Duration) -> SMatrix<f64, SD, SD> {
+        self.model
+            .transition_jacobian(state, &self.control.get(), dt)
+    }
+
+    fn transition_error(&self, dt: Duration) -> SMatrix<f64, SD, SD> {
+        self.model.transition_error(dt)
+    }
+}
@@ -0,0 +1,312 @@
+use std::cell::RefCell;
+
+use chrono::{DateTime, Utc};
+use nalgebra::{SMatrix, SVector};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+
+use crate::estimator::{time_since_latest_waypoint, EstimationError, Estimator};
+use crate::linalg::sqrt_psd;
+use crate::model::{NonlinearMeasurementModel, NonlinearTransitionModel};
+use crate::particle::resampling::Resampler;
+use crate::state::{GaussianState, Measurement, Waypoint};
+use crate::track::Track;
+
+/// # Explanation
+/// A particle is one hypothesis of the state together with its (normalized) weight.
+#[derive(Debug, Clone)]
+pub struct Particle<const SD: usize> {
+    pub state: SVector<f64, SD>,
+    pub weight: f64,
+}
+
+impl<const SD: usize> Particle<SD> {
+    pub fn new(state: SVector<f64, SD>, weight: f64) -> Self {
+        Self { state, weight }
+    }
+}
+
+/// # Explanation
+/// The ParticleFilter represents the posterior by a cloud of weighted particles, so (unlike the kalman
+/// filters) it can represent posteriors that are not gaussian (eg multimodal ones).
+/// Every particle is moved with the transition function plus random noise drawn from the transition
+/// error and then weighted with the likelihood of the measurement.
+/// When the effective sample size drops below resampling_threshold * number_of_particles the particles
+/// are resampled with the given resampler (and regularized afterwards).
+///
+/// The track only stores the gaussian summary (weighted mean and covariance) of the particle cloud, so the
+/// filter keeps the cloud of its latest estimate. The cloud is only used if it belongs to the latest
+/// waypoint of the track (same timestamp and state), otherwise (eg for another track or when a
+/// measurement is estimated again) the particles are drawn from the state of the latest waypoint. A failed
+/// estimate does not change the cloud.
+pub struct ParticleFilter<const MD: usize, const SD: usize, TModel, MModel, R> {
+    transition_model: TModel,
+    measurement_model: MModel,
+    resampler: R,
+    number_of_particles: usize,
+    resampling_threshold: f64,
+    cloud: RefCell<Option<ParticleCloud<SD>>>,
+    rng: RefCell<StdRng>,
+}
+
+/// # Explanation
+/// The particles that belong to the waypoint with the given timestamp and (summarized) state.
+struct ParticleCloud<const SD: usize> {
+    timestamp: DateTime<Utc>,
+    state: GaussianState<SD>,
+    particles: Vec<Particle<SD>>,
+}
+
+impl<const SD: usize> ParticleCloud<SD> {
+    fn belongs_to(&self, waypoint: &Waypoint<SD>) -> bool {
+        self.timestamp == waypoint.timestamp
+            && self.state.estimate == waypoint.state.estimate
+            && self.state.error == waypoint.state.error
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel, R> ParticleFilter<MD, SD, TModel, MModel, R>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+    R: Resampler,
+{
+    /// # Returns
+    /// Returns the particle filter or EstimationError::InvalidParameter if the number of particles is zero
+    /// or the resampling threshold is not in [0, 1].
+    pub fn new(
+        transition_model: TModel,
+        measurement_model: MModel,
+        resampler: R,
+        number_of_particles: usize,
+        resampling_threshold: f64,
+    ) -> Result<Self, EstimationError> {
+        if number_of_particles == 0 {
+            return Err(EstimationError::InvalidParameter(
+                "a particle filter needs at least one particle".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&resampling_threshold) {
+            return Err(EstimationError::InvalidParameter(format!(
+                "the resampling threshold {} is not in [0, 1]",
+                resampling_threshold
+            )));
+        }
+
+        Ok(Self {
+            transition_model,
+            measurement_model,
+            resampler,
+            number_of_particles,
+            resampling_threshold,
+            cloud: RefCell::new(None),
+            rng: RefCell::new(StdRng::from_entropy()),
+        })
+    }
+
+    /// # Explanation
+    /// Seeds the random number generator, so that the estimates can be reproduced.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = RefCell::new(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// # Returns
+    /// Returns a copy of the particle cloud of the latest estimate (empty before the first estimate).
+    pub fn particles(&self) -> Vec<Particle<SD>> {
+        self.cloud
+            .borrow()
+            .as_ref()
+            .map(|cloud| cloud.particles.clone())
+            .unwrap_or_default()
+    }
+
+    /// # Returns
+    /// Returns the effective sample size of the particle cloud of the latest estimate.
+    pub fn effective_sample_size(&self) -> f64 {
+        effective_sample_size(&self.particles())
+    }
+
+    /// # Returns
+    /// Returns the particles of the latest waypoint: the cloud of the latest estimate if it belongs to the
+    /// waypoint, otherwise particles drawn from the state of the waypoint.
+    fn particles_of(&self, waypoint: &Waypoint<SD>) -> Vec<Particle<SD>> {
+        if let Some(cloud) = self.cloud.borrow().as_ref() {
+            if cloud.belongs_to(waypoint) {
+                return cloud.particles.clone();
+            }
+        }
+
+        let sqrt_error = sqrt_psd(&waypoint.state.error);
+        let weight = 1. / self.number_of_particles as f64;
+        let mut rng = self.rng.borrow_mut();
+        (0..self.number_of_particles)
+            .map(|_| {
+                Particle::new(
+                    waypoint.state.estimate + sqrt_error * standard_normal(&mut rng),
+                    weight,
+                )
+            })
+            .collect()
+    }
+
+    /// # Returns
+    /// Returns the resampled particles, which are moved by a small random amount drawn from the
+    /// covariance of the cloud (regularized particle filter). Without this the resampled particles
+    /// would collapse onto a few states when the transition error is small.
+    fn resample(&self, particles: &[Particle<SD>]) -> Vec<Particle<SD>> {
+        let sqrt_error = sqrt_psd(&summary(particles).error);
+        let weights: Vec<f64> = particles.iter().map(|particle| particle.weight).collect();
+        let weight = 1. / particles.len() as f64;
+
+        // optimal bandwidth of the gaussian kernel
+        let n = particles.len() as f64;
+        let d = SD as f64;
+        let bandwidth = (4. / (n * (d + 2.))).powf(1. / (d + 4.));
+
+        let mut rng = self.rng.borrow_mut();
+        self.resampler
+            .resample(&weights, &mut *rng)
+            .into_iter()
+            .map(|i| {
+                let jitter = bandwidth * sqrt_error * standard_normal(&mut rng);
+                Particle::new(particles[i].state + jitter, weight)
+            })
+            .collect()
+    }
+}
+
+impl<const MD: usize, const SD: usize, TModel, MModel, R> Estimator<MD, SD>
+    for ParticleFilter<MD, SD, TModel, MModel, R>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    MModel: NonlinearMeasurementModel<MD, SD>,
+    R: Resampler,
+{
+    fn estimate(
+        &self,
+        track: &Track<SD>,
+        measurement: Measurement<MD>,
+    ) -> Result<GaussianState<SD>, EstimationError> {
+        let dt = time_since_latest_waypoint(track, measurement.timestamp)?;
+        let sqrt_transition_error = sqrt_psd(&self.transition_model.transition_error(dt));
+        let measurement_error_inverse = self
+            .measurement_model
+            .measurement_error()
+            .try_inverse()
+            .ok_or_else(|| {
+                EstimationError::NumericalError(
+                    "the measurement error is not invertible".to_string(),
+                )
+            })?;
+
+        // the cloud is only replaced once the estimate succeeded
+        let mut particles = self.particles_of(track.get_latest_waypoint());
+        let log_likelihoods: Vec<f64> = {
+            let mut rng = self.rng.borrow_mut();
+            particles
+                .iter_mut()
+                .map(|particle| {
+                    particle.state = self.transition_model.transition(&particle.state, dt)
+                        + sqrt_transition_error * standard_normal(&mut rng);
+                    let innovation =
+                        measurement.vector - self.measurement_model.measure(&particle.state);
+                    -0.5 * (innovation.transpose() * measurement_error_inverse * innovation)[0]
+                })
+                .collect()
+        };
+
+        let max_log_likelihood = log_likelihoods.iter().cloned().fold(f64::MIN, f64::max);
+        for (particle, log_likelihood) in particles.iter_mut().zip(log_likelihoods) {
+            particle.weight *= (log_likelihood - max_log_likelihood).exp();
+        }
+
+        let weight_sum: f64 = particles.iter().map(|particle| particle.weight).sum();
+        if !weight_sum.is_normal() {
+            return Err(EstimationError::NumericalError(
+                "the weights of all particles are zero".to_string(),
+            ));
+        }
+        particles
+            .iter_mut()
+            .for_each(|particle| particle.weight /= weight_sum);
+
+        let state = summary(&particles);
+        if effective_sample_size(&particles)
+            < self.resampling_threshold * self.number_of_particles as f64
+        {
+            particles = self.resample(&particles);
+        }
+        self.cloud.replace(Some(ParticleCloud {
+            timestamp: measurement.timestamp,
+            state: state.clone(),
+            particles,
+        }));
+        Ok(state)
+    }
+}
+
+/// # Returns
+/// Returns the weighted mean and covariance of the particles.
+fn summary<const SD: usize>(particles: &[Particle<SD>]) -> GaussianState<SD> {
+    let mean = particles
+        .iter()
+        .fold(SVector::<f64, SD>::zeros(), |mean, particle| {
+            mean + particle.weight * particle.state
+        });
+    let covariance =
+        particles
+            .iter()
+            .fold(SMatrix::<f64, SD, SD>::zeros(), |covariance, particle| {
+                let diff = particle.state - mean;
+                covariance + particle.weight * diff * diff.transpose()
+            });
+    GaussianState::new(mean, covariance)
+}
+
+fn effective_sample_size<const SD: usize>(particles: &[Particle<SD>]) -> f64 {
+    1. / particles
+        .iter()
+        .map(|particle| particle.weight.powi(2))
+        .sum::<f64>()
+}
+
+fn standard_normal<const SD: usize>(rng: &mut StdRng) -> SVector<f64, SD> {
+    SVector::<f64, SD>::from_fn(|_, _| rng.sample(StandardNormal))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::estimator::EstimationError;
+    use crate::kalman::model::{ConstantVelocity, PositionMeasurementModel};
+    use crate::particle::estimator::ParticleFilter;
+    use crate::particle::resampling::SystematicResampling;
+
+    #[test]
+    fn test_invalid_parameters() {
+        // without particles there is nothing to resample
+        assert!(matches!(
+            ParticleFilter::new(
+                ConstantVelocity::new(2.0),
+                PositionMeasurementModel::new(0.1, 0.1),
+                SystematicResampling,
+                0,
+                0.5,
+            ),
+            Err(EstimationError::InvalidParameter(_))
+        ));
+        for resampling_threshold in [-0.5, 50., f64::NAN] {
+            assert!(matches!(
+                ParticleFilter::new(
+                    ConstantVelocity::new(2.0),
+                    PositionMeasurementModel::new(0.1, 0.1),
+                    SystematicResampling,
+                    1000,
+                    resampling_threshold,
+                ),
+                Err(EstimationError::InvalidParameter(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use rand::{Rng, RngCore};
+
+/// # Explanation
+/// A Resampler draws a new set of particles from the current one. Particles with a high weight are
+/// (probably) drawn multiple times while particles with a low weight are (probably) dropped.
+pub trait Resampler {
+    /// # Returns
+    /// Returns the indices of the particles that survive the resampling. The weights must be normalized
+    /// and the result has as many indices as there are weights. The random positions are drawn from rng
+    /// (so that a seeded filter can be reproduced).
+    fn resample(&self, weights: &[f64], rng: &mut dyn RngCore) -> Vec<usize>;
+}
+
+/// # Explanation
+/// The systematic resampling uses one random offset and then draws the particles at equally spaced
+/// positions. It has the lowest variance of the implemented resamplers.
+#[derive(Copy, Clone)]
+pub struct SystematicResampling;
+
+impl Resampler for SystematicResampling {
+    fn resample(&self, weights: &[f64], rng: &mut dyn RngCore) -> Vec<usize> {
+        let n = weights.len() as f64;
+        let offset = rng.gen_range(0.0..1.0);
+        let positions = (0..weights.len()).map(|i| (i as f64 + offset) / n);
+        draw_at_positions(weights, positions)
+    }
+}
+
+/// # Explanation
+/// The stratified resampling divides the interval [0, 1) into as many strata as there are particles
+/// and draws one random position from each stratum.
+#[derive(Copy, Clone)]
+pub struct StratifiedResampling;
+
+impl Resampler for StratifiedResampling {
+    fn resample(&self, weights: &[f64], rng: &mut dyn RngCore) -> Vec<usize> {
+        let n = weights.len() as f64;
+        let positions: Vec<f64> = (0..weights.len())
+            .map(|i| (i as f64 + rng.gen_range(0.0..1.0)) / n)
+            .collect();
+        draw_at_positions(weights, positions.into_iter())
+    }
+}
+
+/// # Explanation
+/// The residual resampling first keeps floor(n * weight) copies of every particle. The remaining
+/// particles are drawn at random from the residual weights.
+#[derive(Copy, Clone)]
+pub struct ResidualResampling;
+
+impl Resampler for ResidualResampling {
+    fn resample(&self, weights: &[f64], rng: &mut dyn RngCore) -> Vec<usize> {
+        let n = weights.len();
+        let mut indices = Vec::with_capacity(n);
+        let mut residuals = Vec::with_capacity(n);
+
+        for (i, weight) in weights.iter().enumerate() {
+            let copies = (n as f64 * weight).floor();
+            indices.resize(indices.len() + copies as usize, i);
+            residuals.push(n as f64 * weight - copies);
+        }
+
+        let remaining = n - indices.len();
+        let residual_sum: f64 = residuals.iter().sum();
+        if remaining > 0 && residual_sum > 0. {
+            residuals
+                .iter_mut()
+                .for_each(|residual| *residual /= residual_sum);
+
+            let mut positions: Vec<f64> = (0..remaining).map(|_| rng.gen_range(0.0..1.0)).collect();
+            positions.sort_by(|a, b| a.total_cmp(b));
+            indices.extend(draw_at_positions(&residuals, positions.into_iter()));
+        }
+        indices
+    }
+}
+
+/// # Returns
+/// Returns for every position in [0, 1) the index of the particle whose cumulative weight interval
+/// contains the position. The positions must be sorted in ascending order. Without particles nothing is
+/// drawn.
+fn draw_at_positions(weights: &[f64], positions: impl Iterator<Item = f64>) -> Vec<usize> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+    let mut indices = Vec::with_capacity(weights.len());
+    let mut cumulative_weight = weights[0];
+    let mut i = 0;
+
+    for position in positions {
+        while position > cumulative_weight && i < weights.len() - 1 {
+            i += 1;
+            cumulative_weight += weights[i];
+        }
+        indices.push(i);
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::particle::resampling::{
+        Resampler, ResidualResampling, StratifiedResampling, SystematicResampling,
+    };
+
+    fn test_resampler<R: Resampler>(resampler: R) {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(resampler.resample(&[], &mut rng).is_empty());
+
+        // a particle with all the weight is drawn for every index
+        assert_eq!(resampler.resample(&[0., 1., 0.], &mut rng), vec![1; 3]);
+
+        let weights = [0.1, 0.4, 0.2, 0.3];
+        let indices = resampler.resample(&weights, &mut rng);
+        assert_eq!(indices.len(), weights.len());
+        assert!(indices.iter().all(|index| *index < weights.len()));
+    }
+
+    #[test]
+    fn test_resamplers() {
+        test_resampler(SystematicResampling);
+        test_resampler(StratifiedResampling);
+        test_resampler(ResidualResampling);
+    }
+}
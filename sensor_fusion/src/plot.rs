@@ -0,0 +1,537 @@
+use std::f64::consts::FRAC_PI_6;
+
+use chrono::{DateTime, Duration, Utc};
+use nalgebra::{DMatrix, DVector, Matrix2, Rotation2, Vector2};
+use plotly::common::{DashType, Fill, Line, Marker, Mode, Title};
+use plotly::layout::{Axis, GridPattern, LayoutGrid, Legend, Margin};
+use plotly::{Layout, Plot, Scatter};
+
+use crate::linalg::covariance_ellipse;
+use crate::state::Measurement;
+use crate::track::Track;
+
+/// the default colors of plotly, so that the ellipses, arrows and bands have the color of their track
+const COLORS: [(u8, u8, u8); 10] = [
+    (31, 119, 180),
+    (255, 127, 14),
+    (44, 160, 44),
+    (214, 39, 40),
+    (148, 103, 189),
+    (140, 86, 75),
+    (227, 119, 194),
+    (127, 127, 127),
+    (188, 189, 34),
+    (23, 190, 207),
+];
+const ELLIPSE_POINTS: usize = 36;
+/// plotly supports eight y axes in the layout
+const MAX_TIME_SERIES: usize = 8;
+const MAP_SIZE: usize = 800;
+const MAP_MARGIN: usize = 60;
+const TIME_SERIES_HEIGHT: usize = 250;
+
+struct LayerPoint {
+    timestamp: DateTime<Utc>,
+    estimate: DVector<f64>,
+    error: Option<DMatrix<f64>>,
+}
+
+/// # Explanation
+/// A TrackLayer is one of the tracks that a TrackPlot overlays (eg the raw gps fixes, the filtered, the
+/// smoothed track or the ground truth). The position is made up of the first two entries of the state.
+/// Since the layers are converted when they are created, tracks with different dimensions can be plotted
+/// together.
+pub struct TrackLayer {
+    name: String,
+    points: Vec<LayerPoint>,
+    markers: bool,
+    ellipse_interval: Option<Duration>,
+    arrows: Vec<(Vector2<f64>, Vector2<f64>)>,
+}
+
+impl TrackLayer {
+    /// # Explanation
+    /// Creates a layer that draws the track as a line.
+    pub fn new<const D: usize>(name: &str, track: &Track<D>) -> Self {
+        let points = track
+            .iter()
+            .map(|waypoint| LayerPoint {
+                timestamp: waypoint.timestamp,
+                estimate: DVector::from_column_slice(waypoint.state.estimate.as_slice()),
+                error: Some(DMatrix::from_column_slice(
+                    D,
+                    D,
+                    waypoint.state.error.as_slice(),
+                )),
+            })
+            .collect();
+        Self::with_points(name, points, false)
+    }
+
+    /// # Explanation
+    /// Creates a layer that draws the measurements as markers (eg the raw gps fixes). The entries of the
+    /// measurements are drawn in the time series of the state entries with the same index.
+    pub fn from_measurements<const D: usize>(name: &str, measurements: &[Measurement<D>]) -> Self {
+        let points = measurements
+            .iter()
+            .map(|measurement| LayerPoint {
+                timestamp: measurement.timestamp,
+                estimate: DVector::from_column_slice(measurement.vector.as_slice()),
+                error: None,
+            })
+            .collect();
+        Self::with_points(name, points, true)
+    }
+
+    /// # Explanation
+    /// Draws the 1σ and 2σ ellipses of the position error. To keep the plot readable the ellipses are
+    /// only drawn for waypoints that are at least the interval apart.
+    pub fn with_ellipses(mut self, interval: Duration) -> Self {
+        self.ellipse_interval = Some(interval);
+        self
+    }
+
+    /// # Explanation
+    /// Draws an arrow from the position in the direction of the velocity for waypoints that are at least
+    /// the interval apart. An arrow is as long as the distance travelled within a second. Since the
+    /// velocity is stored differently in the states of the models, to_velocity extracts the velocity
+    /// (vx, vy) of a state.
+    pub fn with_velocity_arrows(
+        mut self,
+        interval: Duration,
+        to_velocity: impl Fn(&DVector<f64>) -> Vector2<f64>,
+    ) -> Self {
+        self.arrows = at_intervals(&self.points, interval)
+            .map(|point| (position(&point.estimate), to_velocity(&point.estimate)))
+            .collect();
+        self
+    }
+
+    fn with_points(name: &str, points: Vec<LayerPoint>, markers: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            points,
+            markers,
+            ellipse_interval: None,
+            arrows: Vec::new(),
+        }
+    }
+}
+
+/// # Explanation
+/// The TrackPlot overlays several TrackLayers. write_html writes two figures:
+/// - the map with the positions of all layers (and their ellipses and velocity arrows). Both axes have
+///   the same scale and a grid in meters.
+/// - the time series of every state entry (one subplot per entry, at most eight) with ±σ bands. The
+///   subplots share the time axis, so zooming into one of them zooms into all of them.
+pub struct TrackPlot {
+    title: String,
+    layers: Vec<TrackLayer>,
+    component_names: Vec<String>,
+    grid_spacing: Option<f64>,
+}
+
+impl TrackPlot {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            layers: Vec::new(),
+            component_names: Vec::new(),
+            grid_spacing: None,
+        }
+    }
+
+    pub fn with_layer(mut self, layer: TrackLayer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    /// # Explanation
+    /// Sets the names of the state entries (eg "v [m/s]") that label the time series.
+    pub fn with_component_names(mut self, names: &[&str]) -> Self {
+        self.component_names = names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// # Explanation
+    /// Sets the distance of the grid lines of the map in meters. By default about ten grid lines are
+    /// drawn (at a distance of 1, 2 or 5 times a power of ten).
+    pub fn with_grid_spacing(mut self, spacing: f64) -> Self {
+        self.grid_spacing = Some(spacing);
+        self
+    }
+
+    /// # Explanation
+    /// Writes the map to `<name>.html` and the time series to `<name>_time_series.html`.
+    pub fn write_html(&self, name: &str) {
+        self.map().write_html(format!("{}.html", name));
+        self.time_series()
+            .write_html(format!("{}_time_series.html", name));
+    }
+
+    fn map(&self) -> Plot {
+        let mut plot = Plot::new();
+        let mut positions: Vec<Vector2<f64>> = Vec::new();
+        for (i, layer) in self.layers.iter().enumerate() {
+            let color = color(i, 1.);
+            let layer_positions: Vec<Vector2<f64>> = layer
+                .points
+                .iter()
+                .map(|point| position(&point.estimate))
+                .collect();
+            let (xs, ys) = layer_positions.iter().map(|p| (p.x, p.y)).unzip();
+            let trace = Scatter::new(xs, ys)
+                .name(&layer.name)
+                .legend_group(&layer.name);
+            plot.add_trace(if layer.markers {
+                trace
+                    .mode(Mode::Markers)
+                    .marker(Marker::new().color(color.clone()).size(4))
+            } else {
+                trace
+                    .mode(Mode::Lines)
+                    .line(Line::new().color(color.clone()))
+            });
+            positions.extend(layer_positions);
+
+            if let Some(interval) = layer.ellipse_interval {
+                for (sigma, dash) in [(1., DashType::Solid), (2., DashType::Dash)] {
+                    let rings: Vec<Vec<Vector2<f64>>> = at_intervals(&layer.points, interval)
+                        .filter_map(|point| {
+                            let error = point.error.as_ref()?;
+                            Some(covariance_ellipse(
+                                position(&point.estimate),
+                                Matrix2::new(
+                                    error[(0, 0)],
+                                    error[(0, 1)],
+                                    error[(1, 0)],
+                                    error[(1, 1)],
+                                ),
+                                sigma,
+                                ELLIPSE_POINTS,
+                            ))
+                        })
+                        .collect();
+                    positions.extend(rings.iter().flatten());
+                    let (xs, ys) = separated(rings);
+                    plot.add_trace(
+                        Scatter::new(xs, ys)
+                            .mode(Mode::Lines)
+                            .name(format!("{} {}σ", layer.name, sigma))
+                            .legend_group(&layer.name)
+                            .line(Line::new().color(color.clone()).width(1.).dash(dash)),
+                    );
+                }
+            }
+
+            if !layer.arrows.is_empty() {
+                let arrows: Vec<Vec<Vector2<f64>>> = layer
+                    .arrows
+                    .iter()
+                    .flat_map(|(position, velocity)| arrow(*position, *velocity))
+                    .collect();
+                positions.extend(arrows.iter().flatten());
+                let (xs, ys) = separated(arrows);
+                plot.add_trace(
+                    Scatter::new(xs, ys)
+                        .mode(Mode::Lines)
+                        .name(format!("{} velocity", layer.name))
+                        .legend_group(&layer.name)
+                        .line(Line::new().color(color).width(1.)),
+                );
+            }
+        }
+
+        // equal ranges on a square plot area give both axes the same scale
+        let (x_range, y_range) = square_ranges(&positions);
+        let spacing = self
+            .grid_spacing
+            .unwrap_or_else(|| grid_spacing(x_range[1] - x_range[0]));
+        let axis = |title: &str, range: [f64; 2]| {
+            Axis::new()
+                .title(Title::new(title))
+                .range(range.to_vec())
+                .dtick(spacing)
+                .show_grid(true)
+        };
+        plot.set_layout(
+            Layout::new()
+                .title(Title::new(&self.title))
+                .width(MAP_SIZE)
+                .height(MAP_SIZE)
+                .margin(
+                    Margin::new()
+                        .left(MAP_MARGIN)
+                        .right(MAP_MARGIN)
+                        .top(MAP_MARGIN)
+                        .bottom(MAP_MARGIN),
+                )
+                // the legend is placed inside, otherwise it would shrink the plot area
+                .legend(Legend::new().x(0.01).y(0.99))
+                .x_axis(axis("x [m]", x_range))
+                .y_axis(axis("y [m]", y_range)),
+        );
+        plot
+    }
+
+    fn time_series(&self) -> Plot {
+        let mut plot = Plot::new();
+        let components = self
+            .layers
+            .iter()
+            .flat_map(|layer| layer.points.iter().map(|point| point.estimate.len()))
+            .max()
+            .unwrap_or(0)
+            .min(MAX_TIME_SERIES);
+        // without layers there is nothing to plot, so the default is never used
+        let start = self
+            .layers
+            .iter()
+            .filter_map(|layer| layer.points.first().map(|point| point.timestamp))
+            .min()
+            .unwrap_or_default();
+
+        for (i, layer) in self.layers.iter().enumerate() {
+            let seconds: Vec<f64> = layer
+                .points
+                .iter()
+                .map(|point| (point.timestamp - start).num_milliseconds() as f64 / 1000.0)
+                .collect();
+            let layer_components = layer
+                .points
+                .first()
+                .map_or(0, |point| point.estimate.len())
+                .min(components);
+
+            for component in 0..layer_components {
+                // plotly names the axes y, y2, y3, ...
+                let y_axis = match component {
+                    0 => "y".to_string(),
+                    _ => format!("y{}", component + 1),
+                };
+                let values: Vec<f64> = layer
+                    .points
+                    .iter()
+                    .map(|point| point.estimate[component])
+                    .collect();
+
+                let standard_deviations: Option<Vec<f64>> = layer
+                    .points
+                    .iter()
+                    .map(|point| {
+                        point
+                            .error
+                            .as_ref()
+                            .map(|error| error[(component, component)].max(0.).sqrt())
+                    })
+                    .collect();
+                if let Some(standard_deviations) = standard_deviations {
+                    // the lower bound is filled up to the upper bound
+                    for sign in [1., -1.] {
+                        let bound = values
+                            .iter()
+                            .zip(&standard_deviations)
+                            .map(|(value, standard_deviation)| value + sign * standard_deviation)
+                            .collect();
+                        let trace = Scatter::new(seconds.clone(), bound)
+                            .mode(Mode::Lines)
+                            .name(format!("{} ±σ", layer.name))
+                            .legend_group(&layer.name)
+                            .show_legend(false)
+                            .line(Line::new().color(color(i, 0.)).width(0.))
+                            .x_axis("x")
+                            .y_axis(&y_axis);
+                        plot.add_trace(if sign < 0. {
+                            trace.fill(Fill::ToNextY).fill_color(color(i, 0.2))
+                        } else {
+                            trace
+                        });
+                    }
+                }
+
+                let trace = Scatter::new(seconds.clone(), values)
+                    .name(&layer.name)
+                    .legend_group(&layer.name)
+                    .show_legend(component == 0)
+                    .x_axis("x")
+                    .y_axis(&y_axis);
+                plot.add_trace(if layer.markers {
+                    trace
+                        .mode(Mode::Markers)
+                        .marker(Marker::new().color(color(i, 1.)).size(4))
+                } else {
+                    trace
+                        .mode(Mode::Lines)
+                        .line(Line::new().color(color(i, 1.)))
+                });
+            }
+        }
+
+        let mut layout = Layout::new()
+            .title(Title::new(&self.title))
+            .height(TIME_SERIES_HEIGHT * components.max(1))
+            .grid(
+                LayoutGrid::new()
+                    .rows(components.max(1))
+                    .columns(1)
+                    .pattern(GridPattern::Coupled),
+            )
+            .x_axis(Axis::new().title(Title::new("t [s]")).show_grid(true));
+        for component in 0..components {
+            let name = self
+                .component_names
+                .get(component)
+                .cloned()
+                .unwrap_or_else(|| format!("state {}", component));
+            let axis = Axis::new().title(Title::new(&name)).show_grid(true);
+            layout = match component {
+                0 => layout.y_axis(axis),
+                1 => layout.y_axis2(axis),
+                2 => layout.y_axis3(axis),
+                3 => layout.y_axis4(axis),
+                4 => layout.y_axis5(axis),
+                5 => layout.y_axis6(axis),
+                6 => layout.y_axis7(axis),
+                _ => layout.y_axis8(axis),
+            };
+        }
+        plot.set_layout(layout);
+        plot
+    }
+}
+
+fn position(estimate: &DVector<f64>) -> Vector2<f64> {
+    Vector2::new(estimate[0], estimate[1])
+}
+
+fn color(index: usize, alpha: f64) -> String {
+    let (r, g, b) = COLORS[index % COLORS.len()];
+    format!("rgba({}, {}, {}, {})", r, g, b, alpha)
+}
+
+/// # Returns
+/// Returns the points that are at least the interval after the previously returned one (starting with
+/// the first point).
+fn at_intervals(points: &[LayerPoint], interval: Duration) -> impl Iterator<Item = &LayerPoint> {
+    let mut last: Option<DateTime<Utc>> = None;
+    points.iter().filter(move |point| {
+        let due = !matches!(last, Some(last) if point.timestamp - last < interval);
+        if due {
+            last = Some(point.timestamp);
+        }
+        due
+    })
+}
+
+/// # Returns
+/// Returns the lines of an arrow from the position along the velocity (the shaft and the two lines of
+/// the head).
+fn arrow(position: Vector2<f64>, velocity: Vector2<f64>) -> Vec<Vec<Vector2<f64>>> {
+    let tip = position + velocity;
+    let head = |angle: f64| {
+        let direction = Rotation2::new(angle) * -velocity;
+        vec![tip, tip + direction * 0.25]
+    };
+    vec![vec![position, tip], head(FRAC_PI_6), head(-FRAC_PI_6)]
+}
+
+/// # Returns
+/// Returns the coordinates of the lines in one trace. The lines are separated by gaps (None), which
+/// plotly does not connect.
+fn separated(lines: Vec<Vec<Vector2<f64>>>) -> (Vec<Option<f64>>, Vec<Option<f64>>) {
+    lines
+        .into_iter()
+        .flat_map(|line| {
+            line.into_iter()
+                .map(|point| (Some(point.x), Some(point.y)))
+                .chain(std::iter::once((None, None)))
+        })
+        .unzip()
+}
+
+/// # Returns
+/// Returns ranges of the same length for both axes that contain all positions (with a small margin).
+fn square_ranges(positions: &[Vector2<f64>]) -> ([f64; 2], [f64; 2]) {
+    if positions.is_empty() {
+        return ([-1., 1.], [-1., 1.]);
+    }
+    let min = positions.iter().fold(positions[0], |min, p| min.inf(p));
+    let max = positions.iter().fold(positions[0], |max, p| max.sup(p));
+    let center = (min + max) / 2.;
+    let half_size = ((max - min).max() / 2. * 1.05).max(0.5);
+    (
+        [center.x - half_size, center.x + half_size],
+        [center.y - half_size, center.y + half_size],
+    )
+}
+
+/// # Returns
+/// Returns a spacing of 1, 2 or 5 times a power of ten that gives about ten grid lines.
+fn grid_spacing(size: f64) -> f64 {
+    let rough = size / 10.;
+    let power = 10f64.powf(rough.log10().floor());
+    [1., 2., 5., 10.]
+        .into_iter()
+        .map(|factor| factor * power)
+        .find(|spacing| *spacing >= rough)
+        .unwrap_or(10. * power)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::{SMatrix, Vector2};
+
+    use crate::plot::{TrackLayer, TrackPlot};
+    use crate::sim::sensor::SensorSimulator;
+    use crate::sim::trajectory::{FigureEight, Kinematics, Trajectory};
+
+    #[test]
+    fn test_plot() {
+        let figure_eight = FigureEight::new(2., 2., 2. * PI, 10.);
+        let start = Utc.timestamp_nanos(0);
+        let interval = Duration::milliseconds(100);
+        let ground_truth = figure_eight
+            .ground_truth(start, interval, Kinematics::constant_velocity_state)
+            .unwrap();
+        let measurements = SensorSimulator::position(interval)
+            .unwrap()
+            .with_noise(0.01 * SMatrix::<f64, 2, 2>::identity())
+            .with_seed(20)
+            .simulate(&figure_eight, start);
+
+        // the arrows are drawn once per second (the ground truth is ten seconds long)
+        let layer = TrackLayer::new("ground truth", &ground_truth)
+            .with_ellipses(Duration::seconds(1))
+            .with_velocity_arrows(Duration::seconds(1), |state| {
+                Vector2::new(state[2], state[3])
+            });
+        assert_eq!(layer.points.len(), ground_truth.len());
+        assert_eq!(layer.arrows.len(), 11);
+        assert!(!layer.markers);
+        let measurement_layer = TrackLayer::from_measurements("gps", &measurements);
+        assert!(measurement_layer.markers);
+        assert!(measurement_layer
+            .points
+            .iter()
+            .all(|point| point.error.is_none()));
+
+        let name = std::env::temp_dir().join("sensor_fusion_test_plot");
+        let name = name.to_str().unwrap();
+        TrackPlot::new("test")
+            .with_layer(measurement_layer)
+            .with_layer(layer)
+            .with_component_names(&["x [m]", "y [m]", "vx [m/s]", "vy [m/s]"])
+            .write_html(name);
+
+        for file in [
+            format!("{}.html", name),
+            format!("{}_time_series.html", name),
+        ] {
+            assert!(std::path::Path::new(&file).exists());
+            std::fs::remove_file(file).unwrap();
+        }
+    }
+}
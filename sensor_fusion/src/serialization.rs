@@ -0,0 +1,387 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use csv::StringRecord;
+use nalgebra::{SMatrix, SVector};
+
+use crate::state::{GaussianState, Prediction, Waypoint};
+use crate::track::Track;
+
+#[derive(Debug)]
+pub enum SerializationError {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    InvalidTrack(String),
+}
+
+impl Display for SerializationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializationError::Io(error) => write!(f, "Reading or writing failed: {}.", error),
+            SerializationError::Csv(error) => write!(f, "The csv is invalid: {}.", error),
+            SerializationError::Json(error) => write!(f, "The json is invalid: {}.", error),
+            SerializationError::Binary(error) => {
+                write!(f, "The binary format is invalid: {}.", error)
+            }
+            SerializationError::InvalidTrack(details) => {
+                write!(f, "The track is invalid: {}.", details)
+            }
+        }
+    }
+}
+
+impl Error for SerializationError {}
+
+impl From<std::io::Error> for SerializationError {
+    fn from(error: std::io::Error) -> Self {
+        SerializationError::Io(error)
+    }
+}
+
+impl From<csv::Error> for SerializationError {
+    fn from(error: csv::Error) -> Self {
+        SerializationError::Csv(error)
+    }
+}
+
+impl From<serde_json::Error> for SerializationError {
+    fn from(error: serde_json::Error) -> Self {
+        SerializationError::Json(error)
+    }
+}
+
+impl From<bincode::Error> for SerializationError {
+    fn from(error: bincode::Error) -> Self {
+        SerializationError::Binary(error)
+    }
+}
+
+/// # Explanation
+/// Writes the track as csv with one row per waypoint. The vectors and matrices are flattened into one
+/// column per entry (the matrices row by row):
+/// - timestamp (rfc 3339 with nanoseconds)
+/// - estimate_i and error_i_j
+/// - prediction_dt_ns, prediction_estimate_i and prediction_error_i_j (empty without a prediction)
+/// - mode_probabilities separated by semicolons (empty without mode probabilities)
+pub fn write_csv<const D: usize>(
+    track: &Track<D>,
+    writer: impl Write,
+) -> Result<(), SerializationError> {
+    let mut writer = csv_writer::<D, _>(writer)?;
+    for waypoint in track {
+        write_csv_waypoint(&mut writer, waypoint)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_csv<const D: usize>(reader: impl Read) -> Result<Track<D>, SerializationError> {
+    to_track(read_csv_waypoints(reader)?)
+}
+
+pub fn write_json<const D: usize>(
+    track: &Track<D>,
+    writer: impl Write,
+) -> Result<(), SerializationError> {
+    Ok(serde_json::to_writer_pretty(writer, track)?)
+}
+
+pub fn read_json<const D: usize>(reader: impl Read) -> Result<Track<D>, SerializationError> {
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// # Explanation
+/// Writes the track in a compact binary format (bincode). The dimension of the state is written first,
+/// so that a track is not read with the wrong dimension.
+pub fn write_binary<const D: usize>(
+    track: &Track<D>,
+    mut writer: impl Write,
+) -> Result<(), SerializationError> {
+    bincode::serialize_into(&mut writer, &(D as u64))?;
+    bincode::serialize_into(&mut writer, track)?;
+    Ok(())
+}
+
+pub fn read_binary<const D: usize>(mut reader: impl Read) -> Result<Track<D>, SerializationError> {
+    let dimension: u64 = bincode::deserialize_from(&mut reader)?;
+    if dimension != D as u64 {
+        return Err(SerializationError::InvalidTrack(format!(
+            "the states have {} dimensions instead of {}",
+            dimension, D
+        )));
+    }
+    Ok(bincode::deserialize_from(reader)?)
+}
+
+/// # Returns
+/// Returns a csv writer that has already written the header.
+pub(crate) fn csv_writer<const D: usize, W: Write>(
+    writer: W,
+) -> Result<csv::Writer<W>, SerializationError> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    writer.write_record(csv_header::<D>())?;
+    Ok(writer)
+}
+
+pub(crate) fn write_csv_waypoint<const D: usize, W: Write>(
+    writer: &mut csv::Writer<W>,
+    waypoint: &Waypoint<D>,
+) -> Result<(), SerializationError> {
+    let mut record = vec![waypoint
+        .timestamp
+        .to_rfc3339_opts(SecondsFormat::Nanos, true)];
+    push_state(&mut record, Some(&waypoint.state));
+
+    let prediction = waypoint.prediction.as_ref();
+    let dt = prediction
+        .map(|prediction| {
+            prediction.dt.num_nanoseconds().ok_or_else(|| {
+                SerializationError::InvalidTrack("the dt of a prediction is too long".to_string())
+            })
+        })
+        .transpose()?;
+    record.push(dt.map(|dt| dt.to_string()).unwrap_or_default());
+    push_state(&mut record, prediction.map(|prediction| &prediction.state));
+
+    let mode_probabilities = waypoint.mode_probabilities.as_ref().map(|probabilities| {
+        probabilities
+            .iter()
+            .map(|probability| probability.to_string())
+            .collect::<Vec<String>>()
+            .join(";")
+    });
+    record.push(mode_probabilities.unwrap_or_default());
+
+    writer.write_record(record)?;
+    Ok(())
+}
+
+/// # Returns
+/// Returns the waypoints of a csv that was written by write_csv (it may contain no waypoint at all).
+pub(crate) fn read_csv_waypoints<const D: usize>(
+    reader: impl Read,
+) -> Result<Vec<Waypoint<D>>, SerializationError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    if reader.headers()? != &StringRecord::from(csv_header::<D>()) {
+        return Err(SerializationError::InvalidTrack(format!(
+            "the header does not match the one of states with {} dimensions",
+            D
+        )));
+    }
+
+    reader
+        .records()
+        .map(|record| read_csv_waypoint(&record?))
+        .collect()
+}
+
+fn read_csv_waypoint<const D: usize>(
+    record: &StringRecord,
+) -> Result<Waypoint<D>, SerializationError> {
+    let invalid = |details: String| SerializationError::InvalidTrack(details);
+    let mut fields = record.iter();
+    let mut next_field = || fields.next().unwrap_or_default();
+
+    let timestamp = DateTime::parse_from_rfc3339(next_field())
+        .map_err(|error| invalid(error.to_string()))?
+        .with_timezone(&Utc);
+    let state =
+        read_state(&mut next_field)?.ok_or_else(|| invalid("a state is missing".to_string()))?;
+
+    let dt = next_field();
+    let prediction_state = read_state(&mut next_field)?;
+    let prediction = match (dt.is_empty(), prediction_state) {
+        (true, None) => None,
+        (false, Some(state)) => {
+            let dt = dt
+                .parse::<i64>()
+                .map_err(|error| invalid(error.to_string()))?;
+            Some(Prediction::new(Duration::nanoseconds(dt), state))
+        }
+        _ => return Err(invalid("a prediction is incomplete".to_string())),
+    };
+
+    let mode_probabilities = next_field();
+    let mode_probabilities = if mode_probabilities.is_empty() {
+        None
+    } else {
+        Some(
+            mode_probabilities
+                .split(';')
+                .map(|probability| probability.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|error| invalid(error.to_string()))?,
+        )
+    };
+
+    Ok(Waypoint {
+        timestamp,
+        state,
+        prediction,
+        mode_probabilities,
+    })
+}
+
+fn csv_header<const D: usize>() -> Vec<String> {
+    let state_columns = |prefix: &str| {
+        let estimate = (0..D).map(move |i| format!("{}estimate_{}", prefix, i));
+        let error = (0..D)
+            .flat_map(move |i| (0..D).map(move |j| (i, j)))
+            .map(move |(i, j)| format!("{}error_{}_{}", prefix, i, j));
+        estimate.chain(error).collect::<Vec<String>>()
+    };
+
+    let mut header = vec!["timestamp".to_string()];
+    header.extend(state_columns(""));
+    header.push("prediction_dt_ns".to_string());
+    header.extend(state_columns("prediction_"));
+    header.push("mode_probabilities".to_string());
+    header
+}
+
+/// # Explanation
+/// Appends the D entries of the estimate and the D * D entries of the error (row by row) to the record.
+/// Without a state the entries are left empty.
+fn push_state<const D: usize>(record: &mut Vec<String>, state: Option<&GaussianState<D>>) {
+    match state {
+        Some(state) => {
+            record.extend(state.estimate.iter().map(|value| value.to_string()));
+            record.extend(
+                state
+                    .error
+                    .transpose()
+                    .iter()
+                    .map(|value| value.to_string()),
+            );
+        }
+        None => record.extend(vec![String::new(); D + D * D]),
+    }
+}
+
+fn read_state<'a, const D: usize>(
+    next_field: &mut impl FnMut() -> &'a str,
+) -> Result<Option<GaussianState<D>>, SerializationError> {
+    let fields: Vec<&str> = (0..D + D * D).map(|_| next_field()).collect();
+    if fields.iter().all(|field| field.is_empty()) {
+        return Ok(None);
+    }
+
+    let values = fields
+        .iter()
+        .map(|field| field.parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|error| SerializationError::InvalidTrack(error.to_string()))?;
+    Ok(Some(GaussianState::new(
+        SVector::<f64, D>::from_column_slice(&values[..D]),
+        SMatrix::<f64, D, D>::from_row_slice(&values[D..]),
+    )))
+}
+
+fn to_track<const D: usize>(waypoints: Vec<Waypoint<D>>) -> Result<Track<D>, SerializationError> {
+    let mut waypoints = waypoints.into_iter();
+    let first = waypoints.next().ok_or_else(|| {
+        SerializationError::InvalidTrack("a track needs at least one waypoint".to_string())
+    })?;
+    let mut track = Track::new(first);
+    waypoints.for_each(|waypoint| track.add_waypoint(waypoint));
+    Ok(track)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::SMatrix;
+
+    use crate::estimator::Estimator;
+    use crate::kalman::estimator::KalmanFilter;
+    use crate::kalman::model::{ConstantVelocity, PositionMeasurementModel};
+    use crate::serialization;
+    use crate::sim::sensor::SensorSimulator;
+    use crate::sim::trajectory::{FigureEight, Kinematics, Trajectory};
+    use crate::track::Track;
+
+    /// # Returns
+    /// Returns a filtered track whose waypoints (except the first one) contain the prediction and every
+    /// second one the mode probabilities.
+    fn filtered_track() -> Track<4> {
+        let figure_eight = FigureEight::new(2., 2., 2. * PI, 10.);
+        let start = Utc.timestamp_nanos(0);
+        let interval = Duration::milliseconds(100);
+        let mut initial_waypoint = figure_eight
+            .ground_truth(start, interval, Kinematics::constant_velocity_state)
+            .unwrap()
+            .get_first_waypoint()
+            .clone();
+        initial_waypoint.state.error.fill_with_identity();
+        let measurements = SensorSimulator::position(interval)
+            .unwrap()
+            .with_noise(0.01 * SMatrix::<f64, 2, 2>::identity())
+            .with_seed(18)
+            .simulate(&figure_eight, start);
+
+        let kalman_filter = KalmanFilter::new(
+            ConstantVelocity::new(0.05),
+            PositionMeasurementModel::new(0.1, 0.1),
+        );
+        let mut track = Track::new(initial_waypoint);
+        for (i, measurement) in measurements.into_iter().enumerate() {
+            let mut waypoint = kalman_filter
+                .estimate_waypoint(&track, measurement)
+                .unwrap();
+            if i % 2 == 0 {
+                waypoint.mode_probabilities = Some(vec![0.25, 0.75]);
+            }
+            track.add_waypoint(waypoint);
+        }
+        track
+    }
+
+    fn assert_same(read_track: &Track<4>, track: &Track<4>) {
+        assert_eq!(read_track.len(), track.len());
+        for (read, waypoint) in read_track.iter().zip(track.iter()) {
+            assert_eq!(read.timestamp, waypoint.timestamp);
+            assert_eq!(read.state.estimate, waypoint.state.estimate);
+            assert_eq!(read.state.error, waypoint.state.error);
+            assert_eq!(read.prediction.is_some(), waypoint.prediction.is_some());
+            if let (Some(read), Some(prediction)) = (&read.prediction, &waypoint.prediction) {
+                assert_eq!(read.dt, prediction.dt);
+                assert_eq!(read.state.estimate, prediction.state.estimate);
+                assert_eq!(read.state.error, prediction.state.error);
+            }
+            assert_eq!(read.mode_probabilities, waypoint.mode_probabilities);
+        }
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let track = filtered_track();
+
+        let mut csv = Vec::new();
+        serialization::write_csv(&track, &mut csv).unwrap();
+        assert_same(&serialization::read_csv(csv.as_slice()).unwrap(), &track);
+        assert!(serialization::read_csv::<5>(csv.as_slice()).is_err());
+
+        let mut json = Vec::new();
+        serialization::write_json(&track, &mut json).unwrap();
+        assert_same(&serialization::read_json(json.as_slice()).unwrap(), &track);
+        assert!(serialization::read_json::<5>(json.as_slice()).is_err());
+        assert!(serialization::read_json::<4>("[]".as_bytes()).is_err());
+
+        let mut binary = Vec::new();
+        serialization::write_binary(&track, &mut binary).unwrap();
+        assert_same(
+            &serialization::read_binary(binary.as_slice()).unwrap(),
+            &track,
+        );
+        assert!(serialization::read_binary::<5>(binary.as_slice()).is_err());
+        assert!(binary.len() < json.len());
+    }
+}
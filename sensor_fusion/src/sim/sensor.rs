@@ -0,0 +1,285 @@
+use chrono::{DateTime, Duration, Utc};
+use nalgebra::{Rotation2, SMatrix, SVector, Vector2};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+
+use crate::estimator::EstimationError;
+use crate::linalg::sqrt_psd;
+use crate::sim::trajectory::{check_interval, sample_times, Kinematics, Trajectory};
+use crate::state::Measurement;
+
+type MeasureFunction<const MD: usize> = Box<dyn Fn(&Kinematics) -> SVector<f64, MD>>;
+
+/// # Explanation
+/// The SensorSimulator measures a Trajectory every interval and emits the measurements that a real
+/// sensor would deliver. Every measurement is the true value (given by the measure function) plus
+/// - the gaussian noise with the noise covariance,
+/// - the constant bias.
+///
+/// Position sensors can also have an offset that turns with the robot (see position_with_offset).
+///
+/// A measurement is dropped with the dropout probability. With a latency the measurement is timestamped
+/// when it arrives (like the robot does with Utc::now()), ie the latency after the motion it describes.
+///
+/// The first measurement is taken one interval after the start (the start is the initial waypoint).
+///
+/// # Type parameters
+/// - MD: the dimension of the measurements
+pub struct SensorSimulator<const MD: usize> {
+    interval: Duration,
+    measure: MeasureFunction<MD>,
+    noise: SMatrix<f64, MD, MD>,
+    bias: SVector<f64, MD>,
+    dropout_probability: f64,
+    latency: Duration,
+    rng: StdRng,
+}
+
+impl<const MD: usize> SensorSimulator<MD> {
+    /// # Returns
+    /// Returns the simulator or EstimationError::InvalidParameter if the interval is not positive.
+    pub fn new(
+        interval: Duration,
+        measure: impl Fn(&Kinematics) -> SVector<f64, MD> + 'static,
+    ) -> Result<Self, EstimationError> {
+        check_interval(interval)?;
+        Ok(Self {
+            interval,
+            measure: Box::new(measure),
+            noise: SMatrix::zeros(),
+            bias: SVector::zeros(),
+            dropout_probability: 0.,
+            latency: Duration::zero(),
+            rng: StdRng::from_entropy(),
+        })
+    }
+
+    /// # Explanation
+    /// Adds gaussian noise with the given covariance to the measurements.
+    pub fn with_noise(mut self, noise: SMatrix<f64, MD, MD>) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn with_bias(mut self, bias: SVector<f64, MD>) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    /// # Returns
+    /// Returns the simulator or EstimationError::InvalidParameter if the dropout probability is not in
+    /// [0, 1].
+    pub fn with_dropout_probability(
+        mut self,
+        dropout_probability: f64,
+    ) -> Result<Self, EstimationError> {
+        if !(0.0..=1.0).contains(&dropout_probability) {
+            return Err(EstimationError::InvalidParameter(format!(
+                "the dropout probability {} is not in [0, 1]",
+                dropout_probability
+            )));
+        }
+        self.dropout_probability = dropout_probability;
+        Ok(self)
+    }
+
+    /// # Returns
+    /// Returns the simulator or EstimationError::InvalidParameter if the latency is negative (a sensor
+    /// cannot deliver a measurement before the motion happened).
+    pub fn with_latency(mut self, latency: Duration) -> Result<Self, EstimationError> {
+        if latency < Duration::zero() {
+            return Err(EstimationError::InvalidParameter(format!(
+                "the latency of {} ms is negative",
+                latency.num_milliseconds()
+            )));
+        }
+        self.latency = latency;
+        Ok(self)
+    }
+
+    /// # Explanation
+    /// Seeds the random number generator, so that the simulation can be reproduced.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// # Returns
+    /// Returns the measurements of the trajectory that starts at the start timestamp.
+    pub fn simulate(
+        &mut self,
+        trajectory: &impl Trajectory,
+        start: DateTime<Utc>,
+    ) -> Vec<Measurement<MD>> {
+        let sqrt_noise = sqrt_psd(&self.noise);
+        sample_times(trajectory, self.interval, 1)
+            .filter_map(|(offset, t)| {
+                let kinematics = trajectory.kinematics(t);
+                let standard_normal =
+                    SVector::<f64, MD>::from_fn(|_, _| self.rng.sample(StandardNormal));
+                if self.rng.gen_bool(self.dropout_probability) {
+                    return None;
+                }
+
+                let vector = (self.measure)(&kinematics) + sqrt_noise * standard_normal + self.bias;
+                Some(Measurement::new(start + offset + self.latency, vector))
+            })
+            .collect()
+    }
+}
+
+impl SensorSimulator<2> {
+    /// # Explanation
+    /// Creates a gps sensor that measures the position (x, y).
+    pub fn position(interval: Duration) -> Result<Self, EstimationError> {
+        Self::new(interval, |kinematics| kinematics.position)
+    }
+
+    /// # Explanation
+    /// Creates a gps sensor whose positions have an offset that turns with the heading of the robot. The
+    /// offset is given in the frame of the robot (forward, left), so a lateral offset moves the positions
+    /// to the same side of the direction of travel. On the way to the table and back this gives two
+    /// parallel lines, ie the U-shape of the README.
+    pub fn position_with_offset(
+        interval: Duration,
+        offset: Vector2<f64>,
+    ) -> Result<Self, EstimationError> {
+        Self::new(interval, move |kinematics| {
+            kinematics.position + Rotation2::new(kinematics.heading) * offset
+        })
+    }
+
+    /// # Explanation
+    /// Creates a velocity sensor that measures the velocity in the world frame (vx, vy), like the
+    /// SimpleVelocitySensor of the robot.
+    pub fn velocity(interval: Duration) -> Result<Self, EstimationError> {
+        Self::new(interval, |kinematics| kinematics.velocity)
+    }
+}
+
+impl SensorSimulator<1> {
+    /// # Explanation
+    /// Creates a compass that measures the heading.
+    pub fn heading(interval: Duration) -> Result<Self, EstimationError> {
+        Self::new(interval, |kinematics| {
+            SVector::<f64, 1>::new(kinematics.heading)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::{SMatrix, Vector2};
+
+    use crate::estimator::EstimationError;
+    use crate::sim::sensor::SensorSimulator;
+    use crate::sim::trajectory::{FigureEight, Kinematics, Path, Trajectory};
+
+    fn gps(interval: Duration) -> SensorSimulator<2> {
+        SensorSimulator::position(interval)
+            .unwrap()
+            .with_noise(SMatrix::<f64, 2, 2>::new(0.04, 0., 0., 0.01))
+            .with_bias(Vector2::new(0.5, -0.2))
+            .with_dropout_probability(0.3)
+            .unwrap()
+            .with_latency(Duration::milliseconds(50))
+            .unwrap()
+            .with_seed(42)
+    }
+
+    #[test]
+    fn test_noise_bias_dropout_and_latency() {
+        let trajectory = FigureEight::new(2., 2., 2. * PI, 100.);
+        let start = Utc.timestamp_nanos(0);
+        let interval = Duration::milliseconds(100);
+        let ground_truth = trajectory
+            .ground_truth(start, interval, Kinematics::constant_velocity_state)
+            .unwrap();
+
+        let measurements = gps(interval).simulate(&trajectory, start);
+        let dropped = 1. - measurements.len() as f64 / (ground_truth.len() - 1) as f64;
+        assert!((dropped - 0.3).abs() < 0.05);
+
+        let errors: Vec<Vector2<f64>> = measurements
+            .iter()
+            .map(|measurement| {
+                // the measurement describes the position before the latency
+                let true_position = ground_truth
+                    .get_waypoint(measurement.timestamp - Duration::milliseconds(50))
+                    .unwrap()
+                    .state
+                    .estimate
+                    .fixed_rows::<2>(0)
+                    .into_owned();
+                measurement.vector - true_position
+            })
+            .collect();
+        let n = errors.len() as f64;
+        let mean = errors.iter().sum::<Vector2<f64>>() / n;
+        assert!((mean - Vector2::new(0.5, -0.2)).norm() < 0.03);
+        let variance = errors
+            .iter()
+            .map(|error| (error - mean).component_mul(&(error - mean)))
+            .sum::<Vector2<f64>>()
+            / n;
+        assert!((variance - Vector2::new(0.04, 0.01)).norm() < 0.01);
+
+        // the same seed gives the same measurements
+        let same_measurements = gps(interval).simulate(&trajectory, start);
+        assert_eq!(same_measurements.len(), measurements.len());
+        assert!(same_measurements
+            .iter()
+            .zip(&measurements)
+            .all(|(same, measurement)| same.vector == measurement.vector));
+    }
+
+    #[test]
+    fn test_offset() {
+        // the U-shape: with a lateral offset the way to the table and the way back are apart
+        let around_table = Path::around_table(10., 2., 1.);
+        let start = Utc.timestamp_nanos(0);
+        let measurements = SensorSimulator::position_with_offset(
+            Duration::milliseconds(100),
+            Vector2::new(0., 0.5),
+        )
+        .unwrap()
+        .with_seed(21)
+        .simulate(&around_table, start);
+        let (way_there, way_back): (Vec<_>, Vec<_>) = measurements
+            .iter()
+            .filter(|measurement| measurement.vector.y > 1. && measurement.vector.y < 9.)
+            .partition(|measurement| measurement.timestamp < start + Duration::seconds(10));
+        assert!(way_there
+            .iter()
+            .all(|measurement| (measurement.vector.x + 0.5).abs() < 1e-6));
+        assert!(way_back
+            .iter()
+            .all(|measurement| (measurement.vector.x - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        // the samples would never reach the end without a positive interval
+        assert!(matches!(
+            SensorSimulator::position(Duration::zero()),
+            Err(EstimationError::InvalidParameter(_))
+        ));
+        let interval = Duration::milliseconds(100);
+        assert!(matches!(
+            SensorSimulator::position(interval)
+                .unwrap()
+                .with_dropout_probability(30.),
+            Err(EstimationError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            SensorSimulator::position(interval)
+                .unwrap()
+                .with_latency(Duration::milliseconds(-50)),
+            Err(EstimationError::InvalidParameter(_))
+        ));
+    }
+}
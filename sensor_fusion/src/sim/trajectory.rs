@@ -0,0 +1,468 @@
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI, SQRT_2};
+
+use chrono::{DateTime, Duration, Utc};
+use nalgebra::{SMatrix, SVector, Vector2, Vector4};
+
+use crate::estimator::EstimationError;
+use crate::state::{GaussianState, Waypoint};
+use crate::track::Track;
+
+/// # Explanation
+/// The Kinematics describe the true motion at one point in time. The heading is measured counterclockwise
+/// from the x axis (like in the coordinated turn model) and the yaw rate is its derivative.
+#[derive(Debug, Copy, Clone)]
+pub struct Kinematics {
+    pub position: Vector2<f64>,
+    pub velocity: Vector2<f64>,
+    pub acceleration: Vector2<f64>,
+    pub heading: f64,
+    pub yaw_rate: f64,
+}
+
+impl Kinematics {
+    /// # Returns
+    /// Returns the state of the constant velocity model (x, y, vx, vy).
+    pub fn constant_velocity_state(&self) -> Vector4<f64> {
+        Vector4::new(
+            self.position.x,
+            self.position.y,
+            self.velocity.x,
+            self.velocity.y,
+        )
+    }
+
+    /// # Returns
+    /// Returns the state of the constant acceleration model (x, y, vx, vy, ax, ay).
+    pub fn constant_acceleration_state(&self) -> SVector<f64, 6> {
+        SVector::<f64, 6>::new(
+            self.position.x,
+            self.position.y,
+            self.velocity.x,
+            self.velocity.y,
+            self.acceleration.x,
+            self.acceleration.y,
+        )
+    }
+
+    /// # Returns
+    /// Returns the state of the coordinated turn and the differential drive model
+    /// (x, y, v, heading, yaw rate).
+    pub fn coordinated_turn_state(&self) -> SVector<f64, 5> {
+        SVector::<f64, 5>::new(
+            self.position.x,
+            self.position.y,
+            self.velocity.norm(),
+            self.heading,
+            self.yaw_rate,
+        )
+    }
+}
+
+/// # Explanation
+/// A Trajectory is the true motion of the robot over time (starting at t = 0 seconds). It is the ground
+/// truth of a simulation: the SensorSimulators measure it and the estimated track can be compared to the
+/// track of ground_truth.
+pub trait Trajectory {
+    /// # Returns
+    /// Returns the duration of the trajectory in seconds.
+    fn duration(&self) -> f64;
+
+    /// # Returns
+    /// Returns the kinematics at t seconds after the start.
+    fn kinematics(&self, t: f64) -> Kinematics;
+
+    /// # Returns
+    /// Returns the track of the trajectory with a waypoint every interval (starting at the start
+    /// timestamp). to_state converts the kinematics to the state of the model (eg
+    /// Kinematics::constant_velocity_state); the errors of the states are zero.
+    /// EstimationError::InvalidParameter is returned if the interval is not positive.
+    fn ground_truth<const SD: usize>(
+        &self,
+        start: DateTime<Utc>,
+        interval: Duration,
+        to_state: impl Fn(&Kinematics) -> SVector<f64, SD>,
+    ) -> Result<Track<SD>, EstimationError>
+    where
+        Self: Sized,
+    {
+        check_interval(interval)?;
+        let mut waypoints = sample_times(self, interval, 0).map(|(offset, t)| {
+            let state = GaussianState::new(to_state(&self.kinematics(t)), SMatrix::zeros());
+            Waypoint::new(start + offset, state)
+        });
+
+        // the first sample is at t = 0
+        let mut ground_truth = Track::new(waypoints.next().unwrap());
+        waypoints.for_each(|waypoint| ground_truth.add_waypoint(waypoint));
+        Ok(ground_truth)
+    }
+}
+
+/// # Explanation
+/// Checks that the interval of the samples is positive, otherwise the samples would never reach the end
+/// of the trajectory.
+pub(crate) fn check_interval(interval: Duration) -> Result<(), EstimationError> {
+    if interval <= Duration::zero() {
+        return Err(EstimationError::InvalidParameter(format!(
+            "the interval {} is not positive",
+            interval
+        )));
+    }
+    Ok(())
+}
+
+/// # Returns
+/// Returns the offsets (as duration and in seconds) of the samples of the trajectory every interval,
+/// starting with the sample with the given index. The interval must be positive (see check_interval).
+pub(crate) fn sample_times(
+    trajectory: &impl Trajectory,
+    interval: Duration,
+    first_sample: i32,
+) -> impl Iterator<Item = (Duration, f64)> {
+    let duration = trajectory.duration();
+    (first_sample..)
+        .map(move |i| interval * i)
+        .map(|offset| (offset, offset.num_milliseconds() as f64 / 1000.0))
+        .take_while(move |(_, t)| *t <= duration)
+}
+
+/// # Explanation
+/// The FigureEight is the shape of an eight (like the robot was often driven):<br>
+/// x = width / 2 * sin(ωt)<br>
+/// y = height / 2 * sin(2ωt)<br>
+/// with ω = 2π / period.
+///
+/// The heading is continuous (it stays within (-3/2 π, π/2)), so it can be used as the heading of the
+/// state without unwrapping.
+pub struct FigureEight {
+    width: f64,
+    height: f64,
+    period: f64,
+    duration: f64,
+}
+
+impl FigureEight {
+    pub fn new(width: f64, height: f64, period: f64, duration: f64) -> Self {
+        Self {
+            width,
+            height,
+            period,
+            duration,
+        }
+    }
+}
+
+impl Trajectory for FigureEight {
+    fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    fn kinematics(&self, t: f64) -> Kinematics {
+        let omega = 2. * PI / self.period;
+        let (a, b) = (self.width / 2., self.height / 2.);
+        let position = Vector2::new(a * (omega * t).sin(), b * (2. * omega * t).sin());
+        let velocity = Vector2::new(
+            a * omega * (omega * t).cos(),
+            2. * b * omega * (2. * omega * t).cos(),
+        );
+        let acceleration = Vector2::new(
+            -a * omega.powi(2) * (omega * t).sin(),
+            -4. * b * omega.powi(2) * (2. * omega * t).sin(),
+        );
+        // the speed of the figure eight is never zero
+        let yaw_rate =
+            (velocity.x * acceleration.y - velocity.y * acceleration.x) / velocity.norm_squared();
+        // the first loop is driven clockwise and the second one counterclockwise, so the heading only
+        // passes through (-3/2 pi, pi/2) and is continuous in this range (atan2 would jump at ±pi)
+        let heading = velocity.y.atan2(velocity.x);
+        let heading = if heading > FRAC_PI_2 {
+            heading - 2. * PI
+        } else {
+            heading
+        };
+
+        Kinematics {
+            position,
+            velocity,
+            acceleration,
+            heading,
+            yaw_rate,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Segment {
+    Straight,
+    Turn { radius: f64, angle: f64 },
+    Stop,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct TimedSegment {
+    segment: Segment,
+    start_time: f64,
+    duration: f64,
+    start_position: Vector2<f64>,
+    start_heading: f64,
+}
+
+/// # Explanation
+/// A Path is made up of straight lines, turns (arcs of circles) and stops that are driven one after the
+/// other with a constant speed. The robot stops and starts again immediately, so the velocity jumps at
+/// the beginning and the end of a stop.
+///
+/// The path is built with the builder methods, eg:<br>
+/// Path::new(Vector2::zeros(), 0., 1.).straight(5.).turn(1., PI / 2.).stop(2.).straight(3.)
+pub struct Path {
+    speed: f64,
+    segments: Vec<TimedSegment>,
+    end_position: Vector2<f64>,
+    end_heading: f64,
+    duration: f64,
+}
+
+impl Path {
+    /// # Parameters
+    /// The path starts at the start position with the start heading and is driven with the speed (in m/s).
+    pub fn new(start_position: Vector2<f64>, start_heading: f64, speed: f64) -> Self {
+        Self {
+            speed,
+            segments: Vec::new(),
+            end_position: start_position,
+            end_heading: start_heading,
+            duration: 0.,
+        }
+    }
+
+    /// # Explanation
+    /// Drives straight ahead for the given length (in meters).
+    pub fn straight(self, length: f64) -> Self {
+        let duration = length / self.speed;
+        self.with_segment(Segment::Straight, duration)
+    }
+
+    /// # Explanation
+    /// Turns on a circle with the given radius by the given angle (in radians, positive angles turn left).
+    pub fn turn(self, radius: f64, angle: f64) -> Self {
+        let duration = radius * angle.abs() / self.speed;
+        self.with_segment(Segment::Turn { radius, angle }, duration)
+    }
+
+    /// # Explanation
+    /// Stands still for the given duration (in seconds).
+    pub fn stop(self, duration: f64) -> Self {
+        self.with_segment(Segment::Stop, duration)
+    }
+
+    /// # Explanation
+    /// The path of the runs in the README: the robot drives the given distance north (from the origin),
+    /// drives a loop with the given radius around the table and drives back to the origin on the same
+    /// line. So the shape is a balloon.
+    pub fn around_table(distance: f64, loop_radius: f64, speed: f64) -> Self {
+        // the loop is entered and left with a small turn and a straight line, which are chosen such that
+        // the center of the loop lies on the line to the table
+        let entry_radius = loop_radius / 4.;
+        let entry_length = loop_radius - entry_radius * (SQRT_2 - 1.);
+        Self::new(Vector2::zeros(), FRAC_PI_2, speed)
+            .straight(distance)
+            .turn(entry_radius, -FRAC_PI_4)
+            .straight(entry_length)
+            .turn(loop_radius, 3. * FRAC_PI_2)
+            .straight(entry_length)
+            .turn(entry_radius, -FRAC_PI_4)
+            .straight(distance)
+    }
+
+    /// # Explanation
+    /// Drives straight lines of the given length along the x axis that are interrupted by stops of the
+    /// given duration.
+    pub fn stop_and_go(lines: usize, length: f64, stop_duration: f64, speed: f64) -> Self {
+        (1..lines).fold(
+            Self::new(Vector2::zeros(), 0., speed).straight(length),
+            |path, _| path.stop(stop_duration).straight(length),
+        )
+    }
+
+    fn with_segment(mut self, segment: Segment, duration: f64) -> Self {
+        let timed_segment = TimedSegment {
+            segment,
+            start_time: self.duration,
+            duration,
+            start_position: self.end_position,
+            start_heading: self.end_heading,
+        };
+        let end = self.segment_kinematics(&timed_segment, duration);
+        self.end_position = end.position;
+        self.end_heading = end.heading;
+        self.duration += duration;
+        self.segments.push(timed_segment);
+        self
+    }
+
+    /// # Returns
+    /// Returns the kinematics at t seconds after the start of the segment.
+    fn segment_kinematics(&self, timed_segment: &TimedSegment, t: f64) -> Kinematics {
+        let heading = timed_segment.start_heading;
+        let direction = Vector2::new(heading.cos(), heading.sin());
+        match timed_segment.segment {
+            Segment::Straight => Kinematics {
+                position: timed_segment.start_position + direction * self.speed * t,
+                velocity: direction * self.speed,
+                acceleration: Vector2::zeros(),
+                heading,
+                yaw_rate: 0.,
+            },
+            Segment::Turn { radius, angle } => {
+                let sign = angle.signum();
+                let yaw_rate = sign * self.speed / radius;
+                let center = timed_segment.start_position
+                    + sign * radius * Vector2::new(-heading.sin(), heading.cos());
+                let heading = heading + yaw_rate * t;
+                let direction = Vector2::new(heading.cos(), heading.sin());
+                Kinematics {
+                    position: center + sign * radius * Vector2::new(heading.sin(), -heading.cos()),
+                    velocity: direction * self.speed,
+                    acceleration: Vector2::new(-heading.sin(), heading.cos())
+                        * self.speed
+                        * yaw_rate,
+                    heading,
+                    yaw_rate,
+                }
+            }
+            Segment::Stop => Kinematics {
+                position: timed_segment.start_position,
+                velocity: Vector2::zeros(),
+                acceleration: Vector2::zeros(),
+                heading,
+                yaw_rate: 0.,
+            },
+        }
+    }
+}
+
+impl Trajectory for Path {
+    fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    /// # Explanation
+    /// Before the start and after the end of the path the robot stands still.
+    fn kinematics(&self, t: f64) -> Kinematics {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.start_time + segment.duration <= t);
+        match self.segments.get(index) {
+            Some(segment) => self.segment_kinematics(segment, (t - segment.start_time).max(0.)),
+            None => Kinematics {
+                position: self.end_position,
+                velocity: Vector2::zeros(),
+                acceleration: Vector2::zeros(),
+                heading: self.end_heading,
+                yaw_rate: 0.,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use nalgebra::Vector2;
+
+    use crate::estimator::EstimationError;
+    use crate::sim::trajectory::{FigureEight, Kinematics, Path, Trajectory};
+
+    #[test]
+    fn test_figure_eight() {
+        // x = sin(t) and y = sin(2t)
+        let figure_eight = FigureEight::new(2., 2., 2. * PI, 10.);
+        for t in [0., 1.3, 4.2, 9.9] {
+            let kinematics = figure_eight.kinematics(t);
+            let expected = [
+                Vector2::new(t.sin(), (2. * t).sin()),
+                Vector2::new(t.cos(), 2. * (2. * t).cos()),
+                Vector2::new(-t.sin(), -4. * (2. * t).sin()),
+            ];
+            for (vector, expected) in [
+                kinematics.position,
+                kinematics.velocity,
+                kinematics.acceleration,
+            ]
+            .into_iter()
+            .zip(expected)
+            {
+                assert!((vector - expected).norm() < 1e-12);
+            }
+        }
+        // the heading of the figure eight does not jump by 2π (it turns by at most 0.6 rad in 0.1 s)
+        for i in 0..99 {
+            let t = i as f64 * 0.1;
+            let step =
+                figure_eight.kinematics(t + 0.1).heading - figure_eight.kinematics(t).heading;
+            assert!(step.abs() < 1.);
+        }
+    }
+
+    #[test]
+    fn test_paths() {
+        let around_table = Path::around_table(10., 2., 1.);
+        let stop_and_go = Path::stop_and_go(3, 5., 2., 1.);
+        assert!((stop_and_go.duration() - 19.).abs() < 1e-12);
+        assert_eq!(stop_and_go.kinematics(6.).velocity, Vector2::zeros());
+        let turns = Path::new(Vector2::zeros(), 0., 2.)
+            .straight(4.)
+            .turn(1., PI / 2.)
+            .straight(4.)
+            .turn(3., -PI);
+        let end = turns.kinematics(turns.duration());
+        assert!((end.position - Vector2::new(11., 5.)).norm() < 1e-9);
+        assert!((end.heading + PI / 2.).abs() < 1e-9);
+
+        // the velocity is the derivative of the position
+        for path in [&around_table, &stop_and_go, &turns] {
+            let samples = ((path.duration() - 0.1) / 0.1) as usize;
+            for i in 0..=samples {
+                let t = 0.05 + i as f64 * 0.1;
+                let kinematics = path.kinematics(t);
+                let difference = (path.kinematics(t + 1e-6).position
+                    - path.kinematics(t - 1e-6).position)
+                    / 2e-6;
+                if kinematics.velocity.norm() > 0. {
+                    assert!((difference - kinematics.velocity).norm() < 1e-4);
+                }
+            }
+        }
+
+        // the balloon ends where it started
+        let start = around_table.kinematics(0.);
+        let end = around_table.kinematics(around_table.duration());
+        assert!(end.position.norm() < 1e-9);
+        assert!((end.heading - start.heading - PI).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ground_truth() {
+        let around_table = Path::around_table(10., 2., 1.);
+        let start = Utc.timestamp_nanos(0);
+        let ground_truth = around_table
+            .ground_truth(
+                start,
+                Duration::milliseconds(100),
+                Kinematics::coordinated_turn_state,
+            )
+            .unwrap();
+        assert_eq!(
+            ground_truth.len(),
+            (around_table.duration() * 10.).floor() as usize + 1
+        );
+
+        // the samples would never reach the end without a positive interval
+        assert!(matches!(
+            around_table.ground_truth(start, Duration::zero(), Kinematics::coordinated_turn_state),
+            Err(EstimationError::InvalidParameter(_))
+        ));
+    }
+}
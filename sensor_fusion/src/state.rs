@@ -1,15 +1,43 @@
-use chrono::{DateTime, Utc};
-use nalgebra::{SMatrix, SVector};
+use chrono::{DateTime, Duration, Utc};
+use nalgebra::{DMatrix, SMatrix, SVector};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Clone)]
+use crate::estimator::EstimationError;
+use crate::linalg::to_dynamic;
+
+/// # Explanation
+/// A waypoint is the (filtered) state at a specific time. If the waypoint was created by a filter the
+/// prediction the filter used (the prior) can also be stored so that the track can be smoothed afterwards.
+/// Estimators with multiple models store the probability of every model in mode_probabilities.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Waypoint<const D: usize> {
     pub timestamp: DateTime<Utc>,
     pub state: GaussianState<D>,
+    pub prediction: Option<Prediction<D>>,
+    pub mode_probabilities: Option<Vec<f64>>,
 }
 
 impl<const D: usize> Waypoint<D> {
     pub fn new(timestamp: DateTime<Utc>, state: GaussianState<D>) -> Self {
-        Self { timestamp, state }
+        Self {
+            timestamp,
+            state,
+            prediction: None,
+            mode_probabilities: None,
+        }
+    }
+
+    pub fn with_prediction(
+        timestamp: DateTime<Utc>,
+        state: GaussianState<D>,
+        prediction: Prediction<D>,
+    ) -> Self {
+        Self {
+            timestamp,
+            state,
+            prediction: Some(prediction),
+            mode_probabilities: None,
+        }
     }
 
     pub fn from_state(state: GaussianState<D>) -> Self {
@@ -17,7 +45,23 @@ impl<const D: usize> Waypoint<D> {
     }
 }
 
-#[derive(Debug, Clone)]
+/// # Explanation
+/// The prediction is the state that was predicted from the previous waypoint before the measurement was
+/// incorporated. dt is the time that has passed since the previous waypoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prediction<const D: usize> {
+    #[serde(with = "duration_nanoseconds")]
+    pub dt: Duration,
+    pub state: GaussianState<D>,
+}
+
+impl<const D: usize> Prediction<D> {
+    pub fn new(dt: Duration, state: GaussianState<D>) -> Self {
+        Self { dt, state }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Measurement<const D: usize> {
     pub timestamp: DateTime<Utc>,
     pub vector: SVector<f64, D>,
@@ -38,7 +82,7 @@ impl<const D: usize> Measurement<D> {
 ///
 /// # Type parameters
 /// SD is the dimension of the state (eg four for the constant velocity model).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GaussianState<const D: usize> {
     pub estimate: SVector<f64, D>,
     pub error: SMatrix<f64, D, D>,
@@ -48,4 +92,103 @@ impl<const D: usize> GaussianState<D> {
     pub fn new(estimate: SVector<f64, D>, error: SMatrix<f64, D, D>) -> Self {
         Self { estimate, error }
     }
+
+    /// # Explanation
+    /// Checks that the estimate and the error are finite and that the error is a valid covariance matrix
+    /// (symmetric and positive semi-definite up to rounding errors). Otherwise
+    /// EstimationError::NumericalError is returned with the reason.
+    pub fn validate(&self) -> Result<(), EstimationError> {
+        validate_gaussian(self.estimate.as_slice(), &to_dynamic(&self.error))
+    }
+}
+
+/// # Explanation
+/// Checks the estimate and the error of a gaussian state of any dimension (see GaussianState::validate).
+pub(crate) fn validate_gaussian(
+    estimate: &[f64],
+    error: &DMatrix<f64>,
+) -> Result<(), EstimationError> {
+    if estimate.iter().any(|value| !value.is_finite()) {
+        return Err(EstimationError::NumericalError(format!(
+            "the estimate {:?} is not finite",
+            estimate
+        )));
+    }
+    if error.iter().any(|value| !value.is_finite()) {
+        return Err(EstimationError::NumericalError(
+            "the error is not finite".to_string(),
+        ));
+    }
+
+    // the standard update accumulates small asymmetries over long tracks
+    let scale = error.amax().max(1.);
+    let asymmetry = (error - error.transpose()).amax();
+    if asymmetry > 1e-6 * scale {
+        return Err(EstimationError::NumericalError(format!(
+            "the error is not symmetric (difference of {:e})",
+            asymmetry
+        )));
+    }
+    // the validation runs after every update, so a cholesky decomposition is used instead of the
+    // eigenvalues: it only exists if no eigenvalue is below the negative tolerance
+    let tolerance = 1e-9 * scale;
+    let shifted = error + DMatrix::<f64>::identity(error.nrows(), error.ncols()) * tolerance;
+    if shifted.cholesky().is_none() {
+        return Err(EstimationError::NumericalError(format!(
+            "the error is not positive semi-definite (tolerance of {:e})",
+            tolerance
+        )));
+    }
+    Ok(())
+}
+
+/// # Explanation
+/// chrono does not implement serde for durations, so they are stored as nanoseconds.
+pub(crate) mod duration_nanoseconds {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        let nanoseconds = duration
+            .num_nanoseconds()
+            .ok_or_else(|| serde::ser::Error::custom("the duration is too long"))?;
+        serializer.serialize_i64(nanoseconds)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::nanoseconds(i64::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{SMatrix, SVector};
+
+    use crate::estimator::EstimationError;
+    use crate::state::GaussianState;
+
+    #[test]
+    fn test_validation() {
+        let estimate = SVector::<f64, 2>::new(1., 2.);
+        let valid = GaussianState::new(estimate, SMatrix::<f64, 2, 2>::new(2., 1., 1., 2.));
+        assert!(valid.validate().is_ok());
+        // the ground truth does not have an error
+        assert!(GaussianState::new(estimate, SMatrix::<f64, 2, 2>::zeros())
+            .validate()
+            .is_ok());
+
+        let invalid_states = [
+            GaussianState::new(
+                SVector::<f64, 2>::new(f64::NAN, 2.),
+                SMatrix::<f64, 2, 2>::identity(),
+            ),
+            GaussianState::new(estimate, SMatrix::<f64, 2, 2>::new(1., 0., 2., 1.)),
+            GaussianState::new(estimate, SMatrix::<f64, 2, 2>::new(1., 2., 2., 1.)),
+        ];
+        for state in invalid_states {
+            assert!(matches!(
+                state.validate(),
+                Err(EstimationError::NumericalError(_))
+            ));
+        }
+    }
 }
@@ -1,17 +1,21 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Utc};
 use plotly::{Plot, Scatter};
 use plotly::common::Mode;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::state::Waypoint;
+use crate::state::{GaussianState, Waypoint};
 
 #[derive(Clone)]
 pub struct Track<const D: usize> {
-    waypoints: Vec<Waypoint<D>>,
+    waypoints: VecDeque<Waypoint<D>>,
 }
 
 impl<const D: usize> Track<D> {
     pub fn new(initial_waypoint: Waypoint<D>) -> Self {
         Self {
-            waypoints: vec![initial_waypoint],
+            waypoints: VecDeque::from([initial_waypoint]),
         }
     }
 
@@ -19,12 +23,120 @@ impl<const D: usize> Track<D> {
         self.waypoints.len()
     }
 
+    /// # Explanation
+    /// The waypoint is appended to the track, so it must not be older than the latest waypoint. The order
+    /// is not checked, but get_waypoint, state_at, slice and split_off_after search the waypoints by their
+    /// timestamps and return wrong results for an unordered track.
     pub fn add_waypoint(&mut self, waypoint: Waypoint<D>) {
-        self.waypoints.push(waypoint);
+        self.waypoints.push_back(waypoint);
     }
 
     pub fn get_latest_waypoint(&self) -> &Waypoint<D> {
-        self.waypoints.last().unwrap() // waypoints cannot be empty
+        self.waypoints.back().unwrap() // waypoints cannot be empty
+    }
+
+    pub fn get_first_waypoint(&self) -> &Waypoint<D> {
+        self.waypoints.front().unwrap() // waypoints cannot be empty
+    }
+
+    /// # Returns
+    /// Removes and returns the oldest waypoint. None is returned if it is the only waypoint, since the
+    /// track cannot be empty.
+    pub fn remove_first_waypoint(&mut self) -> Option<Waypoint<D>> {
+        if self.waypoints.len() > 1 {
+            self.waypoints.pop_front()
+        } else {
+            None
+        }
+    }
+
+    /// # Returns
+    /// Returns the waypoint with exactly the given timestamp.
+    pub fn get_waypoint(&self, timestamp: DateTime<Utc>) -> Option<&Waypoint<D>> {
+        self.waypoints
+            .binary_search_by_key(&timestamp, |waypoint| waypoint.timestamp)
+            .ok()
+            .map(|index| &self.waypoints[index])
+    }
+
+    pub fn iter(&self) -> std::collections::vec_deque::Iter<'_, Waypoint<D>> {
+        self.waypoints.iter()
+    }
+
+    /// # Returns
+    /// Returns the state at the given timestamp. Between two waypoints the estimate and the error are
+    /// interpolated linearly. None is returned if the timestamp is before the first or after the latest
+    /// waypoint.
+    pub fn state_at(&self, timestamp: DateTime<Utc>) -> Option<GaussianState<D>> {
+        let index = self
+            .waypoints
+            .partition_point(|waypoint| waypoint.timestamp < timestamp);
+        let after = self.waypoints.get(index)?;
+        if after.timestamp == timestamp {
+            return Some(after.state.clone());
+        }
+        let before = &self.waypoints[index.checked_sub(1)?];
+
+        let span = seconds(after.timestamp - before.timestamp);
+        let elapsed = seconds(timestamp - before.timestamp);
+        if span <= 0. {
+            return Some(before.state.clone());
+        }
+        let weight = elapsed / span;
+        Some(GaussianState::new(
+            (1. - weight) * before.state.estimate + weight * after.state.estimate,
+            (1. - weight) * before.state.error + weight * after.state.error,
+        ))
+    }
+
+    /// # Returns
+    /// Returns the track of the waypoints between start and end (both inclusive). None is returned if
+    /// there is no such waypoint.
+    pub fn slice(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<Track<D>> {
+        let first = self
+            .waypoints
+            .partition_point(|waypoint| waypoint.timestamp < start);
+        let last = self
+            .waypoints
+            .partition_point(|waypoint| waypoint.timestamp <= end);
+        if first >= last {
+            return None;
+        }
+        Some(Self {
+            waypoints: self.waypoints.range(first..last).cloned().collect(),
+        })
+    }
+
+    /// # Returns
+    /// Returns the track sampled every interval, starting at the first waypoint (see state_at). The
+    /// resampled waypoints contain neither predictions nor mode probabilities. None is returned if the
+    /// interval is not positive.
+    pub fn resample(&self, interval: Duration) -> Option<Track<D>> {
+        if interval <= Duration::zero() {
+            return None;
+        }
+
+        let mut resampled = Track::new(Waypoint::new(
+            self.get_first_waypoint().timestamp,
+            self.get_first_waypoint().state.clone(),
+        ));
+        let mut timestamp = self.get_first_waypoint().timestamp + interval;
+        while let Some(state) = self.state_at(timestamp) {
+            resampled.add_waypoint(Waypoint::new(timestamp, state));
+            timestamp += interval;
+        }
+        Some(resampled)
+    }
+
+    /// # Returns
+    /// Removes and returns the waypoints that are newer than the given timestamp (oldest first). The first
+    /// waypoint is never removed, since the track cannot be empty.
+    pub fn split_off_after(&mut self, timestamp: DateTime<Utc>) -> Vec<Waypoint<D>> {
+        let index = self
+            .waypoints
+            .partition_point(|waypoint| waypoint.timestamp <= timestamp)
+            .max(1);
+        self.waypoints.split_off(index).into()
     }
 
     pub fn create_scatter(
@@ -50,9 +162,130 @@ impl<const D: usize> Track<D> {
 
 impl<const D: usize> IntoIterator for Track<D> {
     type Item = Waypoint<D>;
-    type IntoIter = std::vec::IntoIter<Self::Item>;
+    type IntoIter = std::collections::vec_deque::IntoIter<Self::Item>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.waypoints.into_iter()
     }
 }
+
+impl<'a, const D: usize> IntoIterator for &'a Track<D> {
+    type Item = &'a Waypoint<D>;
+    type IntoIter = std::collections::vec_deque::Iter<'a, Waypoint<D>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.waypoints.iter()
+    }
+}
+
+/// # Explanation
+/// A track is serialized as the sequence of its waypoints. Deserializing an empty sequence fails, since a
+/// track cannot be empty.
+impl<const D: usize> Serialize for Track<D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.waypoints.serialize(serializer)
+    }
+}
+
+impl<'de, const D: usize> Deserialize<'de> for Track<D> {
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let waypoints = VecDeque::<Waypoint<D>>::deserialize(deserializer)?;
+        if waypoints.is_empty() {
+            return Err(serde::de::Error::custom(
+                "a track needs at least one waypoint",
+            ));
+        }
+        Ok(Self { waypoints })
+    }
+}
+
+/// # Returns
+/// Returns the duration in seconds. Durations that do not fit into nanoseconds (about 292 years) are only
+/// precise to the millisecond.
+fn seconds(duration: Duration) -> f64 {
+    match duration.num_nanoseconds() {
+        Some(nanoseconds) => nanoseconds as f64 / 1e9,
+        None => duration.num_milliseconds() as f64 / 1000.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use nalgebra::{SMatrix, SVector};
+
+    use crate::sim::trajectory::{FigureEight, Kinematics, Trajectory};
+    use crate::state::{GaussianState, Waypoint};
+    use crate::track::Track;
+
+    fn at(t: f64) -> DateTime<Utc> {
+        Utc.timestamp_nanos((t * 1_000_000_000.0) as i64)
+    }
+
+    #[test]
+    fn test_track_queries() {
+        let figure_eight = FigureEight::new(2., 2., 2. * PI, 10.);
+        let ground_truth = figure_eight
+            .ground_truth(
+                at(0.),
+                Duration::milliseconds(100),
+                Kinematics::constant_velocity_state,
+            )
+            .unwrap();
+        assert_eq!(ground_truth.iter().count(), ground_truth.len());
+        assert_eq!((&ground_truth).into_iter().count(), ground_truth.len());
+
+        // between two waypoints the state is interpolated
+        let first = ground_truth.get_first_waypoint();
+        let second = ground_truth.iter().nth(1).unwrap();
+        let middle = first.timestamp + (second.timestamp - first.timestamp) / 2;
+        let state = ground_truth.state_at(middle).unwrap();
+        assert!(
+            (state.estimate - (first.state.estimate + second.state.estimate) / 2.).norm() < 1e-9
+        );
+        assert_eq!(
+            ground_truth.state_at(second.timestamp).unwrap().estimate,
+            second.state.estimate
+        );
+        assert!(ground_truth.state_at(at(-1.)).is_none());
+        assert!(ground_truth.state_at(at(11.)).is_none());
+
+        let slice = ground_truth.slice(at(2.05), at(3.05)).unwrap();
+        assert_eq!(slice.len(), 10);
+        assert!(slice
+            .iter()
+            .all(|waypoint| at(2.05) <= waypoint.timestamp && waypoint.timestamp <= at(3.05)));
+        assert!(ground_truth.slice(at(11.), at(12.)).is_none());
+
+        let resampled = ground_truth.resample(Duration::milliseconds(250)).unwrap();
+        let duration = ground_truth.get_latest_waypoint().timestamp - first.timestamp;
+        assert_eq!(
+            resampled.len() as i64,
+            duration.num_milliseconds() / 250 + 1
+        );
+        for waypoint in resampled.iter() {
+            let t = (waypoint.timestamp - first.timestamp).num_milliseconds() as f64 / 1000.0;
+            let position = figure_eight.kinematics(t).position;
+            assert!((waypoint.state.estimate[0] - position.x).abs() < 0.01);
+            assert!((waypoint.state.estimate[1] - position.y).abs() < 0.01);
+        }
+        assert!(ground_truth.resample(Duration::zero()).is_none());
+    }
+
+    #[test]
+    fn test_sub_millisecond_interpolation() {
+        // waypoints that are less than a millisecond apart are interpolated as well
+        let mut short_track = Track::new(Waypoint::new(
+            at(0.),
+            GaussianState::new(SVector::<f64, 1>::new(0.), SMatrix::<f64, 1, 1>::identity()),
+        ));
+        short_track.add_waypoint(Waypoint::new(
+            at(0.0005),
+            GaussianState::new(SVector::<f64, 1>::new(1.), SMatrix::<f64, 1, 1>::identity()),
+        ));
+        let state = short_track.state_at(at(0.0001)).unwrap();
+        assert!((state.estimate[0] - 0.2).abs() < 1e-6);
+    }
+}
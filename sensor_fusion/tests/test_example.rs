@@ -1,9 +1,50 @@
-use chrono::{TimeZone, Utc};
-use nalgebra::{SMatrix, SVector};
-use rand::Rng;
+use std::f64::consts::PI;
 
-use sensor_fusion::kalman::estimator::KalmanFilter;
-use sensor_fusion::kalman::model::{ConstantVelocity, PositionMeasurementModel};
+use chrono::{Duration, TimeZone, Utc};
+use nalgebra::{DMatrix, DVector, Rotation2, SMatrix, SVector, Vector2};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use sensor_fusion::config::{ConfigError, EstimatorConfig};
+use sensor_fusion::delayed::ReprocessingEstimator;
+use sensor_fusion::dynamic::estimator::{DynamicEstimator, DynamicKalmanFilter};
+use sensor_fusion::dynamic::model::{StaticMeasurementModel, StaticTransitionModel};
+use sensor_fusion::dynamic::state::{DynamicMeasurement, DynamicWaypoint};
+use sensor_fusion::dynamic::track::DynamicTrack;
+use sensor_fusion::estimator::{EstimationError, Estimator};
+use sensor_fusion::evaluation::{nees_statistics, nis_statistics, ConsistencyStatistics};
+use sensor_fusion::kalman::adaptive::AdaptiveKalmanFilter;
+use sensor_fusion::kalman::bias;
+use sensor_fusion::kalman::bias::{
+    BiasAugmented, BiasParameters, BiasedPositionMeasurementModel, BiasedVelocityMeasurementModel,
+};
+use sensor_fusion::kalman::diagnostics::DiagnosticFilter;
+use sensor_fusion::kalman::ekf::ExtendedKalmanFilter;
+use sensor_fusion::kalman::estimator::{CovarianceUpdate, KalmanFilter};
+use sensor_fusion::kalman::imm::InteractingMultipleModel;
+use sensor_fusion::kalman::model::{
+    BodyVelocityMeasurementModel, ConstantAcceleration, ConstantVelocity, CoordinatedTurn,
+    DifferentialDrive, HeadingMeasurementModel, KinematicMeasurementModel,
+    PolarVelocityMeasurementModel, PositionMeasurementModel, Stationary, VelocityMeasurementModel,
+};
+use sensor_fusion::kalman::outlier::{chi_square_quantile, OutlierHandling};
+use sensor_fusion::kalman::sequential::{
+    ModeledMeasurement, SensorMeasurement, SequentialKalmanFilter,
+};
+use sensor_fusion::kalman::smoother::{FixedLagSmoother, RauchTungStriebelSmoother};
+use sensor_fusion::kalman::square_root::SquareRootKalmanFilter;
+use sensor_fusion::kalman::ukf::{SigmaPointParameters, UnscentedKalmanFilter};
+use sensor_fusion::metrics;
+use sensor_fusion::metrics::Alignment;
+use sensor_fusion::model::{
+    ControlInput, ControlledTransitionModel, NonlinearMeasurementModel, NonlinearTransitionModel,
+};
+use sensor_fusion::particle::estimator::ParticleFilter;
+use sensor_fusion::particle::resampling::{
+    Resampler, ResidualResampling, StratifiedResampling, SystematicResampling,
+};
+use sensor_fusion::sim::sensor::SensorSimulator;
+use sensor_fusion::sim::trajectory::{FigureEight, Kinematics, Path, Trajectory};
 use sensor_fusion::state::{GaussianState, Measurement, Waypoint};
 use sensor_fusion::track::Track;
 
@@ -22,15 +63,1011 @@ fn test_example() {
     assert!(score.unwrap() <= 1.5); // quite arbitrary for now
 }
 
-fn create_measurements(ground_truth: Track<4>) -> (Waypoint<4>, Vec<Measurement<2>>) {
+#[test]
+fn test_rts_smoother() {
+    let transition_model = ConstantVelocity::new(0.05);
+    let kalman_filter =
+        KalmanFilter::new(transition_model, PositionMeasurementModel::new(0.1, 0.1));
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+    let track = utils::create_track(kalman_filter, initial_waypoint, measurements).unwrap();
+
+    let smoothed_track = RauchTungStriebelSmoother::new(transition_model).smooth(&track);
+    assert!(smoothed_track.is_ok());
+    let smoothed_track = smoothed_track.unwrap();
+    assert_eq!(smoothed_track.len(), track.len());
+
+    // the maximal error is too noisy to compare, so the mean position error is used
+    assert!(
+        metrics::mean_position_error(&ground_truth, &smoothed_track).unwrap()
+            < metrics::mean_position_error(&ground_truth, &track).unwrap()
+    );
+}
+
+#[test]
+fn test_fixed_lag_smoother() {
+    let transition_model = ConstantVelocity::new(0.05);
+    let fixed_lag_smoother = FixedLagSmoother::new(
+        KalmanFilter::new(transition_model, PositionMeasurementModel::new(0.1, 0.1)),
+        transition_model,
+        10,
+    )
+    .unwrap();
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+    let mut track = Track::new(initial_waypoint);
+
+    let mut lagged_waypoints = Vec::new();
+    for measurement in measurements {
+        let waypoint = fixed_lag_smoother.estimate_waypoint(&track, measurement);
+        assert!(waypoint.is_ok());
+        track.add_waypoint(waypoint.unwrap());
+
+        let smoothed_waypoints = fixed_lag_smoother.smoothed_waypoints();
+        assert!(smoothed_waypoints.len() <= 10);
+        if smoothed_waypoints.len() == 10 {
+            lagged_waypoints.push(fixed_lag_smoother.lagged_waypoint().unwrap());
+        }
+    }
+
+    // the lagged waypoints are compared to the filtered waypoints with the same timestamps
+    let mut lagged_waypoints = lagged_waypoints.into_iter();
+    let mut lagged_track = Track::new(lagged_waypoints.next().unwrap());
+    let mut filtered_track = Track::new(
+        track
+            .get_waypoint(lagged_track.get_first_waypoint().timestamp)
+            .unwrap()
+            .clone(),
+    );
+    for waypoint in lagged_waypoints {
+        filtered_track.add_waypoint(track.get_waypoint(waypoint.timestamp).unwrap().clone());
+        lagged_track.add_waypoint(waypoint);
+    }
+    assert!(
+        metrics::mean_position_error(&ground_truth, &lagged_track).unwrap()
+            < metrics::mean_position_error(&ground_truth, &filtered_track).unwrap()
+    );
+}
+
+#[test]
+fn test_delayed_measurements() {
+    let create_filter = || {
+        KalmanFilter::new(
+            ConstantVelocity::new(0.05),
+            PositionMeasurementModel::new(0.1, 0.1),
+        )
+    };
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) = create_measurements(ground_truth);
+    let in_order_track = utils::create_track(
+        create_filter(),
+        initial_waypoint.clone(),
+        measurements.clone(),
+    )
+    .unwrap();
+
+    // every third measurement arrives after the two following ones
+    let mut delayed_measurements = measurements;
+    delayed_measurements
+        .chunks_mut(3)
+        .for_each(|chunk| chunk.rotate_left(1));
+
+    let estimator = ReprocessingEstimator::new(create_filter(), Duration::milliseconds(500));
+    let mut track = Track::new(initial_waypoint);
+    for measurement in delayed_measurements {
+        assert!(estimator.update(&mut track, measurement).is_ok());
+    }
+    assert_eq!(track.len(), in_order_track.len());
+    for (waypoint, in_order_waypoint) in track.clone().into_iter().zip(in_order_track) {
+        assert_eq!(waypoint.timestamp, in_order_waypoint.timestamp);
+        assert!((waypoint.state.estimate - in_order_waypoint.state.estimate).norm() < 1e-9);
+    }
+}
+
+#[test]
+fn test_outlier_handling() {
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, mut measurements) = create_measurements(ground_truth.clone());
+    // some gps positions jump by two meters (eg because of multipath)
+    measurements
+        .iter_mut()
+        .skip(10)
+        .step_by(20)
+        .for_each(|measurement| measurement.vector[0] += 2.);
+
+    // the outliers can only be detected if the filter trusts the other measurements
+    let run_filter = |outlier_handling: OutlierHandling| {
+        let kalman_filter = KalmanFilter::new(
+            ConstantVelocity::new(5.0),
+            PositionMeasurementModel::new(0.01, 0.01),
+        )
+        .with_outlier_handling(outlier_handling);
+        let mut track = Track::new(initial_waypoint.clone());
+        let mut rejected = Vec::new();
+        for measurement in measurements.clone() {
+            let timestamp = measurement.timestamp;
+            match kalman_filter.estimate_waypoint(&track, measurement) {
+                Ok(waypoint) => track.add_waypoint(waypoint),
+                Err(EstimationError::OutlierRejected(nis)) => {
+                    assert!(nis > chi_square_quantile(0.99, 2));
+                    rejected.push(timestamp);
+                }
+                Err(error) => panic!("{}", error),
+            }
+        }
+        assert_eq!(track.len() + rejected.len(), ground_truth.len());
+        (utils::score(ground_truth.clone(), track), rejected)
+    };
+
+    let (accept_all_score, _) = run_filter(OutlierHandling::AcceptAll);
+    let (gated_score, rejected) = run_filter(OutlierHandling::ChiSquareGate {
+        confidence_level: 0.99,
+    });
+    let (huber_score, _) = run_filter(OutlierHandling::Huber { threshold: 3. });
+
+    // every outlier is rejected, but a few correct measurements may be rejected as well
+    assert!(measurements
+        .iter()
+        .skip(10)
+        .step_by(20)
+        .all(|outlier| rejected.contains(&outlier.timestamp)));
+    assert!(rejected.len() <= 20);
+    assert!(gated_score < accept_all_score);
+    assert!(huber_score < accept_all_score);
+}
+
+#[test]
+fn test_covariance_updates() {
+    let transition_model = ConstantVelocity::new(0.05);
+    let measurement_model = PositionMeasurementModel::new(0.1, 0.1);
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+
+    let standard_track = utils::create_track(
+        KalmanFilter::new(transition_model, measurement_model),
+        initial_waypoint.clone(),
+        measurements.clone(),
+    )
+    .unwrap();
+    let joseph_track = utils::create_track(
+        KalmanFilter::new(transition_model, measurement_model)
+            .with_covariance_update(CovarianceUpdate::Joseph),
+        initial_waypoint.clone(),
+        measurements.clone(),
+    )
+    .unwrap();
+    let square_root_track = utils::create_track(
+        SquareRootKalmanFilter::new(transition_model, measurement_model),
+        initial_waypoint,
+        measurements,
+    )
+    .unwrap();
+
+    // all variants compute the same estimates (up to rounding errors)
+    for ((standard, joseph), square_root) in standard_track
+        .clone()
+        .into_iter()
+        .zip(joseph_track)
+        .zip(square_root_track)
+    {
+        for other in [joseph, square_root] {
+            assert!((standard.state.estimate - other.state.estimate).amax() < 1e-9);
+            assert!((standard.state.error - other.state.error).amax() < 1e-9);
+            assert!(other.state.validate().is_ok());
+        }
+    }
+    assert!(utils::score(ground_truth, standard_track) <= 1.5);
+}
+
+#[test]
+fn test_dynamic_kalman_filter() {
+    let transition_model = ConstantVelocity::new(0.05);
+    let measurement_model = PositionMeasurementModel::new(0.1, 0.1);
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+
+    let static_track = utils::create_track(
+        KalmanFilter::new(transition_model, measurement_model),
+        initial_waypoint.clone(),
+        measurements.clone(),
+    )
+    .unwrap();
+    let dynamic_filter = DynamicKalmanFilter::new(
+        Box::new(StaticTransitionModel::new(transition_model)),
+        Box::new(StaticMeasurementModel::new(measurement_model)),
+    );
+    let mut dynamic_track = DynamicTrack::new(initial_waypoint.clone().into());
+    for measurement in measurements.clone() {
+        let waypoint = dynamic_filter
+            .estimate_waypoint(&dynamic_track, measurement.into())
+            .unwrap();
+        dynamic_track.add_waypoint(waypoint);
+    }
+
+    // the dynamic filter computes the same estimates as the static one
+    let converted_track: Track<4> = dynamic_track.clone().try_into().unwrap();
+    assert_eq!(converted_track.len(), static_track.len());
+    for (converted, waypoint) in converted_track.into_iter().zip(static_track.clone()) {
+        assert_eq!(converted.timestamp, waypoint.timestamp);
+        assert!((converted.state.estimate - waypoint.state.estimate).amax() < 1e-9);
+        assert!((converted.state.error - waypoint.state.error).amax() < 1e-9);
+        let (converted, prediction) = (converted.prediction, waypoint.prediction);
+        assert_eq!(converted.is_some(), prediction.is_some());
+        if let (Some(converted), Some(prediction)) = (converted, prediction) {
+            assert!((converted.state.estimate - prediction.state.estimate).amax() < 1e-9);
+        }
+    }
+    assert!(utils::score(ground_truth, static_track) <= 1.5);
+}
+
+#[test]
+fn test_configured_estimator() {
+    let config: EstimatorConfig = serde_json::from_str(
+        r#"{
+            "filter": { "type": "kalman" },
+            "transition_model": { "type": "constant_velocity", "drift": 0.05 },
+            "measurement_models": {
+                "gps": { "type": "position", "error_x": 0.1, "error_y": 0.1 }
+            },
+            "initial_error": [0.1, 0.1, 0.1, 0.1]
+        }"#,
+    )
+    .unwrap();
+    let estimator = config.build().unwrap();
+    assert_eq!(estimator.state_dimension(), 4);
+
+    // the configured kalman filter computes the same estimates as the static one
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) = create_measurements(ground_truth);
+    let static_track = utils::create_track(
+        KalmanFilter::new(
+            ConstantVelocity::new(0.05),
+            PositionMeasurementModel::new(0.1, 0.1),
+        ),
+        initial_waypoint.clone(),
+        measurements.clone(),
+    )
+    .unwrap();
+    let initial_state = config
+        .initial_state(DVector::from_column_slice(
+            initial_waypoint.state.estimate.as_slice(),
+        ))
+        .unwrap();
+    let mut track = DynamicTrack::new(DynamicWaypoint::new(
+        initial_waypoint.timestamp,
+        initial_state,
+    ));
+    for measurement in measurements {
+        let waypoint = estimator
+            .estimate_waypoint(&track, "gps", measurement.into())
+            .unwrap();
+        track.add_waypoint(waypoint);
+    }
+    let configured_track: Track<4> = track.clone().try_into().unwrap();
+    for (configured, waypoint) in configured_track.into_iter().zip(static_track) {
+        assert!((configured.state.estimate - waypoint.state.estimate).amax() < 1e-9);
+        assert!((configured.state.error - waypoint.state.error).amax() < 1e-9);
+    }
+    let measurement = DynamicMeasurement::new(
+        track.get_latest_waypoint().timestamp + Duration::seconds(1),
+        DVector::from_vec(vec![1., 2.]),
+    );
+    assert!(matches!(
+        estimator.estimate_waypoint(&track, "compass", measurement),
+        Err(ConfigError::UnknownSensor(_))
+    ));
+}
+
+#[test]
+fn test_adaptive_kalman_filter() {
+    // the measurement error is way too large (the measurements are at most 0.1 off)
+    let adaptive_filter = AdaptiveKalmanFilter::new(
+        ConstantVelocity::new(0.05),
+        PositionMeasurementModel::new(1.0, 1.0),
+        20,
+    )
+    .unwrap();
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+    let mut track = Track::new(initial_waypoint);
+    for measurement in measurements {
+        let waypoint = adaptive_filter.estimate_waypoint(&track, measurement);
+        assert!(waypoint.is_ok());
+        track.add_waypoint(waypoint.unwrap());
+    }
+
+    let measurement_error = adaptive_filter.measurement_error();
+    // the adapted error also contains the mismatch of the constant velocity model
+    assert!(measurement_error[(0, 0)] < 0.5 && measurement_error[(1, 1)] < 0.5);
+    assert!(adaptive_filter.transition_error_scale() > 1.);
+
+    let kalman_filter = KalmanFilter::new(
+        ConstantVelocity::new(0.05),
+        PositionMeasurementModel::new(1.0, 1.0),
+    );
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+    let unadapted_track =
+        utils::create_track(kalman_filter, initial_waypoint, measurements).unwrap();
+    assert!(
+        metrics::mean_position_error(&ground_truth, &track).unwrap()
+            < metrics::mean_position_error(&ground_truth, &unadapted_track).unwrap()
+    );
+}
+
+#[test]
+fn test_consistency() {
+    // the object does not move and the measurement error is the variance of the uniform noise
+    let ground_truth = create_ground_truth_with(|_| SVector::<f64, 4>::new(1., 2., 0., 0.));
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+    let estimate = |measurement_error: f64| {
+        let kalman_filter = KalmanFilter::new(
+            ConstantVelocity::new(0.),
+            PositionMeasurementModel::new(measurement_error, measurement_error),
+        );
+        let mut track = Track::new(initial_waypoint.clone());
+        let mut nis = Vec::new();
+        for measurement in measurements.clone() {
+            let (waypoint, diagnostics) = kalman_filter
+                .estimate_waypoint_with_diagnostics(&track, measurement)
+                .unwrap();
+            assert!(diagnostics.log_likelihood.is_finite());
+            nis.push(diagnostics.nis);
+            track.add_waypoint(waypoint);
+        }
+        (track, nis)
+    };
+    let variance = 0.2 * 0.2 / 12.;
+
+    let (track, nis) = estimate(variance);
+    let nis_from_track = nis_statistics(
+        &track,
+        &measurements,
+        &PositionMeasurementModel::new(variance, variance),
+        0.95,
+    )
+    .unwrap();
+    let nis = ConsistencyStatistics::new(nis, 2, 0.95).unwrap();
+    assert_eq!(nis_from_track.values.len(), measurements.len());
+    assert!((nis_from_track.mean - nis.mean).abs() < 1e-9);
+    assert!(nis.lower_bound < 2. && 2. < nis.upper_bound);
+    // a test with 95% confidence fails in 5% of the runs, which is why the measurement noise is seeded
+    assert!(nis.is_consistent());
+    let nees = nees_statistics(&ground_truth, &track, 0.95).unwrap();
+    assert_eq!(nees.values.len(), track.len());
+    // the estimation errors of one run are correlated, so the mean of the NEES varies a lot
+    assert!(nees.mean < 5. * nees.degrees_of_freedom as f64);
+
+    // with a too small measurement error the filter is over-confident
+    let (track, nis) = estimate(0.01 * variance);
+    let nis = ConsistencyStatistics::new(nis, 2, 0.95).unwrap();
+    assert!(nis.mean > nis.upper_bound);
+    let over_confident_nees = nees_statistics(&ground_truth, &track, 0.95).unwrap();
+    assert!(over_confident_nees.mean > 10. * nees.mean);
+}
+
+#[test]
+fn test_metrics() {
+    let ground_truth = create_ground_truth();
+    let kalman_filter = KalmanFilter::new(
+        ConstantVelocity::new(0.05),
+        PositionMeasurementModel::new(0.1, 0.1),
+    );
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+    let track = utils::create_track(kalman_filter, initial_waypoint, measurements).unwrap();
+    let mean_error = metrics::mean_position_error(&ground_truth, &track).unwrap();
+    assert!(mean_error <= metrics::rmse(&ground_truth, &track).unwrap());
+    assert!(
+        metrics::median_position_error(&ground_truth, &track).unwrap()
+            <= metrics::max_position_error(&ground_truth, &track).unwrap()
+    );
+    assert!(
+        metrics::absolute_trajectory_error(&ground_truth, &track, Alignment::Rigid).unwrap()
+            <= metrics::absolute_trajectory_error(&ground_truth, &track, Alignment::None).unwrap()
+    );
+}
+
+#[test]
+fn test_sensor_simulation() {
+    let trajectory = FigureEight::new(2., 2., 2. * PI, 100.);
+    let start = Utc.timestamp_nanos(0);
+    let interval = Duration::milliseconds(100);
+    let ground_truth = trajectory
+        .ground_truth(start, interval, Kinematics::constant_velocity_state)
+        .unwrap();
+
+    // the filtered track is closer to the ground truth than the measurements
+    let mut gps = SensorSimulator::position(interval)
+        .unwrap()
+        .with_noise(0.01 * SMatrix::<f64, 2, 2>::identity())
+        .with_seed(7);
+    let measurements = gps.simulate(&trajectory, start);
+    let measurement_rmse = (measurements
+        .iter()
+        .map(|measurement| {
+            let true_waypoint = ground_truth.get_waypoint(measurement.timestamp).unwrap();
+            (measurement.vector - true_waypoint.state.estimate.fixed_rows::<2>(0)).norm_squared()
+        })
+        .sum::<f64>()
+        / measurements.len() as f64)
+        .sqrt();
+    let mut initial_waypoint = ground_truth.get_first_waypoint().clone();
+    initial_waypoint.state.error = 0.1 * SMatrix::<f64, 4, 4>::identity();
+    let track = utils::create_track(
+        KalmanFilter::new(
+            ConstantVelocity::new(5.),
+            PositionMeasurementModel::new(0.01, 0.01),
+        ),
+        initial_waypoint,
+        measurements,
+    )
+    .unwrap();
+    assert!(metrics::rmse(&ground_truth, &track).unwrap() < measurement_rmse);
+}
+
+#[test]
+fn test_bias_augmented_state() {
+    let around_table = Path::around_table(10., 2., 1.);
+    let start = Utc.timestamp_nanos(0);
+    let interval = Duration::milliseconds(100);
+    let augment = |kinematics: &Kinematics| {
+        let mut state = SVector::<f64, 9>::zeros();
+        state
+            .fixed_rows_mut::<5>(0)
+            .copy_from(&kinematics.coordinated_turn_state());
+        state
+    };
+    let ground_truth = around_table.ground_truth(start, interval, augment).unwrap();
+
+    // the gps has the U-shape and the optical flow sensor measures 10% too fast and turned by 0.05 rad
+    let gps =
+        SensorSimulator::position_with_offset(Duration::milliseconds(500), Vector2::new(0., 0.5))
+            .unwrap()
+            .with_noise(0.01 * SMatrix::<f64, 2, 2>::identity())
+            .with_seed(3)
+            .simulate(&around_table, start);
+    let flow = SensorSimulator::new(interval, |kinematics| {
+        1.1 * (Rotation2::new(0.05) * kinematics.velocity)
+    })
+    .unwrap()
+    .with_noise(0.0004 * SMatrix::<f64, 2, 2>::identity())
+    .with_seed(4)
+    .simulate(&around_table, start);
+
+    // the initial position is the first gps fix
+    let mut initial_state = GaussianState::new(
+        around_table.kinematics(0.).coordinated_turn_state(),
+        SMatrix::<f64, 5, 5>::from_diagonal_element(0.01),
+    );
+    initial_state.estimate.x -= 0.5;
+    let parameters = BiasParameters {
+        position_bias_error: 0.25,
+        position_bias_drift: 0.02,
+        scale_error: 0.04,
+        scale_drift: 1e-6,
+        heading_offset_error: 0.01,
+        heading_offset_drift: 1e-6,
+    };
+
+    let augmented_track = create_sequential_track(
+        SequentialKalmanFilter::new(BiasAugmented::new(
+            CoordinatedTurn::new(0.5, 0.5),
+            parameters,
+        )),
+        Waypoint::new(start, parameters.initial_state(&initial_state)),
+        &gps,
+        &flow,
+        BiasedPositionMeasurementModel::new(0.01, 0.01),
+        BiasedVelocityMeasurementModel::new(0.0004),
+    );
+    let last_state = &augmented_track.get_latest_waypoint().state.estimate;
+    assert!((last_state[bias::SCALE] - 1.1).abs() < 0.05);
+    assert!((last_state[bias::HEADING_OFFSET] - 0.05).abs() < 0.02);
+
+    // without the biases the track follows the U-shape of the gps
+    let plain_track = create_sequential_track(
+        SequentialKalmanFilter::new(CoordinatedTurn::new(0.5, 0.5)),
+        Waypoint::new(start, initial_state),
+        &gps,
+        &flow,
+        PositionMeasurementModel::new(0.01, 0.01),
+        PolarVelocityMeasurementModel::new(0.0004),
+    );
+    let plain_ground_truth = around_table
+        .ground_truth(start, interval, Kinematics::coordinated_turn_state)
+        .unwrap();
+    let augmented_error =
+        metrics::absolute_trajectory_error(&ground_truth, &augmented_track, Alignment::Rigid)
+            .unwrap();
+    let plain_error =
+        metrics::absolute_trajectory_error(&plain_ground_truth, &plain_track, Alignment::Rigid)
+            .unwrap();
+    assert!(augmented_error < plain_error);
+}
+
+#[test]
+fn test_heading_measurement() {
+    let trajectory = FigureEight::new(4., 4., 20., 60.);
+    let start = Utc.timestamp_nanos(0);
+    let interval = Duration::milliseconds(100);
+    let ground_truth = trajectory
+        .ground_truth(start, interval, Kinematics::coordinated_turn_state)
+        .unwrap();
+
+    let positions = SensorSimulator::position(Duration::seconds(2))
+        .unwrap()
+        .with_noise(0.25 * SMatrix::<f64, 2, 2>::identity())
+        .with_seed(5)
+        .simulate(&trajectory, start);
+    let body_velocities = SensorSimulator::new(interval, |kinematics| {
+        Vector2::new(kinematics.velocity.norm(), 0.)
+    })
+    .unwrap()
+    .with_noise(0.0004 * SMatrix::<f64, 2, 2>::identity())
+    .with_seed(6)
+    .simulate(&trajectory, start);
+    let headings: Vec<Measurement<1>> = SensorSimulator::heading(interval)
+        .unwrap()
+        .with_noise(SMatrix::<f64, 1, 1>::new(0.04))
+        .with_seed(7)
+        .simulate(&trajectory, start);
+    let initial_waypoint = Waypoint::new(
+        start,
+        GaussianState::new(
+            trajectory.kinematics(0.).coordinated_turn_state(),
+            SMatrix::<f64, 5, 5>::from_diagonal_element(0.01),
+        ),
+    );
+    let position_model = PositionMeasurementModel::new(0.25, 0.25);
+
+    // the heading is a measurement of its own
+    let mut measurements: Vec<Box<dyn SensorMeasurement<5>>> = Vec::new();
+    for position in &positions {
+        measurements.push(Box::new(ModeledMeasurement::new(
+            position.clone(),
+            position_model,
+        )));
+    }
+    for (velocity, heading) in body_velocities.iter().zip(&headings) {
+        measurements.push(Box::new(ModeledMeasurement::new(
+            velocity.clone(),
+            BodyVelocityMeasurementModel::new(0.0004, 0.0004),
+        )));
+        measurements.push(Box::new(ModeledMeasurement::new(
+            heading.clone(),
+            HeadingMeasurementModel::new(0.04),
+        )));
+    }
+    let mut heading_track = Track::new(initial_waypoint.clone());
+    SequentialKalmanFilter::new(CoordinatedTurn::new(0.5, 0.5))
+        .estimate_waypoints(&heading_track, measurements)
+        .unwrap()
+        .into_iter()
+        .for_each(|waypoint| heading_track.add_waypoint(waypoint));
+
+    // the velocity is rotated by the measured heading before the filter sees it (like the
+    // SimpleVelocitySensor does), so the filter does not know about the error of the heading
+    let rotated_velocities: Vec<Measurement<2>> = body_velocities
+        .iter()
+        .zip(&headings)
+        .map(|(velocity, heading)| {
+            Measurement::new(
+                velocity.timestamp,
+                Rotation2::new(heading.vector[0]) * velocity.vector,
+            )
+        })
+        .collect();
+    let rotated_track = create_sequential_track(
+        SequentialKalmanFilter::new(CoordinatedTurn::new(0.5, 0.5)),
+        initial_waypoint,
+        &positions,
+        &rotated_velocities,
+        position_model,
+        PolarVelocityMeasurementModel::new(0.0004),
+    );
+
+    // the share of the positions that lie outside of the 99% ellipse of their covariance
+    let outside_share = |track: &Track<5>| {
+        let outside = track
+            .iter()
+            .filter(|waypoint| {
+                let true_state = ground_truth.get_waypoint(waypoint.timestamp).unwrap();
+                let error = (waypoint.state.estimate - true_state.state.estimate)
+                    .fixed_rows::<2>(0)
+                    .into_owned();
+                let covariance = waypoint.state.error.fixed_view::<2, 2>(0, 0);
+                (error.transpose() * covariance.try_inverse().unwrap() * error)[0]
+                    > chi_square_quantile(0.99, 2)
+            })
+            .count();
+        outside as f64 / track.len() as f64
+    };
+    assert!(outside_share(&heading_track) < 0.02);
+    assert!(outside_share(&rotated_track) > 0.02);
+}
+
+#[test]
+fn test_imm() {
+    let imm = InteractingMultipleModel::new(
+        vec![
+            Box::new(KalmanFilter::new(
+                Stationary::new(0.01),
+                PositionMeasurementModel::new(0.1, 0.1),
+            )),
+            Box::new(KalmanFilter::new(
+                ConstantVelocity::new(0.05),
+                PositionMeasurementModel::new(0.1, 0.1),
+            )),
+        ],
+        DMatrix::from_row_slice(2, 2, &[0.95, 0.05, 0.05, 0.95]),
+    )
+    .unwrap();
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+    let track = utils::create_track(imm, initial_waypoint, measurements).unwrap();
+
+    for waypoint in track.clone().into_iter().skip(1) {
+        let mode_probabilities = waypoint.mode_probabilities.unwrap();
+        assert_eq!(mode_probabilities.len(), 2);
+        assert!((mode_probabilities.iter().sum::<f64>() - 1.).abs() < 1e-9);
+    }
+    // the figure eight is always moving
+    let moving_probability = track
+        .get_latest_waypoint()
+        .mode_probabilities
+        .as_ref()
+        .unwrap()[1];
+    assert!(moving_probability > 0.5);
+    assert!(utils::score(ground_truth, track) <= 1.5);
+}
+
+#[test]
+fn test_constant_acceleration() {
+    let kalman_filter = KalmanFilter::new(
+        ConstantAcceleration::new(0.5),
+        PositionMeasurementModel::new(0.1, 0.1),
+    );
+    let ground_truth = create_ground_truth_with(|t| {
+        let (x, y) = p(t);
+        let (vx, vy) = v(t);
+        let (ax, ay) = a(t);
+        SVector::<f64, 6>::new(x, y, vx, vy, ax, ay)
+    });
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+    let score = utils::test_estimator(kalman_filter, initial_waypoint, measurements, ground_truth);
+    assert!(score.is_ok());
+    assert!(score.unwrap() <= 1.5);
+}
+
+#[test]
+fn test_coordinated_turn() {
+    let ground_truth = create_ground_truth_with(|t| {
+        let (x, y) = p(t);
+        let (vx, vy) = v(t);
+        let (ax, ay) = a(t);
+        let speed = vx.hypot(vy);
+        let yaw_rate = (vx * ay - vy * ax) / speed.powi(2);
+        SVector::<f64, 5>::new(x, y, speed, vy.atan2(vx), yaw_rate)
+    });
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+
+    let ekf = ExtendedKalmanFilter::new(
+        CoordinatedTurn::new(1.0, 1.0),
+        PositionMeasurementModel::new(0.1, 0.1),
+    );
+    let score = utils::test_estimator(
+        ekf,
+        initial_waypoint.clone(),
+        measurements.clone(),
+        ground_truth.clone(),
+    );
+    assert!(score.is_ok());
+    assert!(score.unwrap() <= 1.5);
+
+    let ukf = UnscentedKalmanFilter::new(
+        CoordinatedTurn::new(1.0, 1.0),
+        PositionMeasurementModel::new(0.1, 0.1),
+        SigmaPointParameters::new(0.5, 2., 0.),
+    )
+    .unwrap();
+    let score = utils::test_estimator(
+        ukf,
+        initial_waypoint.clone(),
+        measurements.clone(),
+        ground_truth.clone(),
+    );
+    assert!(score.is_ok());
+    assert!(score.unwrap() <= 1.5);
+
+    let default_ukf = UnscentedKalmanFilter::new(
+        CoordinatedTurn::new(1.0, 1.0),
+        PositionMeasurementModel::new(0.1, 0.1),
+        SigmaPointParameters::default(),
+    )
+    .unwrap();
+    let score = utils::test_estimator(default_ukf, initial_waypoint, measurements, ground_truth);
+    assert!(score.is_ok());
+    assert!(score.unwrap() <= 1.5);
+}
+
+#[test]
+fn test_differential_drive() {
+    let model = DifferentialDrive::new(0.5, 1.5, 0.01, 0.01);
+    let dt = Duration::milliseconds(100);
+    // drive straight, turn left and then stop
+    let controls: Vec<SVector<f64, 2>> = (0..100)
+        .map(|i| match i {
+            0..=29 => SVector::<f64, 2>::new(1., 1.),
+            30..=69 => SVector::<f64, 2>::new(0.2, 1.),
+            _ => SVector::<f64, 2>::zeros(),
+        })
+        .collect();
+
+    let mut true_state = SVector::<f64, 5>::zeros();
+    let mut timestamp = Utc.timestamp_nanos(0);
+    let initial_waypoint = Waypoint::new(
+        timestamp,
+        GaussianState::new(true_state, 0.1 * SMatrix::<f64, 5, 5>::identity()),
+    );
+    let mut ground_truth = Track::new(initial_waypoint.clone());
+    let mut measurements = Vec::new();
+    let mut rng = StdRng::seed_from_u64(8);
+    for control in controls.iter() {
+        true_state = model.transition(&true_state, control, dt);
+        timestamp += dt;
+        ground_truth.add_waypoint(Waypoint::new(
+            timestamp,
+            GaussianState::new(true_state, SMatrix::<f64, 5, 5>::zeros()),
+        ));
+
+        let (v, heading) = (true_state[2], true_state[3]);
+        measurements.push(Measurement::new(
+            timestamp,
+            SVector::<f64, 4>::new(
+                true_state[0] + rng.gen_range(-0.1..=0.1),
+                true_state[1] + rng.gen_range(-0.1..=0.1),
+                v * heading.cos() + rng.gen_range(-0.1..=0.1),
+                v * heading.sin() + rng.gen_range(-0.1..=0.1),
+            ),
+        ));
+    }
+
+    let ekf = ExtendedKalmanFilter::new(
+        ControlInput::new(model),
+        KinematicMeasurementModel::new(0.1, 0.1),
+    );
+    let mut track = Track::new(initial_waypoint);
+    for (control, measurement) in controls.into_iter().zip(measurements) {
+        ekf.transition_model().set_control(control);
+        let waypoint = ekf.estimate_waypoint(&track, measurement);
+        assert!(waypoint.is_ok());
+        track.add_waypoint(waypoint.unwrap());
+    }
+
+    assert!(utils::score(ground_truth, track) <= 0.3);
+}
+
+#[test]
+fn test_sequential_updates() {
+    let sequential_filter = SequentialKalmanFilter::new(ConstantVelocity::new(0.05));
+    let position_model = PositionMeasurementModel::new(0.1, 0.1);
+    let velocity_model = VelocityMeasurementModel::new(0.1, 0.1);
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, positions) = create_measurements(ground_truth.clone());
+
+    // the gps only has a fix every third step while the velocity is measured every step
+    let mut measurements: Vec<Box<dyn SensorMeasurement<4>>> = Vec::new();
+    let mut rng = StdRng::seed_from_u64(9);
+    for (i, (waypoint, position)) in ground_truth
+        .clone()
+        .into_iter()
+        .skip(1)
+        .zip(positions)
+        .enumerate()
+    {
+        if i % 3 == 0 {
+            measurements.push(Box::new(ModeledMeasurement::new(position, position_model)));
+        }
+        let vx = waypoint.state.estimate[2] + rng.gen_range(-0.1..=0.1);
+        let vy = waypoint.state.estimate[3] + rng.gen_range(-0.1..=0.1);
+        let velocity = Measurement::new(waypoint.timestamp, SVector::<f64, 2>::new(vx, vy));
+        measurements.push(Box::new(ModeledMeasurement::new(velocity, velocity_model)));
+    }
+    // the measurements are sorted by the filter
+    measurements.reverse();
+
+    let mut track = Track::new(initial_waypoint);
+    let waypoints = sequential_filter.estimate_waypoints(&track, measurements);
+    assert!(waypoints.is_ok());
+    let waypoints = waypoints.unwrap();
+    assert_eq!(waypoints.len() + 1, ground_truth.len());
+    for waypoint in waypoints {
+        track.add_waypoint(waypoint);
+    }
+    assert!(utils::score(ground_truth, track) <= 1.0);
+}
+
+#[test]
+fn test_ekf_range_bearing() {
+    let station = (0.0, -3.0);
+    let ekf = ExtendedKalmanFilter::new(
+        ConstantVelocity::new(0.05),
+        RangeBearingModel::new(station, 0.01, 0.001),
+    );
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) =
+        create_range_bearing_measurements(&ground_truth, station);
+    let score = utils::test_estimator(ekf, initial_waypoint, measurements, ground_truth);
+    assert!(score.is_ok());
+    assert!(score.unwrap() <= 1.5);
+}
+
+#[test]
+fn test_ukf_range_bearing() {
+    let station = (0.0, -3.0);
+    let ukf = UnscentedKalmanFilter::new(
+        ConstantVelocity::new(0.05),
+        RangeBearingModel::new(station, 0.01, 0.001),
+        SigmaPointParameters::new(0.5, 2., 0.),
+    )
+    .unwrap();
+    let ground_truth = create_ground_truth();
+    let (initial_waypoint, measurements) =
+        create_range_bearing_measurements(&ground_truth, station);
+    let score = utils::test_estimator(ukf, initial_waypoint, measurements, ground_truth);
+    assert!(score.is_ok());
+    assert!(score.unwrap() <= 1.5);
+}
+
+#[test]
+fn test_particle_filter() {
+    fn test_resampler<R: Resampler>(resampler: R) {
+        // the particles need more process noise to follow the maneuvers of the figure eight
+        let particle_filter = ParticleFilter::new(
+            ConstantVelocity::new(2.0),
+            PositionMeasurementModel::new(0.1, 0.1),
+            resampler,
+            1000,
+            0.5,
+        )
+        .unwrap()
+        .with_seed(11);
+        let ground_truth = create_ground_truth();
+        let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+        let mut track = Track::new(initial_waypoint.clone());
+        for measurement in measurements.clone() {
+            let waypoint = particle_filter.estimate_waypoint(&track, measurement);
+            track.add_waypoint(waypoint.unwrap());
+        }
+        assert!(utils::score(ground_truth, track) <= 1.5);
+
+        // the cloud of the first track is not used for another one
+        let mut shifted_waypoint = initial_waypoint;
+        shifted_waypoint.state.estimate.x += 100.;
+        let mut measurement = measurements[0].clone();
+        measurement.vector.x += 100.;
+        let estimate = particle_filter
+            .estimate(&Track::new(shifted_waypoint), measurement.clone())
+            .unwrap();
+        assert!((estimate.estimate.fixed_rows::<2>(0) - measurement.vector).norm() < 0.5);
+    }
+
+    test_resampler(SystematicResampling);
+    test_resampler(StratifiedResampling);
+    test_resampler(ResidualResampling);
+}
+
+fn create_sequential_track<const SD: usize, TModel, PModel, VModel>(
+    filter: SequentialKalmanFilter<SD, TModel>,
+    initial_waypoint: Waypoint<SD>,
+    positions: &[Measurement<2>],
+    velocities: &[Measurement<2>],
+    position_model: PModel,
+    velocity_model: VModel,
+) -> Track<SD>
+where
+    TModel: NonlinearTransitionModel<SD>,
+    PModel: NonlinearMeasurementModel<2, SD> + Copy + 'static,
+    VModel: NonlinearMeasurementModel<2, SD> + Copy + 'static,
+{
+    let mut measurements: Vec<Box<dyn SensorMeasurement<SD>>> = Vec::new();
+    for position in positions {
+        measurements.push(Box::new(ModeledMeasurement::new(
+            position.clone(),
+            position_model,
+        )));
+    }
+    for velocity in velocities {
+        measurements.push(Box::new(ModeledMeasurement::new(
+            velocity.clone(),
+            velocity_model,
+        )));
+    }
+
+    let mut track = Track::new(initial_waypoint);
+    for waypoint in filter.estimate_waypoints(&track, measurements).unwrap() {
+        track.add_waypoint(waypoint);
+    }
+    track
+}
+
+fn create_range_bearing_measurements(
+    ground_truth: &Track<4>,
+    station: (f64, f64),
+) -> (Waypoint<4>, Vec<Measurement<2>>) {
+    let (initial_waypoint, measurements) = create_measurements(ground_truth.clone());
+    let measurements = measurements
+        .into_iter()
+        .map(|measurement| {
+            let (dx, dy) = (
+                measurement.vector[0] - station.0,
+                measurement.vector[1] - station.1,
+            );
+            Measurement::new(
+                measurement.timestamp,
+                SVector::<f64, 2>::new(dx.hypot(dy), dy.atan2(dx)),
+            )
+        })
+        .collect();
+    (initial_waypoint, measurements)
+}
+
+/// Measures the distance and the angle from a fixed station to the object.
+struct RangeBearingModel {
+    station: (f64, f64),
+    range_error: f64,
+    bearing_error: f64,
+}
+
+impl RangeBearingModel {
+    fn new(station: (f64, f64), range_error: f64, bearing_error: f64) -> Self {
+        Self {
+            station,
+            range_error,
+            bearing_error,
+        }
+    }
+}
+
+impl NonlinearMeasurementModel<2, 4> for RangeBearingModel {
+    fn measure(&self, state: &SVector<f64, 4>) -> SVector<f64, 2> {
+        let (dx, dy) = (state[0] - self.station.0, state[1] - self.station.1);
+        SVector::<f64, 2>::new(dx.hypot(dy), dy.atan2(dx))
+    }
+
+    fn measurement_jacobian(&self, state: &SVector<f64, 4>) -> SMatrix<f64, 2, 4> {
+        let (dx, dy) = (state[0] - self.station.0, state[1] - self.station.1);
+        let range_sqrd = dx * dx + dy * dy;
+        let range = range_sqrd.sqrt();
+        SMatrix::<f64, 2, 4>::new(
+            dx / range,
+            dy / range,
+            0.,
+            0.,
+            -dy / range_sqrd,
+            dx / range_sqrd,
+            0.,
+            0.,
+        )
+    }
+
+    fn measurement_error(&self) -> SMatrix<f64, 2, 2> {
+        SMatrix::<f64, 2, 2>::new(self.range_error, 0., 0., self.bearing_error)
+    }
+}
+
+fn create_measurements<const SD: usize>(
+    ground_truth: Track<SD>,
+) -> (Waypoint<SD>, Vec<Measurement<2>>) {
     let mut it = ground_truth.into_iter();
     let initial_waypoint = it.next().unwrap();
 
+    // the noise is seeded, so that the tests can be reproduced
+    let mut rng = StdRng::seed_from_u64(42);
     let measurements = it
         .map(|waypoint| {
             let timestamp = waypoint.timestamp;
-            let mx = waypoint.state.estimate[0] + rand::thread_rng().gen_range(-0.1..=0.1);
-            let my = waypoint.state.estimate[1] + rand::thread_rng().gen_range(-0.1..=0.1);
+            let mx = waypoint.state.estimate[0] + rng.gen_range(-0.1..=0.1);
+            let my = waypoint.state.estimate[1] + rng.gen_range(-0.1..=0.1);
             Measurement::new(timestamp, SVector::<f64, 2>::new(mx, my))
         })
         .collect();
@@ -38,15 +1075,20 @@ fn create_measurements(ground_truth: Track<4>) -> (Waypoint<4>, Vec<Measurement<
 }
 
 fn create_ground_truth() -> Track<4> {
-    let it = utils::FloatRangeInclusive::new(0.0, 10.0, 0.1);
-    let mut gt_waypoints = it.map(|t| {
+    create_ground_truth_with(|t| {
         let (x, y) = p(t);
         let (vx, vy) = v(t);
+        SVector::<f64, 4>::new(x, y, vx, vy)
+    })
+}
+
+fn create_ground_truth_with<const SD: usize>(
+    to_state: impl Fn(f64) -> SVector<f64, SD>,
+) -> Track<SD> {
+    let it = utils::FloatRangeInclusive::new(0.0, 10.0, 0.1);
+    let mut gt_waypoints = it.map(|t| {
         let timestamp = Utc.timestamp_nanos((t * 1_000_000_000.0) as i64);
-        let state = GaussianState::new(
-            SVector::<f64, 4>::new(x, y, vx, vy),
-            0.1 * SMatrix::<f64, 4, 4>::identity(),
-        );
+        let state = GaussianState::new(to_state(t), 0.1 * SMatrix::<f64, SD, SD>::identity());
         let waypoint = Waypoint::new(timestamp, state);
         waypoint
     });
@@ -66,3 +1108,7 @@ fn p(t: f64) -> (f64, f64) {
 fn v(t: f64) -> (f64, f64) {
     (t.cos(), 2. * (2. * t).cos())
 }
+
+fn a(t: f64) -> (f64, f64) {
+    (-t.sin(), -4. * (2. * t).sin())
+}
@@ -1,6 +1,7 @@
 use std::error::Error;
 
 use sensor_fusion::estimator::Estimator;
+use sensor_fusion::metrics;
 use sensor_fusion::state::{Measurement, Waypoint};
 use sensor_fusion::track::Track;
 
@@ -10,25 +11,34 @@ pub fn test_estimator<const MD: usize, const SD: usize, E>(
     measurements: Vec<Measurement<MD>>,
     ground_truth: Track<SD>,
 ) -> Result<f64, Box<dyn Error>>
+where
+    E: Estimator<MD, SD>,
+{
+    let track = create_track(estimator, initial_waypoint, measurements)?;
+    Ok(score(ground_truth, track))
+}
+
+pub fn create_track<const MD: usize, const SD: usize, E>(
+    estimator: E,
+    initial_waypoint: Waypoint<SD>,
+    measurements: Vec<Measurement<MD>>,
+) -> Result<Track<SD>, Box<dyn Error>>
 where
     E: Estimator<MD, SD>,
 {
     let mut track = Track::new(initial_waypoint);
 
     for measurement in measurements {
-        let timestamp = measurement.timestamp;
-        let estimation = estimator.estimate(&track, measurement)?;
-        track.add_waypoint(Waypoint::new(timestamp, estimation))
+        let waypoint = estimator.estimate_waypoint(&track, measurement)?;
+        track.add_waypoint(waypoint)
     }
 
-    // compare the two tracks
-    let mut score: f64 = 0.0;
-    for (true_waypoint, estimated_waypoint) in ground_truth.into_iter().zip(track) {
-        let diff = true_waypoint.state.estimate - estimated_waypoint.state.estimate;
-        score = score.max(diff[0].abs()).max(diff[1].abs())
-    }
+    Ok(track)
+}
 
-    Ok(score)
+pub fn score<const SD: usize>(ground_truth: Track<SD>, track: Track<SD>) -> f64 {
+    // compare the two tracks (waypoints with the same timestamp)
+    metrics::max_position_error(&ground_truth, &track).unwrap()
 }
 
 pub struct FloatRangeInclusive {
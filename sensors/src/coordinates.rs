@@ -6,6 +6,12 @@ pub trait GeoToCartesian {
     fn convert(&self, geo_coord: GeoCoord, height: f64) -> Cartesian3D;
 }
 
+/// # Explanation
+/// The inverse of GeoToCartesian: converts cartesian coordinates back to geographic coordinates and the height.
+pub trait CartesianToGeo {
+    fn convert(&self, cartesian: Cartesian3D) -> (GeoCoord, f64);
+}
+
 /// # Explanation
 /// The GeoCoord struct represents a geographical coordinate (consisting of longitude and latitude).
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
@@ -72,6 +78,12 @@ impl Velocity2D {
     }
 }
 
+impl Into<SVector<f64, 2>> for Velocity2D {
+    fn into(self) -> SVector<f64, 2> {
+        SVector::<f64, 2>::new(self.vx, self.vy)
+    }
+}
+
 /// # Explanation
 /// The Cartesian2D struct represents a point in a cartesian coordinate system with two dimensions.
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
@@ -158,29 +170,9 @@ pub struct GeoToENU {
 impl GeoToENU {
     pub fn new(base_point: GeoCoord, initial_height: f64) -> Self {
         let ecef = GeoToECEF::new();
-        let GeoCoord {
-            lon: base_lon,
-            lat: base_lat,
-        } = base_point;
-
-        let rad_base_lon = base_lon.to_radians();
-        let rad_base_lat = base_lat.to_radians();
-
-        let rotation_matrix = Matrix3::new(
-            -rad_base_lon.sin(),
-            rad_base_lon.cos(),
-            0.0,
-            -rad_base_lat.sin() * rad_base_lon.cos(),
-            -rad_base_lat.sin() * rad_base_lon.sin(),
-            rad_base_lat.cos(),
-            rad_base_lat.cos() * rad_base_lon.cos(),
-            rad_base_lat.cos() * rad_base_lon.sin(),
-            rad_base_lat.sin(),
-        );
-
         GeoToENU {
             base_point: ecef.convert(base_point, initial_height),
-            rotation_matrix,
+            rotation_matrix: enu_rotation_matrix(base_point),
             ecef,
         }
     }
@@ -199,3 +191,91 @@ impl GeoToCartesian for GeoToENU {
         Cartesian3D::new(enu_coords.x, enu_coords.y, enu_coords.z)
     }
 }
+
+/// # Explanation
+/// The ECEFToGeo struct converts ECEF coordinates back to geographic coordinates (the inverse of GeoToECEF).
+/// The latitude and the height are found iteratively, which converges to below a millimeter after a few
+/// iterations for points near the surface of the earth.
+pub struct ECEFToGeo;
+
+impl ECEFToGeo {
+    pub fn new() -> Self {
+        ECEFToGeo {}
+    }
+
+    const ITERATIONS: usize = 10;
+}
+
+impl CartesianToGeo for ECEFToGeo {
+    fn convert(&self, cartesian: Cartesian3D) -> (GeoCoord, f64) {
+        let Cartesian3D { x, y, z } = cartesian;
+        let p = (x * x + y * y).sqrt();
+        let rad_lon = y.atan2(x);
+
+        let mut rad_lat = z.atan2(p * (1.0 - GeoToECEF::ECCENTRICITY_SQRD));
+        let mut height = 0.0;
+        for _ in 0..Self::ITERATIONS {
+            let n = GeoToECEF::n(rad_lat);
+            height = p / rad_lat.cos() - n;
+            rad_lat = z.atan2(p * (1.0 - GeoToECEF::ECCENTRICITY_SQRD * n / (n + height)));
+        }
+
+        (
+            GeoCoord::new(rad_lon.to_degrees(), rad_lat.to_degrees()),
+            height,
+        )
+    }
+}
+
+/// # Explanation
+/// The ENUToGeo struct converts ENU coordinates back to geographic coordinates (the inverse of GeoToENU). With the
+/// same base point and height it maps the positions of a track (which is in the ENU frame of the
+/// SimplePositionSensor) back to longitude and latitude.
+pub struct ENUToGeo {
+    base_point: Cartesian3D,
+    rotation_matrix: Matrix3<f64>,
+    ecef: ECEFToGeo,
+}
+
+impl ENUToGeo {
+    pub fn new(base_point: GeoCoord, initial_height: f64) -> Self {
+        ENUToGeo {
+            base_point: GeoToECEF::new().convert(base_point, initial_height),
+            // the rotation matrix is orthogonal, so its inverse is the transpose
+            rotation_matrix: enu_rotation_matrix(base_point).transpose(),
+            ecef: ECEFToGeo::new(),
+        }
+    }
+}
+
+impl CartesianToGeo for ENUToGeo {
+    fn convert(&self, cartesian: Cartesian3D) -> (GeoCoord, f64) {
+        let ecef_diff = self.rotation_matrix * Vector3::new(cartesian.x, cartesian.y, cartesian.z);
+        let ecef_coord = Cartesian3D::new(
+            ecef_diff.x + self.base_point.x,
+            ecef_diff.y + self.base_point.y,
+            ecef_diff.z + self.base_point.z,
+        );
+
+        self.ecef.convert(ecef_coord)
+    }
+}
+
+/// # Returns
+/// Returns the matrix that rotates differences of ECEF coordinates into the ENU frame at the base point.
+fn enu_rotation_matrix(base_point: GeoCoord) -> Matrix3<f64> {
+    let rad_base_lon = base_point.lon.to_radians();
+    let rad_base_lat = base_point.lat.to_radians();
+
+    Matrix3::new(
+        -rad_base_lon.sin(),
+        rad_base_lon.cos(),
+        0.0,
+        -rad_base_lat.sin() * rad_base_lon.cos(),
+        -rad_base_lat.sin() * rad_base_lon.sin(),
+        rad_base_lat.cos(),
+        rad_base_lat.cos() * rad_base_lon.cos(),
+        rad_base_lat.cos() * rad_base_lon.sin(),
+        rad_base_lat.sin(),
+    )
+}
@@ -1,6 +1,7 @@
+use std::f64::consts::PI;
 use std::time::Instant;
 
-use crate::compass::BNO055;
+use crate::compass::{Compass, Orientation, BNO055};
 use crate::coordinates::{Cartesian2D, GeoCoord, GeoToCartesian, GeoToENU, Velocity2D};
 use crate::distance_traveled::PAA5100;
 use crate::gps::NtripUbloxSensor;
@@ -19,6 +20,7 @@ pub trait VelocitySensor: Iterator<Item = Velocity2D> {}
 
 pub struct SimplePositionSensor {
     ublox_sensor: NtripUbloxSensor,
+    base_point: GeoCoord,
     cartesian_converter: GeoToENU,
 }
 
@@ -32,7 +34,14 @@ impl SimplePositionSensor {
         };
 
         let cartesian_converter = GeoToENU::new(base_point, 0.0);
-        Self { ublox_sensor, cartesian_converter }
+        Self { ublox_sensor, base_point, cartesian_converter }
+    }
+
+    /// # Returns
+    /// Returns the geographic coordinate of the origin of the ENU frame (the first gps fix). With an ENUToGeo
+    /// at this base point the positions can be converted back to geographic coordinates.
+    pub fn base_point(&self) -> GeoCoord {
+        self.base_point
     }
 }
 
@@ -49,15 +58,18 @@ impl PositionSensor for SimplePositionSensor {}
 
 
 
+/// # Explanation
+/// The SimpleVelocitySensor measures the velocity in the global frame: the velocity of the BodyVelocitySensor
+/// is rotated by the heading of the compass. The filter gets no uncertainty of the heading this way (see
+/// BodyVelocitySensor and HeadingSensor to measure both on their own).
 pub struct SimpleVelocitySensor {
-    last_time: Instant,
     compass: BNO055,
-    distance_traveled_sensor: PAA5100
+    body_velocity_sensor: BodyVelocitySensor,
 }
 
 impl SimpleVelocitySensor {
     pub fn new(compass: BNO055, distance_traveled_sensor: PAA5100) -> Self {
-        Self { last_time: Instant::now(), compass, distance_traveled_sensor }
+        Self { compass, body_velocity_sensor: BodyVelocitySensor::new(distance_traveled_sensor) }
     }
 }
 
@@ -66,19 +78,9 @@ impl Iterator for SimpleVelocitySensor {
 
     fn next(&mut self) -> Option<Self::Item> {
         let orientation = self.compass.next();
-        let distance = self.distance_traveled_sensor.next();
-
-        if let (Some(orientation), Some(distance)) = (orientation, distance) {
-            let timestamp = Instant::now();
-            let time_passed = timestamp - self.last_time;
-            self.last_time = timestamp;
-
-            // velocity in local frame
-            let v_local = Velocity2D::new(
-                distance.dx / time_passed.as_secs_f64(),
-                distance.dy / time_passed.as_secs_f64(),
-            );
+        let v_local = self.body_velocity_sensor.next();
 
+        if let (Some(orientation), Some(v_local)) = (orientation, v_local) {
             // velocity in global frame
             let vx = v_local.vx * orientation.radian.cos() + v_local.vy * orientation.radian.sin();
             let vy = -v_local.vx * orientation.radian.sin() + v_local.vy * orientation.radian.cos();
@@ -91,3 +93,75 @@ impl Iterator for SimpleVelocitySensor {
 }
 
 impl VelocitySensor for SimpleVelocitySensor {}
+
+
+
+/// # Explanation
+/// The BodyVelocitySensor measures the velocity in the frame of the robot (the distance the optical flow sensor
+/// traveled since the last call divided by the time that has passed).
+pub struct BodyVelocitySensor {
+    last_time: Instant,
+    distance_traveled_sensor: PAA5100,
+}
+
+impl BodyVelocitySensor {
+    pub fn new(distance_traveled_sensor: PAA5100) -> Self {
+        Self { last_time: Instant::now(), distance_traveled_sensor }
+    }
+}
+
+impl Iterator for BodyVelocitySensor {
+    type Item = Velocity2D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let distance = self.distance_traveled_sensor.next()?;
+
+        let timestamp = Instant::now();
+        let time_passed = timestamp - self.last_time;
+        self.last_time = timestamp;
+
+        Some(Velocity2D::new(
+            distance.dx / time_passed.as_secs_f64(),
+            distance.dy / time_passed.as_secs_f64(),
+        ))
+    }
+}
+
+impl VelocitySensor for BodyVelocitySensor {}
+
+
+
+/// # Explanation
+/// The HeadingSensor measures the heading of the robot with the compass in the frame the filter uses:
+/// - The sign is flipped, so that the heading is the angle of the x-axis of the robot against the x-axis of
+///   the global frame (the SimpleVelocitySensor rotates the velocity by the negative compass heading).
+/// - The heading is unwrapped, ie it does not jump at ±π but keeps on growing when the robot turns multiple
+///   times. The heading of the filter is continuous, so a jump would look like a huge heading error.
+pub struct HeadingSensor {
+    compass: BNO055,
+    last_heading: Option<f64>,
+}
+
+impl HeadingSensor {
+    pub fn new(compass: BNO055) -> Self {
+        Self { compass, last_heading: None }
+    }
+}
+
+impl Iterator for HeadingSensor {
+    type Item = Orientation;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let heading = -self.compass.next()?.radian;
+        let heading = match self.last_heading {
+            // the smallest change of the angle that leads to the new heading
+            Some(last_heading) => last_heading + (heading - last_heading + PI).rem_euclid(2. * PI) - PI,
+            None => heading,
+        };
+        self.last_heading = Some(heading);
+
+        Some(Orientation::new(heading))
+    }
+}
+
+impl Compass for HeadingSensor {}
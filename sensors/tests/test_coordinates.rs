@@ -0,0 +1,64 @@
+use sensors::coordinates::{
+    Cartesian3D, CartesianToGeo, ENUToGeo, GeoCoord, GeoToCartesian, GeoToENU,
+};
+
+/// # Returns
+/// Returns the base points (longitude, latitude) of the round trips: near the robot, on the other
+/// hemispheres and close to the pole.
+fn base_points() -> Vec<GeoCoord> {
+    vec![
+        GeoCoord::new(8.4037, 49.0069),
+        GeoCoord::new(-74.0445, 40.6892),
+        GeoCoord::new(151.2153, -33.8568),
+        GeoCoord::new(0., 0.),
+        GeoCoord::new(45., 85.),
+    ]
+}
+
+/// # Returns
+/// Returns the offsets (east, north, up) in meters from the base point.
+fn offsets() -> Vec<Cartesian3D> {
+    vec![
+        Cartesian3D::new(0., 0., 0.),
+        Cartesian3D::new(10., -5., 0.),
+        Cartesian3D::new(-250., 130., 2.),
+        Cartesian3D::new(1000., 1000., -10.),
+    ]
+}
+
+/// # Returns
+/// Returns whether the geographic coordinates are at most a millimeter apart (a degree of latitude is
+/// about 111 km).
+fn is_close(a: GeoCoord, b: GeoCoord) -> bool {
+    let tolerance = 1e-3 / 111_000.;
+    (a.lat - b.lat).abs() < tolerance
+        && (a.lon - b.lon).abs() * a.lat.to_radians().cos() < tolerance
+}
+
+#[test]
+fn test_geo_round_trip() {
+    for base_point in base_points() {
+        let to_enu = GeoToENU::new(base_point, 0.);
+        let to_geo = ENUToGeo::new(base_point, 0.);
+
+        // the origin of the ENU frame is the base point
+        let (origin, origin_height) = to_geo.convert(Cartesian3D::new(0., 0., 0.));
+        assert!(is_close(origin, base_point));
+        assert!(origin_height.abs() < 1e-3);
+
+        for offset in offsets() {
+            // the points are given by their offset from the base point
+            let (geo_coord, height) = to_geo.convert(offset);
+            let enu = to_enu.convert(geo_coord, height);
+            let (round_trip, round_trip_height) = to_geo.convert(enu);
+
+            assert!(is_close(round_trip, geo_coord));
+            assert!((round_trip_height - height).abs() < 1e-3);
+
+            // and the offset is found again
+            assert!((enu.x - offset.x).abs() < 1e-3);
+            assert!((enu.y - offset.y).abs() < 1e-3);
+            assert!((enu.z - offset.z).abs() < 1e-3);
+        }
+    }
+}